@@ -1,10 +1,24 @@
 #[macro_use]
+extern crate diesel;
+#[macro_use]
 extern crate lazy_static;
 
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
 use serde_derive::{Deserialize, Serialize};
 use structopt::StructOpt;
 
+use dspa_lib::checkpoint::CheckpointArgs;
+use dspa_lib::deadletter::DeadLetterArgs;
+use dspa_lib::metrics::MetricsArgs;
+use dspa_lib::partition::PartitionArgs;
 use dspa_lib::records::{CommentRecord, LikeRecord, PostRecord};
+use dspa_lib::schema::anomaly;
+use dspa_lib::sink::{ResultRow, SinkArgs};
+use dspa_lib::store::StoreArgs;
+use dspa_lib::subscription::SubscriptionArgs;
+use dspa_lib::TimelyArgs;
 
 pub mod operators;
 pub mod statistics;
@@ -22,6 +36,22 @@ pub struct Args {
     pub samples: usize,
     #[structopt(long = "smoothing", default_value = "3")]
     pub alpha: f32,
+    #[structopt(flatten)]
+    pub store: StoreArgs,
+    #[structopt(flatten)]
+    pub checkpoint: CheckpointArgs,
+    #[structopt(flatten)]
+    pub sink: SinkArgs,
+    #[structopt(flatten)]
+    pub metrics: MetricsArgs,
+    #[structopt(flatten)]
+    pub partition: PartitionArgs,
+    #[structopt(flatten)]
+    pub subscription: SubscriptionArgs,
+    #[structopt(flatten)]
+    pub deadletter: DeadLetterArgs,
+    #[structopt(flatten)]
+    pub timely: TimelyArgs,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -32,3 +62,61 @@ pub enum AnomalyEvent {
 }
 
 impl AnomalyEvent {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum AnomalyCause {
+    PostUniqueWords,
+    PostTags,
+    CommentUniqueWords,
+}
+
+impl fmt::Display for AnomalyCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnomalyCause::PostUniqueWords => write!(f, "Post - Unique Words"),
+            AnomalyCause::PostTags => write!(f, "Post - Number of Tags"),
+            AnomalyCause::CommentUniqueWords => write!(f, "Comment - Unique Words"),
+        }
+    }
+}
+
+/// Anomalous user at a timestamp, with the number of standard deviations from the mean. A user can
+/// be anomalous several times for the same cause at a timestamp, the table has a surrogate key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Insertable)]
+#[table_name = "anomaly"]
+pub struct AnomalyRow {
+    pub timestamp: DateTime<Utc>,
+    pub person_id: i32,
+    pub cause: String,
+    pub deviation: f32,
+}
+
+impl fmt::Display for AnomalyRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Anomaly: User {} for reason {} with stddev {}",
+            self.person_id, self.cause, self.deviation
+        )
+    }
+}
+
+impl ResultRow for AnomalyRow {
+    type Record = (i32, String, f32);
+    type Table = anomaly::table;
+
+    const NAME: &'static str = "anomaly";
+
+    fn rows(timestamp: u64, (person_id, cause, deviation): &(i32, String, f32)) -> Vec<Self> {
+        vec![AnomalyRow {
+            timestamp: Utc.timestamp(timestamp as i64, 0),
+            person_id: *person_id,
+            cause: cause.clone(),
+            deviation: *deviation,
+        }]
+    }
+
+    fn table() -> Self::Table {
+        anomaly::table
+    }
+}
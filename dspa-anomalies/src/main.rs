@@ -1,59 +1,69 @@
-use std::sync::Arc;
-
-use diesel::r2d2::ConnectionManager;
-use diesel::PgConnection;
-use r2d2::Pool;
 use timely::dataflow::operators::{Concat, Exchange, Inspect, Map};
 
 use zmq::Context;
 
-use dspa_lib::operators::{streams, Ordered};
-use dspa_lib::DATABASE_URL;
+use dspa_lib::metrics::ANOMALIES_METRICS_PORT;
+use dspa_lib::operators::{streams, Checkpoint, Gather, Ordered, TrackLatency, WriteTo};
+use dspa_lib::shutdown;
 
 use dspa_anomalies::operators::Anomalies;
-use dspa_anomalies::{AnomalyEvent, ARGS};
+use dspa_anomalies::{AnomalyEvent, AnomalyRow, ARGS};
 
 fn main() {
     lazy_static::initialize(&ARGS);
 
-    let pool = Arc::new(
-        Pool::builder()
-            .max_size(16)
-            .build(ConnectionManager::<PgConnection>::new(DATABASE_URL))
-            .unwrap(),
-    );
+    let store = ARGS.store.store();
+    let checkpoints = ARGS.checkpoint.checkpoints("dspa-anomalies");
+    ARGS.metrics
+        .serve(ANOMALIES_METRICS_PORT, ARGS.timely.process);
+    shutdown::handle_signals();
 
     let ctx = Context::new();
-    timely::execute(timely::Configuration::Thread, move |worker| {
-        // timely::execute(timely::Configuration::Process(num_cpus::get()), move |worker| {
+    timely::execute(ARGS.timely.configuration(), move |worker| {
         let idx = worker.index();
         let peers = worker.peers();
 
         worker.dataflow(|scope| {
-            let (posts, comments, likes) = streams(scope, idx, &ctx);
+            let (posts, comments, likes, failed_events, stamps) = streams(
+                scope,
+                idx,
+                &ctx,
+                checkpoints.restored(),
+                ARGS.partition.partitions,
+                &ARGS.subscription,
+                &ARGS.deadletter,
+            );
 
-            let comment_events = comments
+            let (comments, failed_comments) = comments
                 .exchange(|comment| comment.id as u64)
-                .ordered(idx, peers, pool.clone(), &posts)
-                .map(|comment| AnomalyEvent::Comment(comment));
-            let like_events = likes
-                .exchange(|like| like.post_id as u64)
-                .ordered(idx, peers, pool.clone(), &posts)
-                .map(|like| AnomalyEvent::Like(like));
+                .ordered(idx, peers, store.clone(), &posts, &checkpoints);
+            let comment_events = comments.map(|comment| AnomalyEvent::Comment(comment));
+            let (likes, failed_likes) = likes.exchange(|like| like.post_id as u64).ordered(
+                idx,
+                peers,
+                store.clone(),
+                &posts,
+                &checkpoints,
+            );
+            let like_events = likes.map(|like| AnomalyEvent::Like(like));
             let post_events = posts
                 .exchange(|post| post.id as u64)
                 .map(|post| AnomalyEvent::Post(post));
 
-            post_events
+            failed_events
+                .concat(&failed_comments)
+                .concat(&failed_likes)
+                .inspect(|failure| eprintln!("{}", failure));
+
+            let written = post_events
                 .concat(&comment_events)
                 .concat(&like_events)
-                .anomalies()
-                .inspect(|(user, cause, stddevs)| {
-                    println!(
-                        "Anomaly: User {} for reason {} with stddev {}",
-                        user, cause, stddevs
-                    );
-                });
+                .anomalies(ARGS.threshold, ARGS.samples, ARGS.alpha, &checkpoints)
+                .gather()
+                .write_to(ARGS.sink.sink::<AnomalyRow>());
+
+            stamps.track_latency(&written);
+            written.checkpoint(&checkpoints);
         });
     })
     .unwrap();
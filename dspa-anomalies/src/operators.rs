@@ -1,16 +1,18 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::iter::Iterator;
 
 use regex::Regex;
-use timely::dataflow::channels::pact::Pipeline;
-use timely::dataflow::operators::Operator;
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::{Map, Operator};
 use timely::dataflow::{Scope, Stream};
 
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::metrics::batch_time;
+
 use crate::statistics::OnlineStatistic;
-use crate::AnomalyEvent;
-use crate::ARGS;
+use crate::{AnomalyCause, AnomalyEvent};
 
-fn unique_words_per_word(s: &str) -> f32 {
+fn unique_words_per_word(s: &str, alpha: f32) -> f32 {
     lazy_static! {
         static ref REGEX: Regex = Regex::new(r#"[.!?\-,]"#).unwrap();
     }
@@ -20,101 +22,156 @@ fn unique_words_per_word(s: &str) -> f32 {
         .split(' ')
         .inspect(|_| count += 1)
         .collect::<HashSet<_>>();
-    (unique.len() as f32 + ARGS.alpha) / (count as f32 + ARGS.alpha)
+    (unique.len() as f32 + alpha) / (count as f32 + alpha)
 }
 
 pub trait Anomalies<G>
 where
     G: Scope<Timestamp = u64>,
 {
-    fn anomalies(&self) -> Stream<G, (i32, String, f32)>;
+    fn anomalies(
+        &self,
+        threshold: f32,
+        samples: usize,
+        alpha: f32,
+        checkpoints: &Checkpoints,
+    ) -> Stream<G, (i32, String, f32)>;
 }
 
 impl<G> Anomalies<G> for Stream<G, AnomalyEvent>
 where
     G: Scope<Timestamp = u64>,
 {
-    fn anomalies(&self) -> Stream<G, (i32, String, f32)> {
-        self.unary(Pipeline, "Anomalies", |_, _| {
-            // Post statistics
-            let mut post_word_unique_stats = OnlineStatistic::new(ARGS.samples);
-            let mut num_tags_stats = OnlineStatistic::new(ARGS.samples);
+    fn anomalies(
+        &self,
+        threshold: f32,
+        samples: usize,
+        alpha: f32,
+        checkpoints: &Checkpoints,
+    ) -> Stream<G, (i32, String, f32)> {
+        let idx = self.scope().index();
+        let checkpoints = checkpoints.clone();
+
+        // Post unique words, post tags and comment unique words statistics
+        let (mut post_word_unique_stats, mut num_tags_stats, mut comment_word_unique_stats) =
+            checkpoints.load("anomalies", idx).unwrap_or_else(|| {
+                (
+                    OnlineStatistic::new(samples),
+                    OnlineStatistic::new(samples),
+                    OnlineStatistic::new(samples),
+                )
+            });
+
+        // Continue snapshotting restored statistics
+        let init = checkpoints
+            .restored()
+            .filter(|_| {
+                !post_word_unique_stats.is_empty()
+                    || !num_tags_stats.is_empty()
+                    || !comment_word_unique_stats.is_empty()
+            })
+            .and_then(|time| checkpoints.next(time + 1));
+
+        // Time => Samples
+        let mut pending: BTreeMap<u64, Vec<(i32, AnomalyCause, f32)>> = BTreeMap::new();
+
+        let batch_time = batch_time("Anomalies");
 
-            // Comment statistics
-            let mut comment_word_unique_stats = OnlineStatistic::new(ARGS.samples);
+        let mut vec = Vec::new();
+
+        // Samples are computed on every worker, the statistics are global and therefore
+        // maintained on a single worker in timestamp order
+        self.flat_map(move |event| {
+            let mut samples = Vec::new();
+            match event {
+                AnomalyEvent::Post(record) => {
+                    // Post Unique words
+                    if let Some(content) = &record.content {
+                        samples.push((
+                            record.person_id,
+                            AnomalyCause::PostUniqueWords,
+                            unique_words_per_word(content, alpha),
+                        ));
+                    }
+
+                    // Post Number of Tags
+                    samples.push((
+                        record.person_id,
+                        AnomalyCause::PostTags,
+                        record.tags.len() as f32,
+                    ));
+                }
+                AnomalyEvent::Comment(record) => {
+                    // Comment Unique Words
+                    samples.push((
+                        record.person_id,
+                        AnomalyCause::CommentUniqueWords,
+                        unique_words_per_word(&record.content, alpha),
+                    ));
+                }
+                _ => {}
+            }
+            samples
+        })
+        .unary_notify(
+            Exchange::new(|_| 0),
+            "Anomalies",
+            init,
+            move |input, output, notificator| {
+                let _timer = batch_time.start_timer();
 
-            move |input, output| {
-                let mut vec = Vec::new();
                 input.for_each(|cap, data| {
                     data.swap(&mut vec);
 
+                    pending
+                        .entry(*cap.time())
+                        .or_default()
+                        .extend(vec.drain(..));
+                    if let Some(checkpoint) = checkpoints.next(*cap.time()) {
+                        notificator.notify_at(cap.delayed(&checkpoint));
+                    }
+                    notificator.notify_at(cap.retain());
+                });
+
+                notificator.for_each(|cap, _, notificator| {
+                    let mut samples = pending.remove(cap.time()).unwrap_or_default();
+                    // Deterministic order within a single time
+                    samples.sort_by_key(|(person_id, cause, _)| (*cause, *person_id));
+
                     let mut session = output.session(&cap);
-                    vec.drain(..).for_each(|event| {
-                        match event {
-                            AnomalyEvent::Post(record) => {
-                                // Post Unique words
-                                {
-                                    if let Some(content) = &record.content {
-                                        let n_unique = unique_words_per_word(content);
-
-                                        if post_word_unique_stats.saturated() {
-                                            if let Some(stddevs) = post_word_unique_stats
-                                                .is_anomaly(ARGS.threshold, n_unique)
-                                            {
-                                                session.give((
-                                                    record.person_id,
-                                                    "Post - Unique Words".to_owned(),
-                                                    stddevs,
-                                                ));
-                                            }
-                                        }
-                                        post_word_unique_stats.update(n_unique);
-                                    }
-                                }
+                    for (person_id, cause, value) in samples {
+                        let stats = match cause {
+                            AnomalyCause::PostUniqueWords => &mut post_word_unique_stats,
+                            AnomalyCause::PostTags => &mut num_tags_stats,
+                            AnomalyCause::CommentUniqueWords => &mut comment_word_unique_stats,
+                        };
 
-                                // Post Number of Tags
-                                {
-                                    let n_tags = record.tags.len() as f32;
-
-                                    if num_tags_stats.saturated() {
-                                        if let Some(stddevs) =
-                                            num_tags_stats.is_anomaly(ARGS.threshold, n_tags)
-                                        {
-                                            session.give((
-                                                record.person_id,
-                                                "Post - Number of Tags".to_owned(),
-                                                stddevs,
-                                            ));
-                                        }
-                                    }
-                                    num_tags_stats.update(n_tags);
-                                }
+                        if stats.saturated() {
+                            if let Some(stddevs) = stats.is_anomaly(threshold, value) {
+                                session.give((person_id, cause.to_string(), stddevs));
                             }
-                            AnomalyEvent::Comment(record) => {
-                                // Comment Unique Words
-                                {
-                                    let n_unique = unique_words_per_word(&record.content);
-
-                                    if comment_word_unique_stats.saturated() {
-                                        if let Some(stddevs) = comment_word_unique_stats
-                                            .is_anomaly(ARGS.threshold, n_unique)
-                                        {
-                                            session.give((
-                                                record.person_id,
-                                                "Comment - Unique Words".to_owned(),
-                                                stddevs,
-                                            ));
-                                        }
-                                    }
-
-                                    comment_word_unique_stats.update(n_unique);
+                        }
+                        stats.update(value);
+                    }
+
+                    if checkpoints.is_checkpoint(*cap.time()) {
+                        let stats = (
+                            &post_word_unique_stats,
+                            &num_tags_stats,
+                            &comment_word_unique_stats,
+                        );
+                        if !stats.0.is_empty() || !stats.1.is_empty() || !stats.2.is_empty() {
+                            checkpoints.save("anomalies", idx, *cap.time(), &stats);
+
+                            if !notificator.frontier(0).is_empty() {
+                                if let Some(checkpoint) = checkpoints.next(cap.time() + 1) {
+                                    notificator.notify_at(cap.delayed(&checkpoint));
                                 }
                             }
-                            _ => {}
                         }
-                    });
+                    }
                 });
-            }
-        })
+            },
+        )
     }
 }
@@ -1,5 +1,7 @@
 use std::collections::VecDeque;
 
+use serde_derive::{Deserialize, Serialize};
+
 #[derive(Clone, Debug)]
 pub struct RollingStatistic {
     queue: VecDeque<f32>,
@@ -58,7 +60,7 @@ impl RollingStatistic {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OnlineStatistic {
     min_count: usize,
     count: usize,
@@ -102,6 +104,11 @@ impl OnlineStatistic {
         self.count >= self.min_count
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
     pub fn is_anomaly(&self, threshold: f32, value: f32) -> Option<f32> {
         let stddevs = (value - self.mean) / self.stddev();
 
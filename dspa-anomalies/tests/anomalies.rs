@@ -0,0 +1,95 @@
+use timely::dataflow::operators::{Concat, Map};
+
+use dspa_anomalies::operators::Anomalies;
+use dspa_anomalies::AnomalyEvent;
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::records::{CommentRecord, PostRecord};
+use dspa_lib::testing::{comment, post, Harness};
+
+fn tagged_post(id: i32, person_id: i32, tags: usize, time: u64) -> PostRecord {
+    PostRecord {
+        tags: (0..tags as i32).collect(),
+        ..post(id, person_id, time)
+    }
+}
+
+fn worded_comment(id: i32, person_id: i32, content: &str, time: u64) -> CommentRecord {
+    CommentRecord {
+        content: content.to_owned(),
+        ..comment(id, person_id, 1, time)
+    }
+}
+
+fn anomalies(harness: Harness) -> Vec<(u64, Vec<(i32, String)>)> {
+    harness
+        .run(|(posts, comments, _)| {
+            posts
+                .map(AnomalyEvent::Post)
+                .concat(&comments.map(AnomalyEvent::Comment))
+                .anomalies(2.0, 4, 0.0, &Checkpoints::default())
+                .map(|(person_id, cause, _)| (person_id, cause))
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn detects_unusual_number_of_tags() {
+    let harness = Harness::default()
+        .event(10, tagged_post(1, 1, 1, 10))
+        .event(20, tagged_post(2, 2, 1, 20))
+        .event(30, tagged_post(3, 3, 2, 30))
+        .event(40, tagged_post(4, 4, 2, 40))
+        .event(50, tagged_post(5, 5, 2, 50))
+        .event(60, tagged_post(6, 6, 10, 60));
+
+    assert_eq!(
+        anomalies(harness),
+        vec![(60, vec![(6, "Post - Number of Tags".to_owned())])]
+    );
+}
+
+#[test]
+fn statistics_need_minimum_sample_size() {
+    let harness = Harness::default()
+        .event(10, tagged_post(1, 1, 1, 10))
+        .event(20, tagged_post(2, 2, 2, 20))
+        .event(30, tagged_post(3, 3, 1, 30))
+        .event(40, tagged_post(4, 4, 10, 40));
+
+    assert!(anomalies(harness).is_empty());
+}
+
+#[test]
+fn detects_repetitive_comments() {
+    let harness = Harness::default()
+        .event(10, worded_comment(1, 1, "one two three four", 10))
+        .event(20, worded_comment(2, 2, "five six seven eight", 20))
+        .event(30, worded_comment(3, 3, "nine ten eleven twelve", 30))
+        .event(40, worded_comment(4, 4, "a b c a", 40))
+        .event(50, worded_comment(5, 5, "spam spam spam spam", 50));
+
+    assert_eq!(
+        anomalies(harness),
+        vec![(50, vec![(5, "Comment - Unique Words".to_owned())])]
+    );
+}
+
+#[test]
+fn statistics_are_global_across_workers() {
+    let events = (0..64)
+        .map(|i| {
+            let time = 10 * (i as u64 + 1);
+            let tags = if i % 16 == 15 { 12 } else { (i % 3) as usize };
+            (time, tagged_post(i, i, tags, time).into())
+        })
+        .collect::<Vec<_>>();
+
+    let single = anomalies(Harness::new(events.clone()));
+    let multiple = anomalies(Harness::new(events).workers(4));
+
+    assert!(!single.is_empty());
+    assert_eq!(single, multiple);
+}
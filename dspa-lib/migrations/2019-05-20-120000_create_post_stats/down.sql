@@ -0,0 +1 @@
+DROP TABLE post_stats;
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE post_stats (
+    timestamp timestamptz NOT NULL,
+    post_id int NOT NULL,
+    users int NOT NULL,
+    comments bigint NOT NULL,
+    likes bigint NOT NULL,
+    PRIMARY KEY (timestamp, post_id)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE recommendation;
\ No newline at end of file
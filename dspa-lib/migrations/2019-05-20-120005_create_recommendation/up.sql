@@ -0,0 +1,7 @@
+CREATE TABLE recommendation (
+    timestamp timestamptz NOT NULL,
+    person_id int NOT NULL,
+    rank int NOT NULL,
+    recommended_id int NOT NULL,
+    PRIMARY KEY (timestamp, person_id, rank)
+);
\ No newline at end of file
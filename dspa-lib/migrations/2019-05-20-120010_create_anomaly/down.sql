@@ -0,0 +1 @@
+DROP TABLE anomaly;
\ No newline at end of file
@@ -0,0 +1,7 @@
+CREATE TABLE anomaly (
+    id serial PRIMARY KEY,
+    timestamp timestamptz NOT NULL,
+    person_id int NOT NULL,
+    cause varchar NOT NULL,
+    deviation real NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,4 @@
+ALTER TABLE post_stats
+    DROP COLUMN replies,
+    DROP COLUMN max_reply_depth,
+    DROP COLUMN mean_reply_depth;
\ No newline at end of file
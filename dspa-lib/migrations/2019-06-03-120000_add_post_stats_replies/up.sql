@@ -0,0 +1,4 @@
+ALTER TABLE post_stats
+    ADD COLUMN replies bigint NOT NULL DEFAULT 0,
+    ADD COLUMN max_reply_depth int NOT NULL DEFAULT 0,
+    ADD COLUMN mean_reply_depth double precision NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE post_stats_change;
\ No newline at end of file
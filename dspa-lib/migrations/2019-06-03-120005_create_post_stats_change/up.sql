@@ -0,0 +1,12 @@
+CREATE TABLE post_stats_change (
+    timestamp timestamptz NOT NULL,
+    post_id int NOT NULL,
+    change text NOT NULL,
+    users int NOT NULL,
+    comments bigint NOT NULL,
+    likes bigint NOT NULL,
+    replies bigint NOT NULL,
+    max_reply_depth int NOT NULL,
+    mean_reply_depth double precision NOT NULL,
+    PRIMARY KEY (timestamp, post_id)
+);
\ No newline at end of file
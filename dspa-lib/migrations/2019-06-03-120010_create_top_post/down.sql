@@ -0,0 +1 @@
+DROP TABLE top_post;
\ No newline at end of file
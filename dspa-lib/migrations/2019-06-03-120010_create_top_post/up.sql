@@ -0,0 +1,11 @@
+CREATE TABLE top_post (
+    timestamp timestamptz NOT NULL,
+    rank int NOT NULL,
+    post_id int NOT NULL,
+    score double precision NOT NULL,
+    users int NOT NULL,
+    comments bigint NOT NULL,
+    replies bigint NOT NULL,
+    likes bigint NOT NULL,
+    PRIMARY KEY (timestamp, rank)
+);
\ No newline at end of file
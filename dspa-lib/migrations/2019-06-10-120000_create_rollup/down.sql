@@ -0,0 +1 @@
+DROP TABLE rollup;
\ No newline at end of file
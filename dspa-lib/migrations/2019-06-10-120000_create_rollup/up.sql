@@ -0,0 +1,11 @@
+CREATE TABLE rollup (
+    timestamp timestamptz NOT NULL,
+    kind varchar NOT NULL,
+    group_id int NOT NULL,
+    posts bigint NOT NULL,
+    users int NOT NULL,
+    comments bigint NOT NULL,
+    replies bigint NOT NULL,
+    likes bigint NOT NULL,
+    PRIMARY KEY (timestamp, kind, group_id)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE trending_tag;
\ No newline at end of file
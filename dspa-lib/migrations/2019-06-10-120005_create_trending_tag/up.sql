@@ -0,0 +1,11 @@
+CREATE TABLE trending_tag (
+    timestamp timestamptz NOT NULL,
+    rank int NOT NULL,
+    tag_id int NOT NULL,
+    name varchar,
+    score double precision NOT NULL,
+    posts bigint NOT NULL,
+    engagements bigint NOT NULL,
+    baseline double precision NOT NULL,
+    PRIMARY KEY (timestamp, rank)
+);
\ No newline at end of file
@@ -0,0 +1,294 @@
+//! Snapshots of operator state, keyed by event time.
+//!
+//! Operators with state save a snapshot whenever a checkpoint time (a multiple of the interval)
+//! completes. A checkpoint is consistent once the output frontier of the dataflow has passed it,
+//! at which point `Checkpoint` marks it as complete in the directory of every process. Snapshots contain the effects of all events
+//! up to and including the checkpoint time, so a restored processor resumes with the events after
+//! it. Operators without a snapshot at a complete checkpoint had no state.
+
+use std::fs::{create_dir_all, read_dir, remove_dir_all, rename, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use bincode::{deserialize_from, serialize_into};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use structopt::StructOpt;
+
+use crate::error::{DspaError, Result};
+
+pub const CHECKPOINT_DIR: &str = "/tmp/dspa/checkpoints";
+
+const COMPLETE: &str = "COMPLETE";
+
+/// Version of the snapshot format, to be increased whenever the state of an operator changes
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Debug, StructOpt)]
+pub struct CheckpointArgs {
+    #[structopt(long = "checkpoint-interval", default_value = "3600")]
+    /// Event time in seconds between snapshots of the operator state, 0 disables snapshots
+    pub interval: u64,
+    #[structopt(long = "checkpoint-dir", parse(from_os_str))]
+    /// Directory to store snapshots in (default: /tmp/dspa/checkpoints/<processor>)
+    pub dir: Option<PathBuf>,
+    #[structopt(long = "restore")]
+    /// Restore the operator state from the last complete snapshot
+    pub restore: bool,
+}
+
+impl CheckpointArgs {
+    pub fn checkpoints(&self, processor: &str) -> Checkpoints {
+        let dir = self
+            .dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(CHECKPOINT_DIR).join(processor));
+
+        Checkpoints::new(dir, self.interval, self.restore)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Checkpoints {
+    dir: Option<PathBuf>,
+    interval: u64,
+    restored: Option<u64>,
+    // Latest checkpoint marked as complete by a worker of this process
+    completed: Arc<Mutex<Option<u64>>>,
+}
+
+impl Checkpoints {
+    /// Snapshots are written to the given directory every `interval` seconds of event time.
+    /// Existing snapshots are only removed if one was restored, in which case all others are
+    /// removed, or if this run writes snapshots to the directory itself. A snapshot of another
+    /// version is not restored, the processor starts from an empty state instead.
+    pub fn new(dir: PathBuf, interval: u64, restore: bool) -> Self {
+        let mut checkpoints = Checkpoints {
+            dir: None,
+            interval,
+            restored: None,
+            completed: Arc::default(),
+        };
+
+        if restore {
+            checkpoints.restored = Self::latest(&dir);
+            match checkpoints.restored {
+                Some(time) => match Self::check_versions(&dir.join(time.to_string())) {
+                    Ok(()) => eprintln!("Restoring snapshot at {}", time),
+                    Err(error) => {
+                        eprintln!(
+                            "Not restoring snapshot at {}, starting from an empty state: {}",
+                            time, error
+                        );
+                        checkpoints.restored = None;
+                    }
+                },
+                None => eprintln!("No complete snapshot found in {}", dir.display()),
+            }
+        }
+        *checkpoints.completed.lock().unwrap() = checkpoints.restored;
+
+        // Partial snapshots after the restored one and snapshots of a previous run would mix with
+        // the new ones
+        if checkpoints.restored.is_some() || interval > 0 {
+            if checkpoints.restored.is_none() {
+                if let Some(time) = Self::latest(&dir) {
+                    eprintln!(
+                        "Discarding snapshot at {} in {}, pass --restore to resume from it",
+                        time,
+                        dir.display()
+                    );
+                }
+            }
+
+            if let Err(error) = Self::remove_except(&dir, checkpoints.restored) {
+                eprintln!("Failed to remove old snapshots: {}", error);
+            }
+        }
+
+        if interval > 0 {
+            checkpoints.dir = Some(dir);
+        }
+        checkpoints
+    }
+
+    /// Latest complete checkpoint in the given directory
+    fn latest(dir: &PathBuf) -> Option<u64> {
+        read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join(COMPLETE).exists())
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u64>().ok())
+            .max()
+    }
+
+    /// Check that all snapshots in the directory of a checkpoint have the current version
+    fn check_versions(path: &Path) -> Result<()> {
+        for entry in read_dir(path)?.filter_map(|entry| entry.ok()) {
+            if entry.file_name() == COMPLETE {
+                continue;
+            }
+
+            let file = entry.path();
+            let reader = BufReader::new(File::open(&file)?);
+            let version: u32 = deserialize_from(reader)
+                .map_err(|error| DspaError::Output(format!("{}: {}", file.display(), error)))?;
+            if version != SNAPSHOT_VERSION {
+                return Err(DspaError::Output(format!(
+                    "{} has version {}, expected {}",
+                    file.display(),
+                    version,
+                    SNAPSHOT_VERSION
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Time of the restored snapshot, events up to and including it must not be replayed
+    pub fn restored(&self) -> Option<u64> {
+        self.restored
+    }
+
+    /// First checkpoint time at or after the given time
+    pub fn next(&self, time: u64) -> Option<u64> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let rem = time % self.interval;
+        if rem == 0 {
+            Some(time)
+        } else {
+            Some(time + self.interval - rem)
+        }
+    }
+
+    /// Last checkpoint time before the given time
+    pub fn previous(&self, time: u64) -> Option<u64> {
+        if !self.enabled() || time == 0 {
+            return None;
+        }
+
+        Some((time - 1) / self.interval * self.interval)
+    }
+
+    pub fn is_checkpoint(&self, time: u64) -> bool {
+        self.enabled() && time % self.interval == 0
+    }
+
+    fn path(&self, time: u64) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(time.to_string()))
+    }
+
+    /// Save the state of an operator on the given worker as of the checkpoint time
+    pub fn save<S: Serialize>(&self, operator: &str, worker: usize, time: u64, state: &S) {
+        let path = match self.path(time) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result: Result<()> = (|| {
+            create_dir_all(&path)?;
+
+            // Written to a temporary file first, such that snapshots are never partial
+            let file = path.join(format!("{}-{}", operator, worker));
+            let tmp = file.with_extension("tmp");
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            serialize_into(&mut writer, &SNAPSHOT_VERSION)
+                .and_then(|_| serialize_into(&mut writer, state))
+                .map_err(|error| DspaError::Output(error.to_string()))?;
+            drop(writer);
+            rename(tmp, file)?;
+            Ok(())
+        })();
+
+        if let Err(error) = result {
+            eprintln!(
+                "Failed to save snapshot of {} at {}: {}",
+                operator, time, error
+            );
+        }
+    }
+
+    /// State of an operator on the given worker from the restored snapshot, `None` if the
+    /// operator had no state. The versions of the restored snapshot were checked when it was
+    /// found, so a snapshot that cannot be read is corrupted and panics instead of silently
+    /// starting from an empty state.
+    pub fn load<S: DeserializeOwned>(&self, operator: &str, worker: usize) -> Option<S> {
+        let file = self
+            .dir
+            .as_ref()?
+            .join(self.restored?.to_string())
+            .join(format!("{}-{}", operator, worker));
+
+        let mut reader = BufReader::new(File::open(&file).ok()?);
+        match deserialize_from::<_, u32>(&mut reader).and_then(|_| deserialize_from(reader)) {
+            Ok(state) => Some(state),
+            Err(error) => panic!("Failed to load snapshot {}: {}", file.display(), error),
+        }
+    }
+
+    /// Remove all snapshots in the given directory except the one at the given time
+    fn remove_except(dir: &PathBuf, keep: Option<u64>) -> Result<()> {
+        if !dir.exists() {
+            return Ok(());
+        }
+
+        for entry in read_dir(dir)?.filter_map(|entry| entry.ok()) {
+            let time = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u64>().ok());
+
+            if time.is_some() && time != keep {
+                remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the checkpoint as consistent in the directory of this process and remove all older
+    /// snapshots. Only the first worker of the process to complete a checkpoint marks it.
+    pub fn complete(&self, time: u64) {
+        let (dir, path) = match (self.dir.as_ref(), self.path(time)) {
+            (Some(dir), Some(path)) => (dir, path),
+            _ => return,
+        };
+
+        {
+            let mut completed = self.completed.lock().unwrap();
+            if completed.map_or(false, |completed| completed >= time) {
+                return;
+            }
+            *completed = Some(time);
+        }
+
+        let result: Result<()> = (|| {
+            create_dir_all(&path)?;
+            File::create(path.join(COMPLETE))?;
+
+            for entry in read_dir(dir)?.filter_map(|entry| entry.ok()) {
+                let older = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.parse::<u64>().ok())
+                    .map_or(false, |snapshot| snapshot < time);
+
+                if older {
+                    remove_dir_all(entry.path())?;
+                }
+            }
+            Ok(())
+        })();
+
+        if let Err(error) = result {
+            eprintln!("Failed to complete snapshot at {}: {}", time, error);
+        }
+    }
+}
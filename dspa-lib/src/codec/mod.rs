@@ -0,0 +1,106 @@
+//! Serializations of the messages on the sockets.
+//!
+//! The codec of a message is recorded in its envelope, such that consumers decode every message
+//! with the codec it was encoded with. Every endpoint chooses the codec it sends with.
+
+use std::fmt;
+use std::str::FromStr;
+
+use prost::Message;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+
+mod protobuf;
+
+pub use protobuf::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    Bincode,
+    Json,
+    MessagePack,
+    Protobuf,
+}
+
+/// Values that can be encoded with every codec, using their protobuf message for `Protobuf`
+pub trait Encode
+where
+    Self: Serialize + DeserializeOwned + Sized,
+{
+    type Message: Message + Default;
+
+    fn to_message(&self) -> Self::Message;
+
+    fn from_message(message: Self::Message) -> Result<Self, String>;
+}
+
+impl Codec {
+    /// Identifier of the codec on the wire
+    pub fn id(self) -> u8 {
+        match self {
+            Codec::Bincode => 0,
+            Codec::Json => 1,
+            Codec::MessagePack => 2,
+            Codec::Protobuf => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, String> {
+        match id {
+            0 => Ok(Codec::Bincode),
+            1 => Ok(Codec::Json),
+            2 => Ok(Codec::MessagePack),
+            3 => Ok(Codec::Protobuf),
+            _ => Err(format!("Unknown codec: {}", id)),
+        }
+    }
+
+    pub fn encode<T: Encode>(self, value: &T) -> Vec<u8> {
+        match self {
+            Codec::Bincode => bincode::serialize(value).expect("Failed to encode bincode"),
+            Codec::Json => serde_json::to_vec(value).expect("Failed to encode JSON"),
+            // Named fields, such that other tools do not depend on the field order
+            Codec::MessagePack => {
+                rmp_serde::to_vec_named(value).expect("Failed to encode MessagePack")
+            }
+            Codec::Protobuf => value.to_message().encode_to_vec(),
+        }
+    }
+
+    pub fn decode<T: Encode>(self, data: &[u8]) -> Result<T, String> {
+        match self {
+            Codec::Bincode => bincode::deserialize(data).map_err(|error| error.to_string()),
+            Codec::Json => serde_json::from_slice(data).map_err(|error| error.to_string()),
+            Codec::MessagePack => rmp_serde::from_slice(data).map_err(|error| error.to_string()),
+            Codec::Protobuf => T::Message::decode(data)
+                .map_err(|error| error.to_string())
+                .and_then(T::from_message),
+        }
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Codec::Bincode => write!(f, "bincode"),
+            Codec::Json => write!(f, "json"),
+            Codec::MessagePack => write!(f, "msgpack"),
+            Codec::Protobuf => write!(f, "protobuf"),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "bincode" => Ok(Codec::Bincode),
+            "json" => Ok(Codec::Json),
+            "msgpack" => Ok(Codec::MessagePack),
+            "protobuf" => Ok(Codec::Protobuf),
+            _ => Err(format!("Unknown codec: {}", value)),
+        }
+    }
+}
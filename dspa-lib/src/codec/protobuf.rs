@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use prost::Message;
+
+use crate::codec::Encode;
+use crate::envelope::Header;
+use crate::records::{CommentRecord, LikeRecord, PostRecord};
+use crate::Control;
+
+// Dates are RFC 3339 strings, as in the JSON and MessagePack encodings
+
+fn date(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|error| error.to_string())
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PostMessage {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(int32, tag = "2")]
+    pub person_id: i32,
+    #[prost(string, tag = "3")]
+    pub creation_date: String,
+    #[prost(string, optional, tag = "4")]
+    pub image_file: Option<String>,
+    #[prost(string, tag = "5")]
+    pub location_ip: String,
+    #[prost(string, tag = "6")]
+    pub browser_used: String,
+    #[prost(string, optional, tag = "7")]
+    pub language: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub content: Option<String>,
+    #[prost(int32, repeated, tag = "9")]
+    pub tags: Vec<i32>,
+    #[prost(int32, tag = "10")]
+    pub forum_id: i32,
+    #[prost(int32, tag = "11")]
+    pub place_id: i32,
+}
+
+impl Encode for PostRecord {
+    type Message = PostMessage;
+
+    fn to_message(&self) -> PostMessage {
+        PostMessage {
+            id: self.id,
+            person_id: self.person_id,
+            creation_date: self.creation_date.to_rfc3339(),
+            image_file: self.image_file.clone(),
+            location_ip: self.location_ip.clone(),
+            browser_used: self.browser_used.clone(),
+            language: self.language.clone(),
+            content: self.content.clone(),
+            tags: self.tags.clone(),
+            forum_id: self.forum_id,
+            place_id: self.place_id,
+        }
+    }
+
+    fn from_message(message: PostMessage) -> Result<Self, String> {
+        Ok(PostRecord {
+            id: message.id,
+            person_id: message.person_id,
+            creation_date: date(&message.creation_date)?,
+            image_file: message.image_file,
+            location_ip: message.location_ip,
+            browser_used: message.browser_used,
+            language: message.language,
+            content: message.content,
+            tags: message.tags,
+            forum_id: message.forum_id,
+            place_id: message.place_id,
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CommentMessage {
+    #[prost(int32, tag = "1")]
+    pub id: i32,
+    #[prost(int32, tag = "2")]
+    pub person_id: i32,
+    #[prost(string, tag = "3")]
+    pub creation_date: String,
+    #[prost(string, tag = "4")]
+    pub location_ip: String,
+    #[prost(string, tag = "5")]
+    pub browser_used: String,
+    #[prost(string, tag = "6")]
+    pub content: String,
+    #[prost(int32, optional, tag = "7")]
+    pub reply_to_post_id: Option<i32>,
+    #[prost(int32, optional, tag = "8")]
+    pub reply_to_comment_id: Option<i32>,
+    #[prost(int32, tag = "9")]
+    pub place_id: i32,
+}
+
+impl Encode for CommentRecord {
+    type Message = CommentMessage;
+
+    fn to_message(&self) -> CommentMessage {
+        CommentMessage {
+            id: self.id,
+            person_id: self.person_id,
+            creation_date: self.creation_date.to_rfc3339(),
+            location_ip: self.location_ip.clone(),
+            browser_used: self.browser_used.clone(),
+            content: self.content.clone(),
+            reply_to_post_id: self.reply_to_post_id,
+            reply_to_comment_id: self.reply_to_comment_id,
+            place_id: self.place_id,
+        }
+    }
+
+    fn from_message(message: CommentMessage) -> Result<Self, String> {
+        Ok(CommentRecord {
+            id: message.id,
+            person_id: message.person_id,
+            creation_date: date(&message.creation_date)?,
+            location_ip: message.location_ip,
+            browser_used: message.browser_used,
+            content: message.content,
+            reply_to_post_id: message.reply_to_post_id,
+            reply_to_comment_id: message.reply_to_comment_id,
+            place_id: message.place_id,
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LikeMessage {
+    #[prost(int32, tag = "1")]
+    pub person_id: i32,
+    #[prost(int32, tag = "2")]
+    pub post_id: i32,
+    #[prost(string, tag = "3")]
+    pub creation_date: String,
+}
+
+impl Encode for LikeRecord {
+    type Message = LikeMessage;
+
+    fn to_message(&self) -> LikeMessage {
+        LikeMessage {
+            person_id: self.person_id,
+            post_id: self.post_id,
+            creation_date: self.creation_date.to_rfc3339(),
+        }
+    }
+
+    fn from_message(message: LikeMessage) -> Result<Self, String> {
+        Ok(LikeRecord {
+            person_id: message.person_id,
+            post_id: message.post_id,
+            creation_date: date(&message.creation_date)?,
+        })
+    }
+}
+
+/// Control message, an end of stream unless a watermark or registration is given
+#[derive(Clone, PartialEq, Message)]
+pub struct ControlMessage {
+    #[prost(uint64, optional, tag = "1")]
+    pub watermark: Option<u64>,
+    #[prost(bool, tag = "2")]
+    pub register: bool,
+}
+
+impl Encode for Control {
+    type Message = ControlMessage;
+
+    fn to_message(&self) -> ControlMessage {
+        ControlMessage {
+            watermark: match self {
+                Control::Watermark(time) => Some(*time),
+                _ => None,
+            },
+            register: *self == Control::Register,
+        }
+    }
+
+    fn from_message(message: ControlMessage) -> Result<Self, String> {
+        if message.register {
+            return Ok(Control::Register);
+        }
+        Ok(message.watermark.map_or(Control::Eos, Control::Watermark))
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct HeaderMessage {
+    #[prost(string, tag = "1")]
+    pub producer: String,
+    #[prost(uint64, tag = "2")]
+    pub sequence: u64,
+    #[prost(int64, tag = "3")]
+    pub event_time: i64,
+    #[prost(uint64, tag = "4")]
+    pub emitted: u64,
+    #[prost(btree_map = "string, string", tag = "5")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Encode for Header {
+    type Message = HeaderMessage;
+
+    fn to_message(&self) -> HeaderMessage {
+        HeaderMessage {
+            producer: self.producer.clone(),
+            sequence: self.sequence,
+            event_time: self.event_time,
+            emitted: self.emitted,
+            headers: self.headers.clone(),
+        }
+    }
+
+    fn from_message(message: HeaderMessage) -> Result<Self, String> {
+        Ok(Header {
+            producer: message.producer,
+            sequence: message.sequence,
+            event_time: message.event_time,
+            emitted: message.emitted,
+            headers: message.headers,
+        })
+    }
+}
@@ -0,0 +1,108 @@
+//! Messages that could not be decoded or are invalid, kept for inspection.
+//!
+//! The broker publishes them on the `deadletter` topic, encoded with bincode in a single frame
+//! instead of an envelope, and every receiver appends the ones it rejects to a local JSON Lines file.
+
+use std::fmt;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use serde_derive::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use crate::envelope::Frames;
+use crate::latency::wall_clock;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Wall-clock time the message was rejected at in milliseconds since the Unix epoch
+    pub time: u64,
+    pub topic: String,
+    pub error: String,
+    /// Frames of the message after the topic as received, including the envelope
+    pub frames: Frames,
+}
+
+impl DeadLetter {
+    pub fn new(topic: &str, error: &dyn fmt::Display, frames: &[Vec<u8>]) -> Self {
+        DeadLetter {
+            time: wall_clock(),
+            topic: topic.to_owned(),
+            error: error.to_string(),
+            frames: frames.to_vec(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to encode dead letter")
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        bincode::deserialize(data).map_err(|error| error.to_string())
+    }
+}
+
+impl fmt::Display for DeadLetter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Dead letter on {} ({} bytes): {}",
+            self.topic,
+            self.frames.iter().map(Vec::len).sum::<usize>(),
+            self.error
+        )
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DeadLetterArgs {
+    #[structopt(
+        long = "deadletter-dir",
+        default_value = "/tmp/dspa/deadletter",
+        parse(from_os_str)
+    )]
+    /// Directory to write rejected messages to
+    pub deadletter_dir: PathBuf,
+}
+
+impl DeadLetterArgs {
+    /// File of the dead letters of the given receiver
+    pub fn dead_letters(&self, name: &str) -> DeadLetters {
+        DeadLetters::new(
+            self.deadletter_dir
+                .join(format!("{}.jsonl", name.replace('/', "-"))),
+        )
+    }
+}
+
+/// JSON Lines file of dead letters, created with the first one
+pub struct DeadLetters {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+}
+
+impl DeadLetters {
+    pub fn new(path: PathBuf) -> Self {
+        DeadLetters { path, writer: None }
+    }
+
+    pub fn write(&mut self, letter: &DeadLetter) -> io::Result<()> {
+        if self.writer.is_none() {
+            if let Some(dir) = self.path.parent() {
+                create_dir_all(dir)?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.writer = Some(BufWriter::new(file));
+        }
+
+        // Dead letters are rare, they are written right away
+        let writer = self.writer.as_mut().unwrap();
+        serde_json::to_writer(&mut *writer, letter)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+}
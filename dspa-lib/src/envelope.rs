@@ -0,0 +1,224 @@
+//! Wire format of published records and control messages.
+//!
+//! Every message consists of a topic frame and the frames of an `Envelope`: a format frame with the
+//! schema version and the codec as plain bytes, a header frame and a payload frame. Consumers
+//! reject envelopes of incompatible producers by the format frame and decode the header and the
+//! payload with the codec they were encoded with. The payload is encoded on its own, such that it
+//! is a plain record of the codec rather than bytes nested in the header. Every producer numbers
+//! its messages per topic, which lets consumers detect lost or reordered messages.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::codec::{Codec, Encode};
+use crate::error::{self, DspaError};
+use crate::latency::wall_clock;
+use crate::metrics::{MISSING_MESSAGES, REORDERED_MESSAGES};
+use crate::records::StreamRecord;
+use crate::{Control, StreamEvent};
+
+/// Version of the envelope and record layout, envelopes of other versions are rejected
+pub const SCHEMA_VERSION: u16 = 3;
+
+/// Frames of a message after the topic frame
+pub type Frames = Vec<Vec<u8>>;
+
+/// Published record or control message
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    pub version: u16,
+    /// Codec of the header and the payload
+    pub codec: Codec,
+    /// Identity of the publishing operator instance
+    pub producer: String,
+    /// Number of the message among all messages of the producer on its topic, starting at 0
+    pub sequence: u64,
+    /// Event time of the record in seconds
+    pub event_time: i64,
+    /// Wall-clock time the record was published at in milliseconds since the Unix epoch
+    pub emitted: u64,
+    pub headers: BTreeMap<String, String>,
+    pub payload: Vec<u8>,
+}
+
+/// Fields of an envelope in the header frame
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub producer: String,
+    pub sequence: u64,
+    pub event_time: i64,
+    pub emitted: u64,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Envelope {
+    pub fn new<D>(codec: Codec, producer: &str, sequence: u64, record: &D) -> Self
+    where
+        D: StreamRecord + Encode,
+    {
+        Self::with_payload(
+            codec,
+            producer,
+            sequence,
+            record.timestamp(),
+            codec.encode(record),
+        )
+    }
+
+    /// Envelope of a control message, watermarks carry their time as event time
+    pub fn control(codec: Codec, producer: &str, sequence: u64, control: &Control) -> Self {
+        let event_time = match control {
+            Control::Watermark(time) => *time as i64,
+            _ => 0,
+        };
+        Self::with_payload(codec, producer, sequence, event_time, codec.encode(control))
+    }
+
+    fn with_payload(
+        codec: Codec,
+        producer: &str,
+        sequence: u64,
+        event_time: i64,
+        payload: Vec<u8>,
+    ) -> Self {
+        Envelope {
+            version: SCHEMA_VERSION,
+            codec,
+            producer: producer.to_owned(),
+            sequence,
+            event_time,
+            emitted: wall_clock(),
+            headers: BTreeMap::new(),
+            payload,
+        }
+    }
+
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Format, header and payload frame
+    pub fn encode(&self) -> Frames {
+        let mut format = self.version.to_le_bytes().to_vec();
+        format.push(self.codec.id());
+
+        let header = Header {
+            producer: self.producer.clone(),
+            sequence: self.sequence,
+            event_time: self.event_time,
+            emitted: self.emitted,
+            headers: self.headers.clone(),
+        };
+
+        vec![format, self.codec.encode(&header), self.payload.clone()]
+    }
+
+    /// Decode the frames of a message published on the given topic, rejecting incompatible
+    /// versions
+    pub fn decode(topic: &str, frames: &[Vec<u8>]) -> error::Result<Self> {
+        let decode_error = |message: String| DspaError::Decode {
+            topic: topic.to_owned(),
+            message,
+        };
+
+        let (format, header, payload) = match frames {
+            [format, header, payload] if format.len() == 3 => (format, header, payload),
+            [_, _, _] => return Err(decode_error("Invalid format frame".to_owned())),
+            _ => {
+                return Err(decode_error(format!(
+                    "Expected 3 frames, received {}",
+                    frames.len()
+                )))
+            }
+        };
+
+        let version = u16::from_le_bytes([format[0], format[1]]);
+        if version != SCHEMA_VERSION {
+            return Err(DspaError::IncompatibleVersion {
+                topic: topic.to_owned(),
+                version,
+            });
+        }
+
+        let codec = Codec::from_id(format[2]).map_err(decode_error)?;
+        let header: Header = codec.decode(header).map_err(decode_error)?;
+        Ok(Envelope {
+            version,
+            codec,
+            producer: header.producer,
+            sequence: header.sequence,
+            event_time: header.event_time,
+            emitted: header.emitted,
+            headers: header.headers,
+            payload: payload.clone(),
+        })
+    }
+
+    /// Decode the record of a message published on the given topic
+    pub fn event(&self, topic: &str) -> error::Result<StreamEvent> {
+        StreamEvent::decode(self.codec, topic, &self.payload)
+    }
+
+    /// Decode the control message of a message published on the given topic
+    pub fn control_message(&self, topic: &str) -> error::Result<Control> {
+        Control::decode(self.codec, topic, &self.payload)
+    }
+
+    /// Re-encode the payload of a message published on the given topic with another codec
+    pub fn transcode(mut self, topic: &str, codec: Codec) -> error::Result<Self> {
+        if codec != self.codec {
+            self.payload = match self.event(topic) {
+                Ok(event) => event.encode(codec),
+                Err(DspaError::UnknownTopic(_)) => codec.encode(&self.control_message(topic)?),
+                Err(error) => return Err(error),
+            };
+            self.codec = codec;
+        }
+        Ok(self)
+    }
+}
+
+/// Expected sequence numbers per producer and topic
+#[derive(Debug, Default)]
+pub struct Sequences {
+    // (Topic, Producer) => Next sequence number
+    expected: HashMap<(String, String), u64>,
+}
+
+impl Sequences {
+    /// Track the sequence number of a received envelope. Messages after lost ones and messages
+    /// received out of order are reported as a gap, but can still be processed. The first message
+    /// of a producer sets the expected sequence numbers, since a subscriber that joins late or
+    /// resumes does not receive the earlier ones.
+    pub fn observe(&mut self, topic: &str, envelope: &Envelope) -> error::Result<()> {
+        let received = envelope.sequence;
+        let expected = self
+            .expected
+            .entry((topic.to_owned(), envelope.producer.clone()))
+            .or_insert(received);
+
+        if received == *expected {
+            *expected += 1;
+            return Ok(());
+        }
+
+        if received > *expected {
+            MISSING_MESSAGES
+                .with_label_values(&[topic])
+                .inc_by((received - *expected) as i64);
+        } else {
+            REORDERED_MESSAGES.with_label_values(&[topic]).inc();
+        }
+
+        let gap = DspaError::SequenceGap {
+            topic: topic.to_owned(),
+            producer: envelope.producer.clone(),
+            expected: *expected,
+            received,
+        };
+        *expected = (*expected).max(received + 1);
+        Err(gap)
+    }
+}
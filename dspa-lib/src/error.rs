@@ -0,0 +1,189 @@
+use std::error::Error;
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+use timely::dataflow::operators::{Map, Partition};
+use timely::dataflow::{Scope, Stream};
+use timely::Data;
+
+use crate::envelope::SCHEMA_VERSION;
+
+pub type Result<T> = std::result::Result<T, DspaError>;
+
+/// Messages of database errors that are not classified by kind but mean the connection was lost
+const CONNECTION_LOST: &[&str] = &[
+    "server closed the connection",
+    "terminating connection",
+    "no connection to the server",
+    "could not receive data from server",
+    "could not send data to server",
+    "connection timed out",
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DspaError {
+    /// Database query failed
+    Database(String),
+    /// Database was unreachable or the transaction conflicted, after retrying
+    Unavailable(String),
+    /// Payload of a message could not be decoded
+    Decode { topic: String, message: String },
+    /// Message of a producer with another schema version
+    IncompatibleVersion { topic: String, version: u16 },
+    /// Sequence number of a producer's message differs from the expected one
+    SequenceGap {
+        topic: String,
+        producer: String,
+        expected: u64,
+        received: u64,
+    },
+    /// Message with a topic that is not known
+    UnknownTopic(String),
+    /// Comment without a post or comment to reply to
+    MissingParent { comment_id: i32 },
+    /// Results could not be written to the output
+    Output(String),
+}
+
+impl fmt::Display for DspaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DspaError::Database(message) => write!(f, "Database error: {}", message),
+            DspaError::Unavailable(message) => write!(f, "Database unavailable: {}", message),
+            DspaError::Decode { topic, message } => {
+                write!(f, "Failed to decode {} record: {}", topic, message)
+            }
+            DspaError::IncompatibleVersion { topic, version } => write!(
+                f,
+                "Incompatible {} record of schema version {}, expected {}",
+                topic, version, SCHEMA_VERSION
+            ),
+            DspaError::SequenceGap {
+                topic,
+                producer,
+                expected,
+                received,
+            } if received > expected => write!(
+                f,
+                "Lost {} {} messages of {} before sequence number {}",
+                received - expected,
+                topic,
+                producer,
+                received
+            ),
+            DspaError::SequenceGap {
+                topic,
+                producer,
+                expected,
+                received,
+            } => write!(
+                f,
+                "Received {} message {} of {} out of order, expected {}",
+                topic, received, producer, expected
+            ),
+            DspaError::UnknownTopic(topic) => write!(f, "Unknown topic: {}", topic),
+            DspaError::MissingParent { comment_id } => {
+                write!(f, "Missing parent for comment {}", comment_id)
+            }
+            DspaError::Output(message) => write!(f, "Output error: {}", message),
+        }
+    }
+}
+
+impl Error for DspaError {}
+
+impl From<diesel::result::Error> for DspaError {
+    fn from(error: diesel::result::Error) -> Self {
+        use diesel::result::{DatabaseErrorKind, Error};
+
+        match error {
+            // No result was received, the connection was lost
+            Error::DatabaseError(DatabaseErrorKind::UnableToSendCommand, _)
+            | Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _) => {
+                DspaError::Unavailable(error.to_string())
+            }
+            // The connection was dropped during the query
+            Error::DatabaseError(_, ref info)
+                if CONNECTION_LOST
+                    .iter()
+                    .any(|lost| info.message().contains(lost)) =>
+            {
+                DspaError::Unavailable(error.to_string())
+            }
+            _ => DspaError::Database(error.to_string()),
+        }
+    }
+}
+
+impl From<r2d2::Error> for DspaError {
+    fn from(error: r2d2::Error) -> Self {
+        DspaError::Unavailable(error.to_string())
+    }
+}
+
+impl From<std::io::Error> for DspaError {
+    fn from(error: std::io::Error) -> Self {
+        DspaError::Output(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for DspaError {
+    fn from(error: serde_json::Error) -> Self {
+        DspaError::Output(error.to_string())
+    }
+}
+
+impl From<csv::Error> for DspaError {
+    fn from(error: csv::Error) -> Self {
+        DspaError::Output(error.to_string())
+    }
+}
+
+/// Record that could not be processed, together with what was being done with it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FailedRecord {
+    pub context: String,
+    pub error: DspaError,
+}
+
+impl FailedRecord {
+    pub fn new<C: fmt::Debug>(context: &C, error: DspaError) -> Self {
+        FailedRecord {
+            context: format!("{:?}", context),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for FailedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {}", self.error, self.context)
+    }
+}
+
+pub trait SplitErrors<G, D>
+where
+    G: Scope,
+    D: Data,
+{
+    /// Split a stream of results into its successful records and its failed records
+    fn split_errors(&self) -> (Stream<G, D>, Stream<G, FailedRecord>);
+}
+
+impl<G, D> SplitErrors<G, D> for Stream<G, std::result::Result<D, FailedRecord>>
+where
+    G: Scope,
+    D: Data,
+{
+    fn split_errors(&self) -> (Stream<G, D>, Stream<G, FailedRecord>) {
+        let streams = self.partition(2, |result| match result {
+            Ok(_) => (0, result),
+            Err(_) => (1, result),
+        });
+
+        (
+            streams[0].flat_map(|result| result.ok()),
+            streams[1].flat_map(|result| result.err()),
+        )
+    }
+}
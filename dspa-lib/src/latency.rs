@@ -0,0 +1,215 @@
+//! End-to-end latency from publishing a record in `dspa-source` to the first result batch at or
+//! after its time.
+//!
+//! `streams` emits a `Stamp` for every received record on a side stream at the time of the
+//! record, and records the wall-clock time the input frontier passed that time in it. A record
+//! first shows up in the result batch at or after its time, which can only be produced once the
+//! input frontier has passed that batch. The time a record waits for the frontier after being
+//! received is delay-induced, everything else (transport and computation) is processing-induced.
+//!
+//! Every worker aggregates the stamps of a time into a `PartialLatency` of histograms, so only one
+//! aggregate per worker and time is sent to the worker that reports the latency of the batches.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Milliseconds since the Unix epoch
+pub fn wall_clock() -> u64 {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before Unix epoch");
+    elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())
+}
+
+/// Wall-clock times of a received record in milliseconds
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stamp {
+    /// Publish time in the envelope
+    pub emitted: u64,
+    pub received: u64,
+    /// Time the input frontier passed the time of the record
+    pub passed: u64,
+}
+
+impl Stamp {
+    pub fn new(emitted: u64, received: u64) -> Self {
+        Stamp {
+            emitted,
+            received,
+            passed: received,
+        }
+    }
+}
+
+/// Significant bits of the histogram buckets, values below `2^PRECISION` are exact and larger
+/// ones are rounded down by less than 1%
+const PRECISION: u32 = 8;
+
+/// Histogram of latencies in milliseconds
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    // Lower bound of the bucket => Count
+    buckets: BTreeMap<u64, u64>,
+}
+
+impl Histogram {
+    fn bucket(value: u64) -> u64 {
+        let shift = (64 - value.leading_zeros()).saturating_sub(PRECISION);
+        value >> shift << shift
+    }
+
+    fn add(&mut self, value: u64, count: u64) {
+        *self.buckets.entry(Self::bucket(value)).or_default() += count;
+    }
+
+    pub fn insert(&mut self, value: u64) {
+        self.add(value, 1);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.values().sum()
+    }
+
+    /// Add the values of the other histogram, increased by the given offset
+    pub fn merge(&mut self, other: &Histogram, offset: u64) {
+        for (value, count) in &other.buckets {
+            self.add(value + offset, *count);
+        }
+    }
+}
+
+/// Latency percentiles in milliseconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+}
+
+impl Percentiles {
+    /// Nearest rank percentiles of the values in the histogram
+    fn new(histogram: &Histogram) -> Self {
+        let count = histogram.count();
+        let rank = |percentile: u64| {
+            let rank = ((percentile * count + 99) / 100).max(1);
+            let mut seen = 0;
+            for (value, count) in &histogram.buckets {
+                seen += count;
+                if seen >= rank {
+                    return *value;
+                }
+            }
+            0
+        };
+
+        Percentiles {
+            p50: rank(50),
+            p95: rank(95),
+            p99: rank(99),
+        }
+    }
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "p50 {}ms, p95 {}ms, p99 {}ms",
+            self.p50, self.p95, self.p99
+        )
+    }
+}
+
+/// Latency of the records that first contributed to a result batch
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchLatency {
+    pub records: usize,
+    pub total: Percentiles,
+    pub delay: Percentiles,
+    pub processing: Percentiles,
+}
+
+impl fmt::Display for BatchLatency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} records, total {}, delay {}, processing {}",
+            self.records, self.total, self.delay, self.processing
+        )
+    }
+}
+
+/// Latency of the records a worker received for a time, up to the wall-clock time the input
+/// frontier passed that time
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PartialLatency {
+    /// Time the input frontier passed the time of the records
+    pub passed: u64,
+    total: Histogram,
+    delay: Histogram,
+    processing: Histogram,
+}
+
+impl PartialLatency {
+    pub fn new(stamps: &[Stamp]) -> Self {
+        let passed = stamps.iter().map(|stamp| stamp.passed).max().unwrap_or(0);
+        let mut partial = PartialLatency {
+            passed,
+            ..PartialLatency::default()
+        };
+
+        for stamp in stamps {
+            let latency = passed.saturating_sub(stamp.emitted);
+            let waiting = stamp.passed.saturating_sub(stamp.received).min(latency);
+            partial.total.insert(latency);
+            partial.delay.insert(waiting);
+            partial.processing.insert(latency - waiting);
+        }
+        partial
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LatencyTracker {
+    // Time => Partial latencies of the records received for it
+    pending: BTreeMap<u64, Vec<PartialLatency>>,
+}
+
+impl LatencyTracker {
+    /// Track the records a worker received for the given time
+    pub fn received(&mut self, time: u64, partial: PartialLatency) {
+        self.pending.entry(time).or_default().push(partial);
+    }
+
+    /// Latency of all records up to the given time, whose batch was written at the given
+    /// wall-clock time
+    pub fn complete(&mut self, time: u64, now: u64) -> Option<BatchLatency> {
+        let later = self.pending.split_off(&(time + 1));
+        let partials = std::mem::replace(&mut self.pending, later);
+
+        // Records keep waiting for the batch after the frontier passed their time
+        let mut total = Histogram::default();
+        let mut delay = Histogram::default();
+        let mut processing = Histogram::default();
+        for partial in partials.values().flatten() {
+            let waited = now.saturating_sub(partial.passed);
+            total.merge(&partial.total, waited);
+            delay.merge(&partial.delay, 0);
+            processing.merge(&partial.processing, waited);
+        }
+
+        if total.count() == 0 {
+            return None;
+        }
+
+        Some(BatchLatency {
+            records: total.count() as usize,
+            total: Percentiles::new(&total),
+            delay: Percentiles::new(&delay),
+            processing: Percentiles::new(&processing),
+        })
+    }
+}
@@ -2,13 +2,37 @@
 
 #[macro_use]
 extern crate diesel;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
 use serde_derive::{Deserialize, Serialize};
+use structopt::StructOpt;
+use timely::Configuration;
 
+use codec::Codec;
+use error::DspaError;
+use partition::split_topic;
 use records::{CommentRecord, LikeRecord, PostRecord, StreamRecord};
 
+pub mod checkpoint;
+pub mod codec;
+pub mod deadletter;
+pub mod envelope;
+pub mod error;
+pub mod latency;
+pub mod metrics;
 pub mod operators;
+pub mod partition;
 pub mod records;
 pub mod schema;
+pub mod shutdown;
+pub mod sink;
+pub mod store;
+pub mod subscription;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub const SOURCE_SOCKET: &str = "/tmp/dspa/source";
 pub const DATA_SOCKET: &str = "/tmp/dspa/data";
@@ -17,15 +41,82 @@ pub const DATABASE_URL: &str = "postgres://root@localhost/dspa";
 
 pub const SOCKET_TIMEOUT: i32 = 100;
 
+// Database connection timeout and retry policy, backoff doubles after every attempt
+pub const DATABASE_TIMEOUT: u64 = 1000;
+pub const DATABASE_RETRIES: u32 = 5;
+pub const DATABASE_BACKOFF: u64 = 100;
+
 // Max delay of one day
 pub const MAX_DELAY: u64 = 60 * 60 * 24;
 
-#[derive(Copy, Clone, Debug)]
+/// Timely execution arguments, mirroring the standard `-w/-n/-p/-h` flags
+#[derive(Debug, StructOpt)]
+pub struct TimelyArgs {
+    #[structopt(short = "w", long = "threads", default_value = "1")]
+    /// Number of worker threads per process
+    pub threads: usize,
+    #[structopt(short = "n", long = "processes", default_value = "1")]
+    /// Number of processes
+    pub processes: usize,
+    #[structopt(short = "p", long = "process", default_value = "0")]
+    /// Identity of this process
+    pub process: usize,
+    #[structopt(short = "h", long = "hostfile", parse(from_os_str))]
+    /// Text file whose lines are process addresses
+    pub hostfile: Option<PathBuf>,
+}
+
+impl TimelyArgs {
+    pub fn configuration(&self) -> Configuration {
+        assert!(
+            self.process < self.processes,
+            "Process index must be smaller than number of processes"
+        );
+
+        if self.processes > 1 {
+            let addresses = if let Some(hostfile) = &self.hostfile {
+                let file = File::open(hostfile).expect("Failed to open hostfile");
+                let addresses = BufReader::new(file)
+                    .lines()
+                    .take(self.processes)
+                    .collect::<Result<Vec<_>, _>>()
+                    .expect("Failed to read hostfile");
+                assert_eq!(
+                    addresses.len(),
+                    self.processes,
+                    "Hostfile contains fewer addresses than processes"
+                );
+                addresses
+            } else {
+                (0..self.processes)
+                    .map(|idx| format!("localhost:{}", 2101 + idx))
+                    .collect()
+            };
+
+            Configuration::Cluster {
+                threads: self.threads,
+                process: self.process,
+                addresses,
+                report: false,
+                log_fn: Box::new(|_| None),
+            }
+        } else if self.threads > 1 {
+            Configuration::Process(self.threads)
+        } else {
+            Configuration::Thread
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Topic {
     Post,
     Comment,
     Like,
     EOS,
+    Watermark,
+    Register,
+    DeadLetter,
 }
 
 impl ToString for Topic {
@@ -35,6 +126,43 @@ impl ToString for Topic {
             Topic::Comment => "comment".to_owned(),
             Topic::Like => "like".to_owned(),
             Topic::EOS => "eos".to_owned(),
+            Topic::Watermark => "watermark".to_owned(),
+            Topic::Register => "register".to_owned(),
+            Topic::DeadLetter => "deadletter".to_owned(),
+        }
+    }
+}
+
+/// Control message of a producer, published on the `register`, `eos` and `watermark` topics
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Control {
+    /// The producer starts publishing, the broker waits for its end of stream
+    Register,
+    /// The producer does not publish any more records
+    Eos,
+    /// The producer does not publish any more records before the given event time
+    Watermark(u64),
+}
+
+impl Control {
+    pub fn topic(&self) -> Topic {
+        match self {
+            Control::Register => Topic::Register,
+            Control::Eos => Topic::EOS,
+            Control::Watermark(_) => Topic::Watermark,
+        }
+    }
+
+    /// Decode the payload of a message published on the given topic
+    pub fn decode(codec: Codec, topic: &str, data: &[u8]) -> error::Result<Self> {
+        match split_topic(topic).0 {
+            "register" | "eos" | "watermark" => {
+                codec.decode(data).map_err(|message| DspaError::Decode {
+                    topic: topic.to_owned(),
+                    message,
+                })
+            }
+            _ => Err(DspaError::UnknownTopic(topic.to_owned())),
         }
     }
 }
@@ -47,6 +175,38 @@ pub enum StreamEvent {
 }
 
 impl StreamEvent {
+    /// Decode the payload of a message published on the given topic
+    pub fn decode(codec: Codec, topic: &str, data: &[u8]) -> error::Result<Self> {
+        let decode_error = |message: String| DspaError::Decode {
+            topic: topic.to_owned(),
+            message,
+        };
+
+        match split_topic(topic).0 {
+            "post" => codec.decode(data).map(StreamEvent::Post),
+            "comment" => codec.decode(data).map(StreamEvent::Comment),
+            "like" => codec.decode(data).map(StreamEvent::Like),
+            _ => return Err(DspaError::UnknownTopic(topic.to_owned())),
+        }
+        .map_err(decode_error)
+    }
+
+    pub fn encode(&self, codec: Codec) -> Vec<u8> {
+        match self {
+            StreamEvent::Post(record) => codec.encode(record),
+            StreamEvent::Comment(record) => codec.encode(record),
+            StreamEvent::Like(record) => codec.encode(record),
+        }
+    }
+
+    pub fn topic(&self) -> Topic {
+        match self {
+            StreamEvent::Post(_) => Topic::Post,
+            StreamEvent::Comment(_) => Topic::Comment,
+            StreamEvent::Like(_) => Topic::Like,
+        }
+    }
+
     pub fn id(&self) -> Option<i32> {
         match self {
             StreamEvent::Post(record) => record.id(),
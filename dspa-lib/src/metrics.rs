@@ -0,0 +1,234 @@
+//! Prometheus metrics of the binaries, served over a local HTTP port.
+//!
+//! All metrics are registered in the default registry, such that every process exposes one
+//! endpoint for all of its workers.
+
+use std::thread;
+
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Encoder, GaugeVec, Histogram, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use structopt::StructOpt;
+use tiny_http::{Header, Response, Server};
+
+use crate::error::DspaError;
+use crate::latency::BatchLatency;
+
+pub const METRICS_ADDRESS: &str = "127.0.0.1";
+
+// Default ports, processes of the processors add their index
+pub const SOURCE_METRICS_PORT: u16 = 9200;
+pub const MQ_METRICS_PORT: u16 = 9201;
+pub const POST_STATS_METRICS_PORT: u16 = 9210;
+pub const RECOMMENDATIONS_METRICS_PORT: u16 = 9220;
+pub const ANOMALIES_METRICS_PORT: u16 = 9230;
+
+lazy_static! {
+    pub static ref RECORDS_RECEIVED: IntCounterVec = register_int_counter_vec!(
+        "dspa_records_received_total",
+        "Records received per topic",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref RECORDS_EMITTED: IntCounterVec = register_int_counter_vec!(
+        "dspa_records_emitted_total",
+        "Records emitted per topic or result table",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref LATE_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "dspa_late_events_total",
+        "Events discarded because they arrived behind the input frontier",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref DISCARDED_EVENTS: IntCounterVec = register_int_counter_vec!(
+        "dspa_discarded_events_total",
+        "Events discarded because they could not be processed",
+        &["topic", "reason"]
+    )
+    .unwrap();
+    pub static ref MISSING_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "dspa_missing_messages_total",
+        "Messages lost according to the sequence numbers of their producers",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref REORDERED_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "dspa_reordered_messages_total",
+        "Messages received after a message with a higher sequence number",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref DROPPED_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "dspa_dropped_messages_total",
+        "Messages the broker dropped because the queue of a subscriber was full",
+        &["subscriber", "topic"]
+    )
+    .unwrap();
+    pub static ref SPILLED_MESSAGES: IntCounterVec = register_int_counter_vec!(
+        "dspa_spilled_messages_total",
+        "Messages the broker spilled to disk because the queue of a subscriber was full",
+        &["subscriber"]
+    )
+    .unwrap();
+    pub static ref QUEUED_MESSAGES: IntGaugeVec = register_int_gauge_vec!(
+        "dspa_queued_messages",
+        "Messages waiting in the broker queue of a subscriber, including spilled ones",
+        &["subscriber"]
+    )
+    .unwrap();
+    pub static ref PENDING_RECORDS: IntGaugeVec = register_int_gauge_vec!(
+        "dspa_pending_records",
+        "Records waiting for their dependencies in the ordering operators",
+        &["operator", "worker"]
+    )
+    .unwrap();
+    pub static ref INPUT_FRONTIER: IntGauge = register_int_gauge!(
+        "dspa_input_frontier_seconds",
+        "Event time of the input frontier of the stream source"
+    )
+    .unwrap();
+    pub static ref EVENT_TIME: IntGauge = register_int_gauge!(
+        "dspa_event_time_seconds",
+        "Latest event time received by the stream source"
+    )
+    .unwrap();
+    pub static ref FRONTIER_LAG: IntGauge = register_int_gauge!(
+        "dspa_frontier_lag_seconds",
+        "Event time by which the input frontier trails the latest received event"
+    )
+    .unwrap();
+    pub static ref DATABASE_LATENCY: HistogramVec = register_histogram_vec!(
+        "dspa_database_seconds",
+        "Latency of database operations including retries",
+        &["operation"]
+    )
+    .unwrap();
+    pub static ref FORWARD_LATENCY: HistogramVec = register_histogram_vec!(
+        "dspa_forward_latency_seconds",
+        "Latency from publishing a record to forwarding it in the broker",
+        &["topic"]
+    )
+    .unwrap();
+    pub static ref BATCH_LATENCY: GaugeVec = register_gauge_vec!(
+        "dspa_batch_latency_seconds",
+        "Latency from publishing to the last result batch of the records first contained in it",
+        &["part", "quantile"]
+    )
+    .unwrap();
+    pub static ref USERS_ESTIMATION_ERROR: GaugeVec = register_gauge_vec!(
+        "dspa_users_estimation_error_ratio",
+        "Relative error of the estimated unique users in the last report when comparing counts",
+        &["worker", "statistic"]
+    )
+    .unwrap();
+    pub static ref SEEDING_FAILURES: IntCounter = register_int_counter!(
+        "dspa_seeding_failures_total",
+        "Failed attempts to seed the operator state from the store"
+    )
+    .unwrap();
+    pub static ref BATCH_TIME: HistogramVec = register_histogram_vec!(
+        "dspa_batch_seconds",
+        "Time an operator spends on one activation",
+        &["operator"]
+    )
+    .unwrap();
+}
+
+/// Reason label of a message that could not be decoded
+pub fn discard_reason(error: &DspaError) -> &'static str {
+    match error {
+        DspaError::IncompatibleVersion { .. } => "version",
+        DspaError::UnknownTopic(_) => "topic",
+        _ => "decode",
+    }
+}
+
+/// Histogram of the activations of the given operator, to be timed with `start_timer`
+pub fn batch_time(operator: &str) -> Histogram {
+    BATCH_TIME.with_label_values(&[operator])
+}
+
+/// Update the latency percentiles of the last result batch
+pub fn observe_latency(latency: &BatchLatency) {
+    let parts = [
+        ("total", &latency.total),
+        ("delay", &latency.delay),
+        ("processing", &latency.processing),
+    ];
+    for (part, percentiles) in parts.iter() {
+        let quantiles = [
+            ("0.5", percentiles.p50),
+            ("0.95", percentiles.p95),
+            ("0.99", percentiles.p99),
+        ];
+        for (quantile, millis) in quantiles.iter() {
+            BATCH_LATENCY
+                .with_label_values(&[part, quantile])
+                .set(*millis as f64 / 1000.0);
+        }
+    }
+}
+
+/// Update the input frontier and the latest event time of the stream source
+pub fn observe_frontier(frontier: u64, event_time: u64) {
+    let event_time = EVENT_TIME.get().max(event_time as i64);
+    INPUT_FRONTIER.set(frontier as i64);
+    EVENT_TIME.set(event_time);
+    FRONTIER_LAG.set(event_time - frontier as i64);
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MetricsArgs {
+    #[structopt(long = "metrics-port")]
+    /// Port to serve metrics on, 0 disables the endpoint (default: binary specific port + process)
+    pub port: Option<u16>,
+}
+
+impl MetricsArgs {
+    /// Serve the metrics of the given process, processes of one binary use consecutive ports
+    pub fn serve(&self, default_port: u16, process: usize) {
+        match self.port {
+            Some(0) => {}
+            Some(port) => serve(port + process as u16),
+            None => serve(default_port + process as u16),
+        }
+    }
+}
+
+/// Serve the metrics in the Prometheus text format on a background thread
+pub fn serve(port: u16) {
+    let server = match Server::http((METRICS_ADDRESS, port)) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("Failed to serve metrics on port {}: {}", port, error);
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        let encoder = TextEncoder::new();
+        let content_type = Header::from_bytes(&b"Content-Type"[..], encoder.format_type()).unwrap();
+
+        for request in server.incoming_requests() {
+            let response = match request.url() {
+                "/" | "/metrics" => {
+                    let mut buffer = Vec::new();
+                    encoder
+                        .encode(&prometheus::gather(), &mut buffer)
+                        .expect("Failed to encode metrics");
+                    Response::from_data(buffer).with_header(content_type.clone())
+                }
+                _ => Response::from_string("Not Found").with_status_code(404),
+            };
+
+            if let Err(error) = request.respond(response) {
+                eprintln!("Failed to respond to metrics request: {}", error);
+            }
+        }
+    });
+}
@@ -0,0 +1,43 @@
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::Data;
+
+use crate::checkpoint::Checkpoints;
+
+pub trait Checkpoint<G, D>
+where
+    G: Scope<Timestamp = u64>,
+    D: Data,
+{
+    /// Mark checkpoints as complete once the frontier of the stream has passed them. Must be
+    /// applied to the output of the dataflow, such that all operators have saved their snapshots.
+    /// The frontier is the same on all workers, so every process marks its own directory.
+    fn checkpoint(&self, checkpoints: &Checkpoints) -> Stream<G, D>;
+}
+
+impl<G, D> Checkpoint<G, D> for Stream<G, D>
+where
+    G: Scope<Timestamp = u64>,
+    D: Data,
+{
+    fn checkpoint(&self, checkpoints: &Checkpoints) -> Stream<G, D> {
+        let checkpoints = checkpoints.clone();
+
+        let mut vec = Vec::new();
+        self.unary_frontier(Pipeline, "Checkpoint", move |_, _| {
+            move |input, output| {
+                input.for_each(|cap, data| {
+                    data.swap(&mut vec);
+                    output.session(&cap).give_vec(&mut vec);
+                });
+
+                // The frontier is empty once the input has ended, snapshots are not needed then
+                let frontier = input.frontier().frontier().iter().min().cloned();
+                if let Some(time) = frontier.and_then(|time| checkpoints.previous(time)) {
+                    checkpoints.complete(time);
+                }
+            }
+        })
+    }
+}
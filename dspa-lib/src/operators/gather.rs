@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::ExchangeData;
+
+use crate::metrics::batch_time;
+
+pub trait Gather<G, D>
+where
+    G: Scope<Timestamp = u64>,
+    D: ExchangeData,
+{
+    fn gather(&self) -> Stream<G, Vec<D>>;
+}
+
+impl<G, D> Gather<G, D> for Stream<G, D>
+where
+    G: Scope<Timestamp = u64>,
+    D: ExchangeData,
+{
+    fn gather(&self) -> Stream<G, Vec<D>> {
+        // Time => Records
+        let mut pending: HashMap<u64, Vec<D>> = HashMap::new();
+
+        let batch_time = batch_time("Gather");
+
+        let mut vec = Vec::new();
+        self.unary_notify(
+            Exchange::new(|_| 0),
+            "Gather",
+            None,
+            move |input, output, notificator| {
+                let _timer = batch_time.start_timer();
+
+                input.for_each(|cap, data| {
+                    data.swap(&mut vec);
+
+                    pending
+                        .entry(*cap.time())
+                        .or_default()
+                        .extend(vec.drain(..));
+                    notificator.notify_at(cap.retain());
+                });
+
+                // Output all records of a time once it is complete on every worker
+                notificator.for_each(|cap, _, _| {
+                    if let Some(records) = pending.remove(cap.time()) {
+                        output.session(&cap).give(records);
+                    }
+                });
+            },
+        )
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+use timely::dataflow::operators::Operator;
+use timely::dataflow::{Scope, Stream};
+use timely::Data;
+
+use crate::latency::{wall_clock, BatchLatency, LatencyTracker, PartialLatency, Stamp};
+use crate::metrics::observe_latency;
+
+/// Record the wall-clock time at which the input frontier passed the time of every stamp
+pub(crate) fn frontier_passed<G>(stamps: &Stream<G, Stamp>) -> Stream<G, Stamp>
+where
+    G: Scope<Timestamp = u64>,
+{
+    // Time => Stamps
+    let mut pending: HashMap<u64, Vec<Stamp>> = HashMap::new();
+
+    let mut vec = Vec::new();
+    stamps.unary_notify(
+        Pipeline,
+        "Frontier Passed",
+        None,
+        move |input, output, notificator| {
+            input.for_each(|cap, data| {
+                data.swap(&mut vec);
+                pending
+                    .entry(*cap.time())
+                    .or_default()
+                    .extend(vec.drain(..));
+                notificator.notify_at(cap.retain());
+            });
+
+            notificator.for_each(|cap, _, _| {
+                if let Some(mut stamps) = pending.remove(cap.time()) {
+                    let now = wall_clock();
+                    for stamp in &mut stamps {
+                        stamp.passed = stamp.passed.max(now);
+                    }
+                    output.session(&cap).give_iterator(stamps.into_iter());
+                }
+            });
+        },
+    )
+}
+
+pub trait TrackLatency<G>
+where
+    G: Scope<Timestamp = u64>,
+{
+    /// Report the latency of the stamped records at the first written batch at or after their
+    /// time as metrics, on worker 0. The stamps are aggregated per time on every worker first.
+    fn track_latency<D: Data>(&self, written: &Stream<G, D>) -> Stream<G, BatchLatency>;
+}
+
+impl<G> TrackLatency<G> for Stream<G, Stamp>
+where
+    G: Scope<Timestamp = u64>,
+{
+    fn track_latency<D: Data>(&self, written: &Stream<G, D>) -> Stream<G, BatchLatency> {
+        let mut tracker = LatencyTracker::default();
+        // Time => Wall-clock time the batch was written
+        let mut batches: HashMap<u64, u64> = HashMap::new();
+
+        // Time => Stamps
+        let mut pending: HashMap<u64, Vec<Stamp>> = HashMap::new();
+
+        let mut vec = Vec::new();
+        let partials = self.unary_notify(
+            Pipeline,
+            "Partial Latency",
+            None,
+            move |input, output, notificator| {
+                input.for_each(|cap, data| {
+                    data.swap(&mut vec);
+                    pending
+                        .entry(*cap.time())
+                        .or_default()
+                        .extend(vec.drain(..));
+                    notificator.notify_at(cap.retain());
+                });
+
+                notificator.for_each(|cap, _, _| {
+                    if let Some(stamps) = pending.remove(cap.time()) {
+                        output.session(&cap).give(PartialLatency::new(&stamps));
+                    }
+                });
+            },
+        );
+
+        let mut partials_vec = Vec::new();
+        let mut rows = Vec::new();
+        partials.binary_notify(
+            written,
+            Exchange::new(|_| 0),
+            Pipeline,
+            "Track Latency",
+            None,
+            move |partials_input, written_input, output, notificator| {
+                partials_input.for_each(|cap, data| {
+                    data.swap(&mut partials_vec);
+                    for partial in partials_vec.drain(..) {
+                        tracker.received(*cap.time(), partial);
+                    }
+                });
+
+                written_input.for_each(|cap, data| {
+                    data.swap(&mut rows);
+                    rows.clear();
+                    batches.entry(*cap.time()).or_insert_with(wall_clock);
+                    notificator.notify_at(cap.retain());
+                });
+
+                // All partial latencies up to the batch have arrived once the notification is delivered
+                notificator.for_each(|cap, _, _| {
+                    if let Some(written) = batches.remove(cap.time()) {
+                        if let Some(latency) = tracker.complete(*cap.time(), written) {
+                            observe_latency(&latency);
+                            output.session(&cap).give(latency);
+                        }
+                    }
+                });
+            },
+        )
+    }
+}
@@ -1,5 +1,13 @@
+mod checkpoint;
+mod gather;
+mod latency;
 mod order;
 mod source;
+mod write;
 
+pub use checkpoint::*;
+pub use gather::*;
+pub use latency::*;
 pub use order::*;
 pub use source::*;
+pub use write::*;
@@ -1,18 +1,17 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::schema::post;
-use diesel::prelude::*;
-use diesel::r2d2::ConnectionManager;
-use diesel::PgConnection;
 use either::Either;
-use r2d2::Pool;
 use serde_derive::{Deserialize, Serialize};
 use timely::dataflow::channels::pact::Pipeline;
-use timely::dataflow::operators::{Broadcast, Capability, Concat, Map, Operator};
+use timely::dataflow::operators::{Broadcast, Concat, Map, Operator};
 use timely::dataflow::{Scope, Stream};
 
-use crate::records::{CommentRecord, LikeRecord, PostRecord};
+use crate::checkpoint::Checkpoints;
+use crate::error::{FailedRecord, SplitErrors};
+use crate::metrics::{batch_time, PENDING_RECORDS};
+use crate::records::{CommentRecord, LikeRecord, PostRecord, StreamRecord};
+use crate::store::SocialGraphStore;
 
 pub trait Ordered<G, D1, D2>
 where
@@ -22,9 +21,43 @@ where
         &self,
         idx: usize,
         peers: usize,
-        pool: Arc<Pool<ConnectionManager<PgConnection>>>,
+        store: Arc<dyn SocialGraphStore>,
         dependency: &Stream<G, D2>,
-    ) -> Stream<G, D1>;
+        checkpoints: &Checkpoints,
+    ) -> (Stream<G, D1>, Stream<G, FailedRecord>);
+}
+
+/// Records of a pending map that belong to the snapshot at the given checkpoint time
+fn pending_at<D>(pending: &HashMap<i32, Vec<D>>, time: u64) -> HashMap<i32, Vec<D>>
+where
+    D: StreamRecord + Clone,
+{
+    pending
+        .iter()
+        .map(|(id, records)| {
+            let records = records
+                .iter()
+                .filter(|record| record.timestamp() as u64 <= time)
+                .cloned()
+                .collect::<Vec<_>>();
+            (*id, records)
+        })
+        .filter(|(_, records)| !records.is_empty())
+        .collect()
+}
+
+/// Records released after the checkpoint time although they were pending at it. Their releasing
+/// event is replayed after a restore, so they are part of the snapshot.
+fn released_at<D>(released: &[(u64, D)], time: u64) -> impl Iterator<Item = &D>
+where
+    D: StreamRecord,
+{
+    released
+        .iter()
+        .filter(move |(released_time, record)| {
+            record.timestamp() as u64 <= time && time < *released_time
+        })
+        .map(|(_, record)| record)
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -41,101 +74,149 @@ where
         &self,
         idx: usize,
         peers: usize,
-        pool: Arc<Pool<ConnectionManager<PgConnection>>>,
+        store: Arc<dyn SocialGraphStore>,
         dependency: &Stream<G, PostRecord>,
-    ) -> Stream<G, CommentRecord> {
+        checkpoints: &Checkpoints,
+    ) -> (Stream<G, CommentRecord>, Stream<G, FailedRecord>) {
         // ! Broadcast before map to prevent timely panic
         let posts = dependency.broadcast().map(CommentOrderEvent::Post);
         let comments = self.broadcast().map(CommentOrderEvent::Comment);
 
-        posts
-            .concat(&comments)
-            .unary(Pipeline, "Comment Ordered", move |_, _| {
-                // TODO: Memory leak if parent comment/post dropped
-                let mut pending: HashMap<i32, Vec<CommentRecord>> = HashMap::new();
-                let mut pending_children: HashMap<i32, Vec<CommentRecord>> = HashMap::new();
+        // Every worker tracks every comment, but only outputs the ones it is responsible for
+        let responsible = move |comment: &CommentRecord| comment.id as usize % peers == idx;
 
-                move |input, output| {
-                    let connection = pool.get().unwrap();
+        let checkpoints = checkpoints.clone();
 
-                    let mut vec = Vec::new();
-                    input.for_each(|cap, data| {
-                        data.swap(&mut vec);
+        // TODO: Memory leak if parent comment/post dropped
+        // Post Id => Comments, Comment Id => Comments
+        let (mut pending, mut pending_children): (
+            HashMap<i32, Vec<CommentRecord>>,
+            HashMap<i32, Vec<CommentRecord>>,
+        ) = checkpoints.load("comment-ordered", idx).unwrap_or_default();
+        // Release Time => Comments, for comments released after one of their checkpoints
+        let mut released: Vec<(u64, CommentRecord)> = Vec::new();
 
-                        vec.drain(..).for_each(|event| {
-                            match event {
-                                CommentOrderEvent::Post(post) => {
-                                    if let Some(mut records) = pending.remove(&post.id) {
-                                        output.session(&cap).give_vec(&mut records);
-                                    }
-                                }
-                                CommentOrderEvent::Comment(comment) => {
-                                    match comment.available_parent(&connection) {
-                                        // Known post
-                                        Some(Either::Left(post)) => {
-                                            // Ordered, ouput self and children
-                                            let id = comment.id;
-                                            let mut session = output.session(&cap);
-
-                                            // Output self if responsible
-                                            if comment.id % peers as i32 == idx as i32 {
-                                                session.give(comment);
-                                            }
+        let init = checkpoints
+            .restored()
+            .filter(|_| !pending.is_empty() || !pending_children.is_empty())
+            .and_then(|time| checkpoints.next(time + 1));
 
-                                            // Output children
-                                            if let Some(mut records) = pending_children.remove(&id)
-                                            {
-                                                session.give_vec(&mut records);
-                                            }
-                                        }
-                                        // Known parent comment
-                                        Some(Either::Right(parent)) => {
-                                            let children = pending_children.remove(&comment.id);
+        let batch_time = batch_time("Comment Ordered");
+        let pending_records =
+            PENDING_RECORDS.with_label_values(&["Comment Ordered", &idx.to_string()]);
 
-                                            let vec =
-                                                pending_children.entry(parent.id).or_default();
+        let mut vec = Vec::new();
+        posts
+            .concat(&comments)
+            .unary_notify(
+                Pipeline,
+                "Comment Ordered",
+                init,
+                move |input, output, notificator| {
+                    let _timer = batch_time.start_timer();
 
-                                            // Push self onto parent chain if responsible
-                                            if comment.id % peers as i32 == idx as i32 {
-                                                vec.push(comment);
-                                            }
+                    input.for_each(|cap, data| {
+                        data.swap(&mut vec);
 
-                                            // Push children onto parent chain
-                                            if let Some(records) = children {
-                                                vec.extend(records);
-                                            }
+                        let mut ready = Vec::new();
+                        let mut failed = Vec::new();
+                        vec.drain(..).for_each(|event| match event {
+                            CommentOrderEvent::Post(post) => {
+                                store.observe_post(&post);
+                                if let Some(records) = pending.remove(&post.id) {
+                                    ready.extend(records);
+                                }
+                            }
+                            CommentOrderEvent::Comment(comment) => {
+                                store.observe_comment(&comment);
+                                match comment.available_parent(&*store) {
+                                    // Known post, release self and children
+                                    Ok(Some(Either::Left(_))) => ready.push(comment),
+                                    // Failed lookup, report once and drop the comment
+                                    Err(error) => {
+                                        if responsible(&comment) {
+                                            failed.push(FailedRecord::new(&comment, error));
                                         }
-                                        // No existing parent
-                                        None => {
-                                            // See if parent should be post or comment
-                                            match comment.parent_id() {
-                                                // Post, insert into pending
-                                                Either::Left(parent_id) => {
-                                                    if comment.id % peers as i32 == idx as i32 {
-                                                        pending
-                                                            .entry(parent_id)
-                                                            .or_default()
-                                                            .push(comment);
-                                                    }
-                                                }
-                                                // Comment, insert into pending_children
-                                                Either::Right(parent_id) => {
-                                                    if comment.id % peers as i32 == idx as i32 {
-                                                        pending_children
-                                                            .entry(parent_id)
-                                                            .or_default()
-                                                            .push(comment);
-                                                    }
-                                                }
+                                    }
+                                    // Known ancestor or no existing parent, wait for direct parent
+                                    Ok(Some(Either::Right(_))) | Ok(None) => {
+                                        match comment.parent_id() {
+                                            Either::Left(parent_id) => {
+                                                pending.entry(parent_id).or_default().push(comment)
                                             }
+                                            Either::Right(parent_id) => pending_children
+                                                .entry(parent_id)
+                                                .or_default()
+                                                .push(comment),
                                         }
                                     }
                                 }
                             }
                         });
+
+                        // Release all descendants of released comments
+                        let mut session = output.session(&cap);
+                        while let Some(comment) = ready.pop() {
+                            if let Some(children) = pending_children.remove(&comment.id) {
+                                ready.extend(children);
+                            }
+
+                            let checkpoint = checkpoints.next(comment.timestamp() as u64);
+                            if checkpoint.map_or(false, |checkpoint| checkpoint < *cap.time()) {
+                                released.push((*cap.time(), comment.clone()));
+                            }
+
+                            if responsible(&comment) {
+                                session.give(Ok(comment));
+                            }
+                        }
+                        session.give_iterator(failed.drain(..).map(Err));
+
+                        if let Some(checkpoint) = checkpoints.next(*cap.time()) {
+                            notificator.notify_at(cap.delayed(&checkpoint));
+                        }
                     });
-                }
-            })
+
+                    notificator.for_each(|cap, _, notificator| {
+                        let time = *cap.time();
+
+                        let mut snapshot_pending = pending_at(&pending, time);
+                        let mut snapshot_children = pending_at(&pending_children, time);
+                        for comment in released_at(&released, time) {
+                            let snapshot = match comment.parent_id() {
+                                Either::Left(parent_id) => snapshot_pending.entry(parent_id),
+                                Either::Right(parent_id) => snapshot_children.entry(parent_id),
+                            };
+                            snapshot.or_default().push(comment.clone());
+                        }
+                        released.retain(|(released_time, _)| *released_time > time);
+
+                        if !snapshot_pending.is_empty() || !snapshot_children.is_empty() {
+                            checkpoints.save(
+                                "comment-ordered",
+                                idx,
+                                time,
+                                &(snapshot_pending, snapshot_children),
+                            );
+                        }
+
+                        // Pending comments are part of every following snapshot
+                        let waiting = !pending.is_empty() || !pending_children.is_empty();
+                        if waiting && !notificator.frontier(0).is_empty() {
+                            if let Some(checkpoint) = checkpoints.next(time + 1) {
+                                notificator.notify_at(cap.delayed(&checkpoint));
+                            }
+                        }
+                    });
+
+                    let count = pending
+                        .values()
+                        .chain(pending_children.values())
+                        .map(Vec::len);
+                    pending_records.set(count.sum::<usize>() as i64);
+                },
+            )
+            .split_errors()
     }
 }
 
@@ -152,41 +233,102 @@ where
     fn ordered(
         &self,
         idx: usize,
-        peers: usize,
-        pool: Arc<Pool<ConnectionManager<PgConnection>>>,
+        _peers: usize,
+        store: Arc<dyn SocialGraphStore>,
         dependency: &Stream<G, PostRecord>,
-    ) -> Stream<G, LikeRecord> {
+        checkpoints: &Checkpoints,
+    ) -> (Stream<G, LikeRecord>, Stream<G, FailedRecord>) {
         // ! Broadcast before map to prevent timely panic
         let posts = dependency.broadcast().map(LikeOrderEvent::Post);
         let likes = self.map(LikeOrderEvent::Like);
 
+        let checkpoints = checkpoints.clone();
+
+        // Post Id => Likes
+        let mut pending: HashMap<i32, Vec<LikeRecord>> =
+            checkpoints.load("like-ordered", idx).unwrap_or_default();
+        // Release Time => Likes, for likes released after one of their checkpoints
+        let mut released: Vec<(u64, LikeRecord)> = Vec::new();
+
+        let init = checkpoints
+            .restored()
+            .filter(|_| !pending.is_empty())
+            .and_then(|time| checkpoints.next(time + 1));
+
+        let batch_time = batch_time("Like Ordered");
+        let pending_records =
+            PENDING_RECORDS.with_label_values(&["Like Ordered", &idx.to_string()]);
+
+        let mut vec = Vec::new();
         posts
             .concat(&likes)
-            .unary(Pipeline, "Like Ordered", move |cap, _| {
-                let mut pending: HashMap<i32, Vec<LikeRecord>> = HashMap::new();
+            .unary_notify(
+                Pipeline,
+                "Like Ordered",
+                init,
+                move |input, output, notificator| {
+                    let _timer = batch_time.start_timer();
 
-                move |input, output| {
-                    let connection = pool.get().unwrap();
-                    let mut vec = Vec::new();
                     input.for_each(|cap, data| {
                         data.swap(&mut vec);
 
                         vec.drain(..).for_each(|event| match event {
                             LikeOrderEvent::Post(post) => {
-                                if let Some(mut records) = pending.remove(&post.id) {
-                                    output.session(&cap).give_vec(&mut records);
-                                }
-                            }
-                            LikeOrderEvent::Like(like) => {
-                                if like.root(&connection).is_some() {
-                                    output.session(&cap).give(like);
-                                } else {
-                                    pending.entry(like.post_id).or_default().push(like);
+                                store.observe_post(&post);
+                                if let Some(records) = pending.remove(&post.id) {
+                                    for like in &records {
+                                        let checkpoint = checkpoints.next(like.timestamp() as u64);
+                                        if checkpoint
+                                            .map_or(false, |checkpoint| checkpoint < *cap.time())
+                                        {
+                                            released.push((*cap.time(), like.clone()));
+                                        }
+                                    }
+
+                                    output
+                                        .session(&cap)
+                                        .give_iterator(records.into_iter().map(Ok));
                                 }
                             }
+                            LikeOrderEvent::Like(like) => match like.root(&*store) {
+                                Ok(Some(_)) => output.session(&cap).give(Ok(like)),
+                                Ok(None) => pending.entry(like.post_id).or_default().push(like),
+                                Err(error) => output
+                                    .session(&cap)
+                                    .give(Err(FailedRecord::new(&like, error))),
+                            },
                         });
+
+                        if let Some(checkpoint) = checkpoints.next(*cap.time()) {
+                            notificator.notify_at(cap.delayed(&checkpoint));
+                        }
                     });
-                }
-            })
+
+                    notificator.for_each(|cap, _, notificator| {
+                        let time = *cap.time();
+
+                        let mut snapshot = pending_at(&pending, time);
+                        for like in released_at(&released, time) {
+                            snapshot.entry(like.post_id).or_default().push(like.clone());
+                        }
+                        released.retain(|(released_time, _)| *released_time > time);
+
+                        if !snapshot.is_empty() {
+                            checkpoints.save("like-ordered", idx, time, &snapshot);
+                        }
+
+                        // Pending likes are part of every following snapshot
+                        if !pending.is_empty() && !notificator.frontier(0).is_empty() {
+                            if let Some(checkpoint) = checkpoints.next(time + 1) {
+                                notificator.notify_at(cap.delayed(&checkpoint));
+                            }
+                        }
+                    });
+
+                    let count = pending.values().map(Vec::len).sum::<usize>();
+                    pending_records.set(count as i64);
+                },
+            )
+            .split_errors()
     }
 }
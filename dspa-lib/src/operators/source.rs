@@ -1,124 +1,233 @@
-use bincode::deserialize;
+use either::Either;
 use timely::dataflow::operators::generic::operator::{empty, source};
 use timely::dataflow::operators::{Map, Partition};
 use timely::dataflow::{Scope, Stream};
-use zmq::{Context, SocketType};
+use zmq::Context;
 
+use crate::operators::frontier_passed;
 use crate::{
+    deadletter::{DeadLetter, DeadLetterArgs},
+    envelope::{Envelope, Sequences},
+    error::{FailedRecord, SplitErrors},
+    latency::{wall_clock, Stamp},
+    metrics::{discard_reason, observe_frontier, DISCARDED_EVENTS, LATE_EVENTS, RECORDS_RECEIVED},
+    partition::{split_topic, worker_partitions, Watermarks},
     records::{CommentRecord, LikeRecord, PostRecord},
-    StreamEvent, Topic, DATA_SOCKET, MAX_DELAY, SOCKET_TIMEOUT,
+    shutdown,
+    subscription::{subscriber_name, SubscriptionArgs},
+    Control, StreamEvent, Topic, MAX_DELAY,
 };
 
+/// Receive the event streams from the broker. Every worker subscribes to its own partitions and
+/// advances its input frontier to the merged watermark of their producers, or to the latest event
+/// time minus `MAX_DELAY` if that is later. Undecodable messages are written to the dead-letter
+/// file of the worker. When resuming from a snapshot, events up to and including the snapshot
+/// time are discarded. Every received event is stamped with its wall-clock times on the stamp
+/// stream, see `TrackLatency`.
 pub fn streams<G>(
     scope: &G,
     idx: usize,
     ctx: &Context,
+    resume: Option<u64>,
+    partitions: usize,
+    subscription: &SubscriptionArgs,
+    deadletter: &DeadLetterArgs,
 ) -> (
     Stream<G, PostRecord>,
     Stream<G, CommentRecord>,
     Stream<G, LikeRecord>,
+    Stream<G, FailedRecord>,
+    Stream<G, Stamp>,
 )
 where
     G: Scope<Timestamp = u64>,
 {
-    if idx == 0 {
-        let events = source(scope, "Stream Source", |capability, info| {
-            let activator = scope.activator_for(&info.address[..]);
-            let mut cap = Some(capability);
-
-            let socket = ctx.socket(SocketType::SUB).unwrap();
-            socket.connect(&format!("ipc://{}", DATA_SOCKET)).unwrap();
-            socket
-                .set_subscribe(Topic::Post.to_string().as_bytes())
-                .unwrap();
-            socket
-                .set_subscribe(Topic::Comment.to_string().as_bytes())
-                .unwrap();
-            socket
-                .set_subscribe(Topic::Like.to_string().as_bytes())
-                .unwrap();
-            socket
-                .set_subscribe(Topic::EOS.to_string().as_bytes())
-                .unwrap();
-            socket.set_rcvtimeo(SOCKET_TIMEOUT).unwrap();
-
-            move |output| {
-                let mut done = false;
-                if let Some(cap) = cap.as_mut() {
-                    if let Ok(Ok(topic)) = socket.recv_bytes(0).map(String::from_utf8) {
-                        if topic == Topic::EOS.to_string() {
-                            done = true;
-                        } else {
-                            loop {
-                                if let Ok(data) = socket.recv_bytes(0) {
-                                    let event = match topic.as_str() {
-                                        "post" => StreamEvent::Post(deserialize(&data).unwrap()),
-                                        "comment" => {
-                                            StreamEvent::Comment(deserialize(&data).unwrap())
-                                        }
-                                        "like" => StreamEvent::Like(deserialize(&data).unwrap()),
-                                        _ => unreachable!(),
-                                    };
-
-                                    let event_time = event.timestamp() as u64;
-
-                                    if event_time >= *cap.time() {
-                                        let max_delay_time = event_time - MAX_DELAY;
-                                        if max_delay_time > *cap.time() {
-                                            // Downgrade shared timestamp
-                                            cap.downgrade(&max_delay_time);
-                                        }
-                                        // println!("INSERT {} record {{ time: {}, timestamp: {}, id: {:?} }}", topic, *cap.time(), event.timestamp(), event.id());
-
-                                        output.session(&cap.delayed(&event_time)).give(event);
-                                    } else {
-                                        // println!("DISCARD {} record {{ time: {}, timestamp: {}, id: {:?} }}", topic, *cap.time(), event.timestamp(), event.id());
+    let partitions = worker_partitions(idx, scope.peers(), partitions);
+    if partitions.is_empty() {
+        return (
+            empty(scope),
+            empty(scope),
+            empty(scope),
+            empty(scope),
+            empty(scope),
+        );
+    }
+
+    // Frontier metrics are tracked for the partitions of the first worker
+    let tracked = idx == 0;
+
+    let events = source(scope, "Stream Source", |mut capability, info| {
+        let activator = scope.activator_for(&info.address[..]);
+        if let Some(resume) = resume {
+            capability.downgrade(&(resume + 1));
+        }
+        let mut cap = Some(capability);
+
+        let mut topics = partitions
+            .iter()
+            .flat_map(|partition| {
+                [Topic::Post, Topic::Comment, Topic::Like, Topic::Watermark]
+                    .iter()
+                    .map(move |topic| topic.partition(*partition))
+            })
+            .collect::<Vec<_>>();
+        topics.push(Topic::EOS.to_string());
+        let mut subscriber = subscription.subscribe(ctx, idx, &topics);
+        let mut dead_letters = deadletter.dead_letters(&subscriber_name(idx));
+
+        let mut sequences = Sequences::default();
+        let mut watermarks = Watermarks::new(&partitions, subscription.producers);
+
+        move |output| {
+            let mut done = false;
+            if let Some(cap) = cap.as_mut() {
+                // The worker stepped the dataflow with the messages of earlier activations
+                subscriber.processed();
+
+                if let Some((topic, frames)) = subscriber.recv() {
+                    let (name, partition) = split_topic(&topic);
+                    if name == Topic::EOS.to_string() {
+                        done = true;
+                    } else if partition.map_or(false, |partition| partitions.contains(&partition)) {
+                        // Subscriptions match by prefix, e.g. `post.1` also matches `post.12`
+                        let partition = partition.unwrap();
+
+                        let decoded = Envelope::decode(&topic, &frames).and_then(|envelope| {
+                            if name == Topic::Watermark.to_string() {
+                                Ok((Either::Right(envelope.control_message(&topic)?), envelope))
+                            } else {
+                                Ok((Either::Left(envelope.event(&topic)?), envelope))
+                            }
+                        });
+                        match decoded {
+                            Ok((Either::Left(event), envelope)) => {
+                                // Gaps are reported, the message itself is still processed
+                                if let Err(error) = sequences.observe(&topic, &envelope) {
+                                    output
+                                        .session(&cap)
+                                        .give(Err(FailedRecord::new(&event, error)));
+                                }
+                                watermarks.producer(partition, &envelope.producer);
+
+                                let event_time = event.timestamp() as u64;
+                                RECORDS_RECEIVED.with_label_values(&[name]).inc();
+
+                                if event_time >= *cap.time() {
+                                    let max_delay_time = event_time.saturating_sub(MAX_DELAY);
+                                    if max_delay_time > *cap.time() {
+                                        // Downgrade shared timestamp
+                                        cap.downgrade(&max_delay_time);
                                     }
-                                    break;
+
+                                    if tracked {
+                                        observe_frontier(*cap.time(), event_time);
+                                    }
+                                    // println!("INSERT {} record {{ time: {}, timestamp: {}, id: {:?} }}", topic, *cap.time(), event.timestamp(), event.id());
+
+                                    let stamp = Stamp::new(envelope.emitted, wall_clock());
+                                    let delayed = cap.delayed(&event_time);
+                                    let mut session = output.session(&delayed);
+                                    session.give(Ok(Either::Left(event)));
+                                    session.give(Ok(Either::Right(stamp)));
+                                } else {
+                                    LATE_EVENTS.with_label_values(&[name]).inc();
+                                    // println!("DISCARD {} record {{ time: {}, timestamp: {}, id: {:?} }}", topic, *cap.time(), event.timestamp(), event.id());
+                                }
+                            }
+                            Ok((Either::Right(control), envelope)) => {
+                                if let Err(error) = sequences.observe(&topic, &envelope) {
+                                    eprintln!("{}", error);
+                                }
+                                if let Control::Watermark(time) = control {
+                                    watermarks.observe(partition, &envelope.producer, time);
+                                }
+
+                                // No more records before the watermark on any partition
+                                if let Some(watermark) = watermarks.watermark() {
+                                    if watermark > *cap.time() {
+                                        cap.downgrade(&watermark);
+                                    }
+                                }
+                            }
+                            Err(error) => {
+                                DISCARDED_EVENTS
+                                    .with_label_values(&[name, discard_reason(&error)])
+                                    .inc();
+                                let letter = DeadLetter::new(&topic, &error, &frames);
+                                if let Err(error) = dead_letters.write(&letter) {
+                                    eprintln!("Failed to write dead letter: {}", error);
                                 }
+                                // Undecodable messages carry no event time
+                                output
+                                    .session(&cap)
+                                    .give(Err(FailedRecord::new(&topic, error)));
                             }
                         }
                     }
                 }
+            }
 
-                if done {
-                    cap = None;
-                } else {
-                    activator.activate();
-                }
+            // A requested shutdown ends the stream, such that pending results are written
+            if shutdown::requested() {
+                done = true;
             }
-        });
-
-        let streams = events.partition(3, |event| match event {
-            StreamEvent::Post(_) => (0, event),
-            StreamEvent::Comment(_) => (1, event),
-            StreamEvent::Like(_) => (2, event),
-        });
-
-        (
-            streams[0].map(|event| {
-                if let StreamEvent::Post(record) = event {
-                    record
-                } else {
-                    unreachable!()
-                }
-            }),
-            streams[1].map(|event| {
-                if let StreamEvent::Comment(record) = event {
-                    record
-                } else {
-                    unreachable!()
-                }
-            }),
-            streams[2].map(|event| {
-                if let StreamEvent::Like(record) = event {
-                    record
-                } else {
-                    unreachable!()
-                }
-            }),
-        )
-    } else {
-        (empty(scope), empty(scope), empty(scope))
-    }
+
+            if done {
+                cap = None;
+            } else {
+                activator.activate();
+            }
+        }
+    });
+
+    let (events, failed) = events.split_errors();
+    let streams = events.partition(2, |event| match event {
+        Either::Left(_) => (0, event),
+        Either::Right(_) => (1, event),
+    });
+    let (posts, comments, likes) = split(&streams[0].flat_map(Either::left));
+    let stamps = frontier_passed(&streams[1].flat_map(Either::right));
+    (posts, comments, likes, failed, stamps)
+}
+
+/// Split a stream of events into its post, comment and like streams
+pub fn split<G>(
+    events: &Stream<G, StreamEvent>,
+) -> (
+    Stream<G, PostRecord>,
+    Stream<G, CommentRecord>,
+    Stream<G, LikeRecord>,
+)
+where
+    G: Scope<Timestamp = u64>,
+{
+    let streams = events.partition(3, |event| match event {
+        StreamEvent::Post(_) => (0, event),
+        StreamEvent::Comment(_) => (1, event),
+        StreamEvent::Like(_) => (2, event),
+    });
+
+    (
+        streams[0].map(|event| {
+            if let StreamEvent::Post(record) = event {
+                record
+            } else {
+                unreachable!()
+            }
+        }),
+        streams[1].map(|event| {
+            if let StreamEvent::Comment(record) = event {
+                record
+            } else {
+                unreachable!()
+            }
+        }),
+        streams[2].map(|event| {
+            if let StreamEvent::Like(record) = event {
+                record
+            } else {
+                unreachable!()
+            }
+        }),
+    )
 }
@@ -0,0 +1,45 @@
+use timely::dataflow::operators::Inspect;
+use timely::dataflow::{Scope, Stream};
+use timely::Data;
+
+use crate::metrics::{batch_time, RECORDS_EMITTED};
+use crate::sink::{ResultRow, Sink};
+
+pub trait WriteTo<G, D>
+where
+    G: Scope<Timestamp = u64>,
+    D: Data,
+{
+    /// Write the gathered records of every timestamp as rows to the given sink
+    fn write_to<R>(&self, sink: Box<dyn Sink<R>>) -> Stream<G, Vec<D>>
+    where
+        R: ResultRow<Record = D>;
+}
+
+impl<G, D> WriteTo<G, D> for Stream<G, Vec<D>>
+where
+    G: Scope<Timestamp = u64>,
+    D: Data,
+{
+    fn write_to<R>(&self, mut sink: Box<dyn Sink<R>>) -> Stream<G, Vec<D>>
+    where
+        R: ResultRow<Record = D>,
+    {
+        let batch_time = batch_time("Write");
+        let records_emitted = RECORDS_EMITTED.with_label_values(&[R::NAME]);
+
+        self.inspect_time(move |timestamp, records| {
+            let _timer = batch_time.start_timer();
+
+            let rows = records
+                .iter()
+                .flat_map(|record| R::rows(*timestamp, record))
+                .collect::<Vec<_>>();
+
+            match sink.write(*timestamp, &rows) {
+                Ok(()) => records_emitted.inc_by(rows.len() as i64),
+                Err(error) => eprintln!("Failed to write results of {}: {}", timestamp, error),
+            }
+        })
+    }
+}
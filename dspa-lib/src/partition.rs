@@ -0,0 +1,112 @@
+//! Key-partitioned topics.
+//!
+//! Records are published on `<topic>.<partition>`, posts and likes keyed by their post id and
+//! comments by the id of their root post. Every worker of a processor subscribes to the
+//! partitions `p` with `p % peers == idx`. Producers send their watermarks to every partition on
+//! `watermark.<partition>`, such that every worker can merge the watermarks of its partitions.
+
+use std::collections::{HashMap, HashSet};
+
+use structopt::StructOpt;
+
+use crate::Topic;
+
+/// Partitioning of the published topics, must be the same for `dspa-source` and the processors
+#[derive(Debug, StructOpt)]
+pub struct PartitionArgs {
+    #[structopt(long = "partitions", default_value = "1")]
+    /// Number of partitions of every topic
+    pub partitions: usize,
+}
+
+/// Partition of a record with the given key
+pub fn partition(key: i32, partitions: usize) -> usize {
+    (i64::from(key).rem_euclid(partitions as i64)) as usize
+}
+
+/// Partitions a worker subscribes to
+pub fn worker_partitions(idx: usize, peers: usize, partitions: usize) -> Vec<usize> {
+    (idx..partitions).step_by(peers).collect()
+}
+
+impl Topic {
+    /// Name of the topic on the given partition
+    pub fn partition(self, partition: usize) -> String {
+        format!("{}.{}", self.to_string(), partition)
+    }
+}
+
+/// Split a topic into its name and partition, topics without partition are returned unchanged
+pub fn split_topic(topic: &str) -> (&str, Option<usize>) {
+    match topic.rfind('.') {
+        Some(position) => match topic[position + 1..].parse() {
+            Ok(partition) => (&topic[..position], Some(partition)),
+            Err(_) => (topic, None),
+        },
+        None => (topic, None),
+    }
+}
+
+/// Watermarks of the producers publishing on a set of partitions
+#[derive(Debug, Default)]
+pub struct Watermarks {
+    // Partition => Producer => Watermark
+    partitions: HashMap<usize, HashMap<String, u64>>,
+    // Producers seen on any partition
+    producers: HashSet<String>,
+    // Number of producers publishing on every partition
+    expected: usize,
+}
+
+impl Watermarks {
+    pub fn new(partitions: &[usize], expected: usize) -> Self {
+        Watermarks {
+            partitions: partitions
+                .iter()
+                .map(|partition| (*partition, HashMap::new()))
+                .collect(),
+            producers: HashSet::new(),
+            expected,
+        }
+    }
+
+    /// Register a producer publishing on a partition, it holds back the merged watermark until
+    /// it sends a watermark itself
+    pub fn producer(&mut self, partition: usize, producer: &str) {
+        if let Some(producers) = self.partitions.get_mut(&partition) {
+            if !producers.contains_key(producer) {
+                producers.insert(producer.to_owned(), 0);
+            }
+            self.producers.insert(producer.to_owned());
+        }
+    }
+
+    /// Track the watermark of a producer on a partition, watermarks never regress
+    pub fn observe(&mut self, partition: usize, producer: &str, watermark: u64) {
+        if let Some(producers) = self.partitions.get_mut(&partition) {
+            let current = producers.entry(producer.to_owned()).or_insert(0);
+            *current = (*current).max(watermark);
+            self.producers.insert(producer.to_owned());
+        }
+    }
+
+    /// Time before which no more records are published on any of the partitions. Unknown until
+    /// the expected number of producers is known. Every producer publishes on every partition,
+    /// such that a producer holds back the partitions it has not reported on yet.
+    pub fn watermark(&self) -> Option<u64> {
+        if self.partitions.is_empty() || self.producers.len() < self.expected {
+            return None;
+        }
+
+        let mut merged = None;
+        for producers in self.partitions.values() {
+            let watermark = self
+                .producers
+                .iter()
+                .map(|producer| producers.get(producer).cloned().unwrap_or(0))
+                .min()?;
+            merged = Some(merged.map_or(watermark, |merged: u64| merged.min(watermark)));
+        }
+        merged
+    }
+}
@@ -2,13 +2,14 @@ use std::collections::HashMap;
 use std::convert::identity;
 
 use chrono::{DateTime, Duration, Utc};
-use diesel::prelude::*;
-use diesel::{Identifiable, Insertable, PgConnection};
+use diesel::{Identifiable, Insertable};
 use either::Either;
 use serde_derive::{Deserialize, Serialize};
 
+use crate::error::{DspaError, Result};
 use crate::records::{FilteredRecord, PostRecord, Record, StreamRecord, TableRecord};
-use crate::schema::{comment, post};
+use crate::schema::comment;
+use crate::store::SocialGraphStore;
 use crate::Topic;
 
 #[derive(Clone, Debug, Deserialize)]
@@ -47,54 +48,57 @@ pub struct CommentRecord {
 }
 
 impl CommentRecord {
-    pub fn root(&self, connection: &PgConnection) -> Option<PostRecord> {
-        match self.parent(connection) {
-            Some(Either::Left(post)) => Some(post),
-            Some(Either::Right(comment)) => comment.root(connection),
-            None => None,
+    pub fn root(&self, store: &dyn SocialGraphStore) -> Result<Option<PostRecord>> {
+        Ok(self.thread(store)?.map(|(post, _)| post))
+    }
+
+    /// Root post and depth of the comment in its thread, 1 for comments on the post
+    pub fn thread(&self, store: &dyn SocialGraphStore) -> Result<Option<(PostRecord, u32)>> {
+        match self.parent(store)? {
+            Some(Either::Left(post)) => Ok(Some((post, 1))),
+            Some(Either::Right(comment)) => Ok(comment
+                .thread(store)?
+                .map(|(post, depth)| (post, depth + 1))),
+            None => Ok(None),
         }
     }
 
-    pub fn parent(&self, connection: &PgConnection) -> Option<Either<PostRecord, CommentRecord>> {
+    pub fn parent(
+        &self,
+        store: &dyn SocialGraphStore,
+    ) -> Result<Option<Either<PostRecord, CommentRecord>>> {
         if let Some(comment_id) = self.reply_to_comment_id {
-            comment::table
-                .filter(comment::id.eq(&comment_id))
-                .first::<CommentRecord>(connection)
-                .ok()
-                .map(|comment| Either::Right(comment))
+            Ok(store.comment(comment_id)?.map(Either::Right))
         } else if let Some(post_id) = self.reply_to_post_id {
-            post::table
-                .filter(post::id.eq(&post_id))
-                .first::<PostRecord>(connection)
-                .ok()
-                .map(|post| Either::Left(post))
+            Ok(store.post(post_id)?.map(Either::Left))
         } else {
-            // Comments must have a parent
-            unreachable!();
+            Err(DspaError::MissingParent {
+                comment_id: self.id,
+            })
         }
     }
 
     pub fn available_parent(
         &self,
-        connection: &PgConnection,
-    ) -> Option<Either<PostRecord, CommentRecord>> {
+        store: &dyn SocialGraphStore,
+    ) -> Result<Option<Either<PostRecord, CommentRecord>>> {
         // Check parent
-        if let Some(parent) = self.parent(connection) {
+        if let Some(parent) = self.parent(store)? {
             match parent {
                 // If post, return
-                Either::Left(post) => Some(Either::Left(post)),
+                Either::Left(post) => Ok(Some(Either::Left(post))),
                 // If comment, try get ancestor
                 Either::Right(comment) => {
-                    match comment.available_parent(connection) {
+                    match comment.available_parent(store)? {
                         // If ancestor, return ancestor
-                        Some(ancestor) => Some(ancestor),
+                        Some(ancestor) => Ok(Some(ancestor)),
                         // Else, return self
-                        None => Some(Either::Right(comment)),
+                        None => Ok(Some(Either::Right(comment))),
                     }
                 }
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -109,12 +113,16 @@ impl CommentRecord {
         }
     }
 
-    pub fn hashmap(&self, connection: &PgConnection) -> HashMap<&'static str, i32> {
+    pub fn hashmap(&self, store: &dyn SocialGraphStore) -> Result<HashMap<&'static str, i32>> {
+        let root = self.root(store)?.ok_or(DspaError::MissingParent {
+            comment_id: self.id,
+        })?;
+
         let mut out: HashMap<&str, i32> = HashMap::new();
         out.insert("person_id", self.person_id);
-        out.insert("post_id", self.root(connection).expect("autismo").id);
+        out.insert("post_id", root.id);
         out.insert("place_id", self.place_id);
-        out
+        Ok(out)
     }
 }
 
@@ -155,7 +163,7 @@ impl StreamRecord for CommentRecord {
         self.creation_date = self.creation_date + Duration::seconds(seconds);
     }
 
-    fn ordered(&self, connection: &PgConnection) -> bool {
-        self.root(connection).is_some()
+    fn ordered(&self, store: &dyn SocialGraphStore) -> Result<bool> {
+        Ok(self.root(store)?.is_some())
     }
 }
@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 
 use chrono::{DateTime, Duration, Utc};
-use diesel::prelude::*;
-use diesel::{Identifiable, Insertable, PgConnection};
+use diesel::{Identifiable, Insertable};
 use serde_derive::{Deserialize, Serialize};
 
+use crate::error::Result;
 use crate::records::{PostRecord, Record, StreamRecord, TableRecord};
-use crate::schema::{like_ as like, post};
+use crate::schema::like_ as like;
+use crate::store::SocialGraphStore;
 use crate::Topic;
 
 #[derive(
@@ -25,11 +26,8 @@ pub struct LikeRecord {
 }
 
 impl LikeRecord {
-    pub fn root(&self, connection: &PgConnection) -> Option<PostRecord> {
-        post::table
-            .filter(post::id.eq(&self.post_id))
-            .first::<PostRecord>(connection)
-            .ok()
+    pub fn root(&self, store: &dyn SocialGraphStore) -> Result<Option<PostRecord>> {
+        store.post(self.post_id)
     }
 
     pub fn hashmap(&self) -> HashMap<&'static str, i32> {
@@ -67,10 +65,7 @@ impl StreamRecord for LikeRecord {
         self.creation_date = self.creation_date + Duration::seconds(seconds);
     }
 
-    fn ordered(&self, connection: &PgConnection) -> bool {
-        post::table
-            .filter(post::id.eq(self.post_id))
-            .first::<PostRecord>(connection)
-            .is_ok()
+    fn ordered(&self, store: &dyn SocialGraphStore) -> Result<bool> {
+        Ok(self.root(store)?.is_some())
     }
 }
@@ -7,11 +7,13 @@ use diesel::query_builder::IntoUpdateTarget;
 use diesel::query_builder::QueryFragment;
 use diesel::query_dsl::methods::ExecuteDsl;
 use diesel::query_dsl::RunQueryDsl;
-use diesel::{Insertable, PgConnection, Table};
+use diesel::{Insertable, Table};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use timely::ExchangeData;
 
+use crate::error::Result;
+use crate::store::SocialGraphStore;
 use crate::Topic;
 
 mod comment;
@@ -71,5 +73,5 @@ where
     fn timestamp(&self) -> i64;
     fn add_timestamp(&mut self, value: i64);
 
-    fn ordered(&self, connection: &PgConnection) -> bool;
+    fn ordered(&self, store: &dyn SocialGraphStore) -> Result<bool>;
 }
@@ -2,14 +2,16 @@ use std::collections::HashMap;
 use std::fmt;
 
 use chrono::{DateTime, Duration, Utc};
-use diesel::{Identifiable, Insertable, PgConnection};
+use diesel::{Identifiable, Insertable};
 use regex::Regex;
 use serde::de::{self, Visitor};
 use serde::{Deserializer, Serializer};
 use serde_derive::{Deserialize, Serialize};
 
+use crate::error;
 use crate::records::{Record, StreamRecord, TableRecord};
 use crate::schema::post;
+use crate::store::SocialGraphStore;
 use crate::Topic;
 
 mod tags {
@@ -129,7 +131,7 @@ impl StreamRecord for PostRecord {
         self.creation_date = self.creation_date + Duration::seconds(seconds);
     }
 
-    fn ordered(&self, connection: &PgConnection) -> bool {
-        true
+    fn ordered(&self, _store: &dyn SocialGraphStore) -> error::Result<bool> {
+        Ok(true)
     }
 }
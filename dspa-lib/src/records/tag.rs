@@ -4,13 +4,13 @@ use serde_derive::{Deserialize, Serialize};
 use crate::records::{Record, TableRecord};
 use crate::schema::{tag, tag_has_type};
 
-#[derive(Clone, Debug, Serialize, Deserialize, Insertable)]
+#[derive(Clone, Debug, Serialize, Deserialize, Insertable, Queryable)]
 #[table_name = "tag_has_type"]
 pub struct TagHasTypeRecord {
     #[serde(rename = "Tag.id")]
-    tag_id: i32,
+    pub tag_id: i32,
     #[serde(rename = "TagClass.id")]
-    tag_class_id: i32,
+    pub tag_class_id: i32,
 }
 
 impl Record for TagHasTypeRecord {
@@ -25,12 +25,12 @@ impl TableRecord for TagHasTypeRecord {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Insertable)]
+#[derive(Clone, Debug, Serialize, Deserialize, Insertable, Queryable)]
 #[table_name = "tag"]
 pub struct TagRecord {
-    id: i32,
-    name: String,
-    url: String,
+    pub id: i32,
+    pub name: String,
+    pub url: String,
 }
 
 impl Record for TagRecord {
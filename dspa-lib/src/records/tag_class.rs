@@ -4,13 +4,13 @@ use serde_derive::{Deserialize, Serialize};
 use crate::records::{Record, TableRecord};
 use crate::schema::{tag_class, tag_class_is_subclass_of};
 
-#[derive(Clone, Debug, Serialize, Deserialize, Insertable)]
+#[derive(Clone, Debug, Serialize, Deserialize, Insertable, Queryable)]
 #[table_name = "tag_class_is_subclass_of"]
 pub struct TagClassIsSubclassOfRecord {
     #[serde(rename = "TagClass.id")]
-    tag_class_id: i32,
+    pub tag_class_id: i32,
     #[serde(rename = "Parent.id")]
-    parent_id: i32,
+    pub parent_id: i32,
 }
 
 impl Record for TagClassIsSubclassOfRecord {
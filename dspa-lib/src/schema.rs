@@ -1,3 +1,13 @@
+table! {
+    anomaly (id) {
+        id -> Int4,
+        timestamp -> Timestamptz,
+        person_id -> Int4,
+        cause -> Varchar,
+        deviation -> Float4,
+    }
+}
+
 table! {
     comment (id) {
         id -> Int4,
@@ -164,6 +174,55 @@ table! {
     }
 }
 
+table! {
+    post_stats (timestamp, post_id) {
+        timestamp -> Timestamptz,
+        post_id -> Int4,
+        users -> Int4,
+        comments -> Int8,
+        likes -> Int8,
+        replies -> Int8,
+        max_reply_depth -> Int4,
+        mean_reply_depth -> Float8,
+    }
+}
+
+table! {
+    post_stats_change (timestamp, post_id) {
+        timestamp -> Timestamptz,
+        post_id -> Int4,
+        change -> Text,
+        users -> Int4,
+        comments -> Int8,
+        likes -> Int8,
+        replies -> Int8,
+        max_reply_depth -> Int4,
+        mean_reply_depth -> Float8,
+    }
+}
+
+table! {
+    recommendation (timestamp, person_id, rank) {
+        timestamp -> Timestamptz,
+        person_id -> Int4,
+        rank -> Int4,
+        recommended_id -> Int4,
+    }
+}
+
+table! {
+    rollup (timestamp, kind, group_id) {
+        timestamp -> Timestamptz,
+        kind -> Varchar,
+        group_id -> Int4,
+        posts -> Int8,
+        users -> Int4,
+        comments -> Int8,
+        replies -> Int8,
+        likes -> Int8,
+    }
+}
+
 table! {
     tag (id) {
         id -> Int4,
@@ -194,6 +253,32 @@ table! {
     }
 }
 
+table! {
+    top_post (timestamp, rank) {
+        timestamp -> Timestamptz,
+        rank -> Int4,
+        post_id -> Int4,
+        score -> Float8,
+        users -> Int4,
+        comments -> Int8,
+        replies -> Int8,
+        likes -> Int8,
+    }
+}
+
+table! {
+    trending_tag (timestamp, rank) {
+        timestamp -> Timestamptz,
+        rank -> Int4,
+        tag_id -> Int4,
+        name -> Nullable<Varchar>,
+        score -> Float8,
+        posts -> Int8,
+        engagements -> Int8,
+        baseline -> Float8,
+    }
+}
+
 joinable!(comment -> person (person_id));
 joinable!(comment -> place (place_id));
 joinable!(forum_has_member -> forum (forum_id));
@@ -222,6 +307,7 @@ joinable!(tag_has_type -> tag (tag_id));
 joinable!(tag_has_type -> tag_class (tag_class_id));
 
 allow_tables_to_appear_in_same_query!(
+    anomaly,
     comment,
     forum,
     forum_has_member,
@@ -241,8 +327,14 @@ allow_tables_to_appear_in_same_query!(
     place,
     place_is_part_of,
     post,
+    post_stats,
+    post_stats_change,
+    recommendation,
+    rollup,
     tag,
     tag_class,
     tag_class_is_subclass_of,
     tag_has_type,
+    top_post,
+    trending_tag,
 );
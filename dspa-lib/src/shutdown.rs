@@ -0,0 +1,31 @@
+//! Graceful shutdown on SIGINT and SIGTERM.
+//!
+//! The first signal requests a shutdown, which the binaries poll: sources stop reading as if the
+//! stream had ended, such that the dataflows drain and write their pending results. A second
+//! signal terminates the process immediately.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+lazy_static! {
+    static ref REQUESTED: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+}
+
+/// Request a shutdown on SIGINT and SIGTERM instead of terminating the process
+pub fn handle_signals() {
+    for signal in &[SIGINT, SIGTERM] {
+        // Terminate if a shutdown was already requested
+        flag::register_conditional_shutdown(*signal, 1, REQUESTED.clone())
+            .expect("Failed to register signal handler");
+        flag::register(*signal, REQUESTED.clone()).expect("Failed to register signal handler");
+    }
+}
+
+/// Whether a shutdown was requested
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
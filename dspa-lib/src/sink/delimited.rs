@@ -0,0 +1,41 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use csv::{Writer, WriterBuilder};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::sink::{writer, Sink};
+
+/// CSV output with a header row
+pub struct CsvSink {
+    path: Option<PathBuf>,
+    writer: Option<Writer<Box<dyn Write>>>,
+}
+
+impl CsvSink {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        CsvSink { path, writer: None }
+    }
+}
+
+impl<R: Serialize> Sink<R> for CsvSink {
+    fn write(&mut self, _timestamp: u64, rows: &[R]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        // Opened on first write, such that only workers receiving results create the file
+        if self.writer.is_none() {
+            let writer = writer(self.path.as_ref().map(PathBuf::as_path))?;
+            self.writer = Some(WriterBuilder::new().has_headers(true).from_writer(writer));
+        }
+        let writer = self.writer.as_mut().unwrap();
+
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::sink::{writer, Sink};
+
+/// JSON Lines output, one object per row
+pub struct JsonSink {
+    path: Option<PathBuf>,
+    writer: Option<Box<dyn Write>>,
+}
+
+impl JsonSink {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        JsonSink { path, writer: None }
+    }
+}
+
+impl<R: Serialize> Sink<R> for JsonSink {
+    fn write(&mut self, _timestamp: u64, rows: &[R]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        // Opened on first write, such that only workers receiving results create the file
+        if self.writer.is_none() {
+            self.writer = Some(writer(self.path.as_ref().map(PathBuf::as_path))?);
+        }
+        let writer = self.writer.as_mut().unwrap();
+
+        for row in rows {
+            serde_json::to_writer(&mut *writer, row)?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use diesel::insertable::CanInsertInSingleQuery;
+use diesel::pg::Pg;
+use diesel::query_builder::QueryFragment;
+use diesel::{Insertable, QuerySource, Table};
+use serde::Serialize;
+use structopt::clap::{Error as UsageError, ErrorKind};
+use structopt::StructOpt;
+
+use crate::error::Result;
+use crate::DATABASE_URL;
+
+mod delimited;
+mod json;
+mod postgres;
+mod text;
+
+pub use delimited::*;
+pub use json::*;
+pub use postgres::*;
+pub use text::*;
+
+/// Row of a result table, created from the records a processor outputs for a timestamp
+pub trait ResultRow
+where
+    Self: Serialize + fmt::Display + Clone + 'static,
+{
+    type Record;
+    type Table: Table + Copy;
+
+    /// Name of the result table, used to label the emitted rows
+    const NAME: &'static str;
+
+    fn rows(timestamp: u64, record: &Self::Record) -> Vec<Self>;
+
+    fn table() -> Self::Table;
+}
+
+/// Destination for the results of a processor
+pub trait Sink<R> {
+    /// Write the rows of a completed timestamp
+    fn write(&mut self, timestamp: u64, rows: &[R]) -> Result<()>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Postgres,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "postgres" => Ok(OutputFormat::Postgres),
+            _ => Err(format!("Unknown output format: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SinkArgs {
+    #[structopt(long = "output", default_value = "text")]
+    /// Format of the results: text, json (JSON Lines), csv or postgres (result tables)
+    pub output: OutputFormat,
+    #[structopt(long = "output-path", parse(from_os_str))]
+    /// File to write text, json or csv results to instead of stdout
+    pub output_path: Option<PathBuf>,
+}
+
+impl SinkArgs {
+    pub fn sink<R>(&self) -> Box<dyn Sink<R>>
+    where
+        R: ResultRow + Insertable<<R as ResultRow>::Table>,
+        <<R as ResultRow>::Table as QuerySource>::FromClause: QueryFragment<Pg>,
+        <R as Insertable<<R as ResultRow>::Table>>::Values:
+            QueryFragment<Pg> + CanInsertInSingleQuery<Pg>,
+    {
+        self.sink_at(self.output_path.clone())
+    }
+
+    /// Sink in the configured format writing to the given file, or to stdout if there is none
+    pub fn sink_at<R>(&self, path: Option<PathBuf>) -> Box<dyn Sink<R>>
+    where
+        R: ResultRow + Insertable<<R as ResultRow>::Table>,
+        <<R as ResultRow>::Table as QuerySource>::FromClause: QueryFragment<Pg>,
+        <R as Insertable<<R as ResultRow>::Table>>::Values:
+            QueryFragment<Pg> + CanInsertInSingleQuery<Pg>,
+    {
+        match self.output {
+            OutputFormat::Text => Box::new(TextSink::new(path)),
+            OutputFormat::Json => Box::new(JsonSink::new(path)),
+            OutputFormat::Csv => Box::new(CsvSink::new(path)),
+            OutputFormat::Postgres => Box::new(PostgresSink::new(DATABASE_URL)),
+        }
+    }
+
+    /// File of an additional output with its own rows: the given path, or `<output-path>.<name>`.
+    /// Text results may share stdout with the main output, but json or csv rows of another
+    /// schema would be mixed with the main rows, which is a usage error.
+    pub fn additional_path(
+        &self,
+        name: &str,
+        path: Option<&PathBuf>,
+    ) -> std::result::Result<Option<PathBuf>, UsageError> {
+        let invalid = |message: String| {
+            Err(UsageError::with_description(
+                &message,
+                ErrorKind::ArgumentConflict,
+            ))
+        };
+
+        match (path, &self.output_path) {
+            _ if self.output == OutputFormat::Postgres => Ok(None),
+            (Some(path), Some(output_path)) if path == output_path => invalid(format!(
+                "The {} results must be written to another file than --output-path",
+                name
+            )),
+            (Some(path), _) => Ok(Some(path.clone())),
+            (None, Some(output_path)) => {
+                let mut path = output_path.clone().into_os_string();
+                path.push(format!(".{}", name));
+                Ok(Some(path.into()))
+            }
+            (None, None) if self.output == OutputFormat::Text => Ok(None),
+            (None, None) => invalid(format!(
+                "The {} results cannot be written to stdout together with the other results, \
+                 pass --output-path or --{}-path",
+                name, name
+            )),
+        }
+    }
+}
+
+/// Open the given file, or stdout if there is none
+fn writer(path: Option<&Path>) -> Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    })
+}
@@ -0,0 +1,62 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use diesel::insertable::CanInsertInSingleQuery;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::query_builder::QueryFragment;
+use diesel::r2d2::ConnectionManager;
+use diesel::{Insertable, PgConnection, QuerySource};
+use r2d2::Pool;
+
+use crate::error::Result;
+use crate::sink::{ResultRow, Sink};
+use crate::store::retry;
+use crate::DATABASE_TIMEOUT;
+
+/// Inserts rows into the result table of the row type, see the migrations
+pub struct PostgresSink<R> {
+    pool: Pool<ConnectionManager<PgConnection>>,
+    rows: PhantomData<R>,
+}
+
+impl<R> PostgresSink<R> {
+    pub fn new(url: &str) -> Self {
+        PostgresSink {
+            pool: Pool::builder()
+                .max_size(1)
+                .connection_timeout(Duration::from_millis(DATABASE_TIMEOUT))
+                .build_unchecked(ConnectionManager::<PgConnection>::new(url)),
+            rows: PhantomData,
+        }
+    }
+}
+
+impl<R> Sink<R> for PostgresSink<R>
+where
+    R: ResultRow + Insertable<<R as ResultRow>::Table>,
+    <<R as ResultRow>::Table as QuerySource>::FromClause: QueryFragment<Pg>,
+    <R as Insertable<<R as ResultRow>::Table>>::Values:
+        QueryFragment<Pg> + CanInsertInSingleQuery<Pg>,
+{
+    fn write(&mut self, _timestamp: u64, rows: &[R]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        retry("write_results", || {
+            let connection = self.pool.get()?;
+
+            // All rows of a timestamp are committed together
+            connection.transaction::<_, diesel::result::Error, _>(|| {
+                for row in rows {
+                    diesel::insert_into(R::table())
+                        .values(row.clone())
+                        .execute(&connection)?;
+                }
+                Ok(())
+            })?;
+            Ok(())
+        })
+    }
+}
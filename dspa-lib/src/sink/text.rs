@@ -0,0 +1,46 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{TimeZone, Utc};
+
+use crate::error::Result;
+use crate::sink::{writer, Sink};
+
+/// Human readable output, one block per timestamp
+pub struct TextSink {
+    path: Option<PathBuf>,
+    writer: Option<Box<dyn Write>>,
+}
+
+impl TextSink {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        TextSink { path, writer: None }
+    }
+}
+
+impl<R: Display> Sink<R> for TextSink {
+    fn write(&mut self, timestamp: u64, rows: &[R]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        // Opened on first write, such that only workers receiving results create the file
+        if self.writer.is_none() {
+            self.writer = Some(writer(self.path.as_ref().map(PathBuf::as_path))?);
+        }
+        let writer = self.writer.as_mut().unwrap();
+
+        writeln!(
+            writer,
+            "{} - {}",
+            Utc.timestamp(timestamp as i64, 0).format("%D - %r"),
+            timestamp
+        )?;
+        for row in rows {
+            writeln!(writer, "\t{}", row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use csv::ReaderBuilder;
+
+use crate::error::Result;
+use crate::records::{
+    CommentRecord, LikeRecord, PersonKnowsRecord, PostRecord, Record, TagClassIsSubclassOfRecord,
+    TagHasTypeRecord, TagRecord,
+};
+use crate::store::SocialGraphStore;
+
+fn records<D>(path: &Path) -> impl Iterator<Item = D>
+where
+    D: Record,
+{
+    ReaderBuilder::new()
+        .delimiter(b'|')
+        .has_headers(true)
+        .from_path(path.join(D::FILENAME))
+        .expect("Failed to open file")
+        .into_deserialize::<D>()
+        .map(std::result::Result::unwrap)
+}
+
+/// Store that keeps the social graph in memory, stream records are added as they are observed
+#[derive(Default)]
+pub struct MemoryStore {
+    posts: RwLock<HashMap<i32, PostRecord>>,
+    comments: RwLock<HashMap<i32, CommentRecord>>,
+    likes: RwLock<Vec<LikeRecord>>,
+    // Person Id => Acquaintance Ids
+    knows: HashMap<i32, Vec<i32>>,
+    // Tag Id => Tag Name
+    tags: HashMap<i32, String>,
+    // Tag Id => Tag Class Ids
+    tag_classes: HashMap<i32, Vec<i32>>,
+    // Tag Class Id => Parent Class Ids
+    parent_classes: HashMap<i32, Vec<i32>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Load the static tables from a directory in the format expected by `dspa-source --tables`
+    pub fn from_tables(path: &Path) -> Self {
+        let mut store = MemoryStore::new();
+        records::<PersonKnowsRecord>(path).for_each(|record| store.insert_knows(record));
+        records::<TagRecord>(path).for_each(|record| store.insert_tag(record));
+        records::<TagHasTypeRecord>(path).for_each(|record| store.insert_tag_type(record));
+        records::<TagClassIsSubclassOfRecord>(path)
+            .for_each(|record| store.insert_subclass(record));
+        store
+    }
+
+    pub fn insert_knows(&mut self, record: PersonKnowsRecord) {
+        self.knows
+            .entry(record.person_id)
+            .or_default()
+            .push(record.acquaintance_id);
+    }
+
+    pub fn insert_tag(&mut self, record: TagRecord) {
+        self.tags.insert(record.id, record.name);
+    }
+
+    pub fn insert_tag_type(&mut self, record: TagHasTypeRecord) {
+        self.tag_classes
+            .entry(record.tag_id)
+            .or_default()
+            .push(record.tag_class_id);
+    }
+
+    pub fn insert_subclass(&mut self, record: TagClassIsSubclassOfRecord) {
+        self.parent_classes
+            .entry(record.tag_class_id)
+            .or_default()
+            .push(record.parent_id);
+    }
+
+    pub fn insert_post(&self, record: PostRecord) {
+        self.posts.write().unwrap().insert(record.id, record);
+    }
+
+    pub fn insert_comment(&self, record: CommentRecord) {
+        self.comments.write().unwrap().insert(record.id, record);
+    }
+
+    pub fn insert_like(&self, record: LikeRecord) {
+        self.likes.write().unwrap().push(record);
+    }
+}
+
+impl SocialGraphStore for MemoryStore {
+    fn post(&self, id: i32) -> Result<Option<PostRecord>> {
+        Ok(self.posts.read().unwrap().get(&id).cloned())
+    }
+
+    fn comment(&self, id: i32) -> Result<Option<CommentRecord>> {
+        Ok(self.comments.read().unwrap().get(&id).cloned())
+    }
+
+    fn comments_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<CommentRecord>> {
+        Ok(self
+            .comments
+            .read()
+            .unwrap()
+            .values()
+            .filter(|comment| comment.creation_date >= from && comment.creation_date < to)
+            .cloned()
+            .collect())
+    }
+
+    fn likes_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<LikeRecord>> {
+        Ok(self
+            .likes
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|like| like.creation_date >= from && like.creation_date < to)
+            .cloned()
+            .collect())
+    }
+
+    fn acquaintances(&self, person_id: i32) -> Result<Vec<i32>> {
+        Ok(self.knows.get(&person_id).cloned().unwrap_or_default())
+    }
+
+    fn tag_name(&self, tag_id: i32) -> Result<Option<String>> {
+        Ok(self.tags.get(&tag_id).cloned())
+    }
+
+    fn tag_classes(&self, tag_id: i32) -> Result<Vec<i32>> {
+        Ok(self.tag_classes.get(&tag_id).cloned().unwrap_or_default())
+    }
+
+    fn parent_classes(&self, tag_class_id: i32) -> Result<Vec<i32>> {
+        Ok(self
+            .parent_classes
+            .get(&tag_class_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn observe_post(&self, record: &PostRecord) {
+        self.insert_post(record.clone());
+    }
+
+    fn observe_comment(&self, record: &CommentRecord) {
+        self.insert_comment(record.clone());
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use structopt::StructOpt;
+
+use crate::error::Result;
+use crate::records::{CommentRecord, LikeRecord, PostRecord};
+use crate::DATABASE_URL;
+
+mod memory;
+mod postgres;
+
+pub use memory::*;
+pub use postgres::*;
+
+/// Read access to the social graph that stream records refer to
+pub trait SocialGraphStore
+where
+    Self: Send + Sync,
+{
+    fn post(&self, id: i32) -> Result<Option<PostRecord>>;
+    fn comment(&self, id: i32) -> Result<Option<CommentRecord>>;
+    fn acquaintances(&self, person_id: i32) -> Result<Vec<i32>>;
+
+    /// Comments with the given ids, missing ones are skipped
+    fn comments(&self, ids: &[i32]) -> Result<Vec<CommentRecord>> {
+        let mut comments = Vec::with_capacity(ids.len());
+        for id in ids {
+            comments.extend(self.comment(*id)?);
+        }
+        Ok(comments)
+    }
+
+    /// Comments created at or after `from` and before `to`. Stores without the stream history
+    /// return none.
+    fn comments_between(
+        &self,
+        _from: DateTime<Utc>,
+        _to: DateTime<Utc>,
+    ) -> Result<Vec<CommentRecord>> {
+        Ok(Vec::new())
+    }
+
+    /// Likes created at or after `from` and before `to`. Stores without the stream history
+    /// return none.
+    fn likes_between(&self, _from: DateTime<Utc>, _to: DateTime<Utc>) -> Result<Vec<LikeRecord>> {
+        Ok(Vec::new())
+    }
+
+    /// Name of a tag. Stores without the tags return none.
+    fn tag_name(&self, _tag_id: i32) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Tag classes the tag has the type of. Stores without the tag hierarchy return none.
+    fn tag_classes(&self, _tag_id: i32) -> Result<Vec<i32>> {
+        Ok(Vec::new())
+    }
+
+    /// Direct parent classes of a tag class. Stores without the tag hierarchy return none.
+    fn parent_classes(&self, _tag_class_id: i32) -> Result<Vec<i32>> {
+        Ok(Vec::new())
+    }
+
+    /// Called for every post a processor receives. Stores that are not populated by
+    /// `dspa-source` use this to learn about stream records.
+    fn observe_post(&self, _record: &PostRecord) {}
+
+    /// Called for every comment a processor receives. Stores that are not populated by
+    /// `dspa-source` use this to learn about stream records.
+    fn observe_comment(&self, _record: &CommentRecord) {}
+}
+
+#[derive(Debug, StructOpt)]
+pub struct StoreArgs {
+    #[structopt(long = "memory", parse(from_os_str))]
+    /// Use an in-memory store loaded from the given tables directory instead of PostgreSQL
+    pub memory: Option<PathBuf>,
+}
+
+impl StoreArgs {
+    pub fn store(&self) -> Arc<dyn SocialGraphStore> {
+        match &self.memory {
+            Some(path) => Arc::new(MemoryStore::from_tables(path)),
+            None => Arc::new(PostgresStore::new(DATABASE_URL)),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::thread::sleep;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::r2d2::ConnectionManager;
+use diesel::PgConnection;
+use r2d2::Pool;
+
+use crate::error::{DspaError, Result};
+use crate::metrics::DATABASE_LATENCY;
+use crate::records::{
+    CommentRecord, LikeRecord, PersonKnowsRecord, PostRecord, TagClassIsSubclassOfRecord,
+    TagHasTypeRecord, TagRecord,
+};
+use crate::schema::{
+    comment, like_, person_knows, post, tag, tag_class_is_subclass_of, tag_has_type,
+};
+use crate::store::SocialGraphStore;
+use crate::{DATABASE_BACKOFF, DATABASE_RETRIES, DATABASE_TIMEOUT};
+
+/// Run the given database operation, retrying with exponential backoff while the database is
+/// unavailable. Other errors are returned immediately. The latency including all retries is
+/// recorded under the given name.
+pub fn retry<T, F>(name: &str, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    let _timer = DATABASE_LATENCY.with_label_values(&[name]).start_timer();
+
+    let mut backoff = DATABASE_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(DspaError::Unavailable(message)) if attempt < DATABASE_RETRIES => {
+                eprintln!(
+                    "Database operation failed, retrying in {}ms: {}",
+                    backoff, message
+                );
+                sleep(Duration::from_millis(backoff));
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+pub struct PostgresStore {
+    pool: Pool<ConnectionManager<PgConnection>>,
+}
+
+impl PostgresStore {
+    pub fn new(url: &str) -> Self {
+        PostgresStore {
+            // Unchecked such that the binaries start even if the database is unavailable
+            pool: Pool::builder()
+                .max_size(16)
+                .connection_timeout(Duration::from_millis(DATABASE_TIMEOUT))
+                .build_unchecked(ConnectionManager::<PgConnection>::new(url)),
+        }
+    }
+
+    fn query<T, F>(&self, name: &str, query: F) -> Result<T>
+    where
+        F: Fn(&PgConnection) -> QueryResult<T>,
+    {
+        retry(name, || {
+            let connection = self.pool.get()?;
+            Ok(query(&connection)?)
+        })
+    }
+}
+
+impl SocialGraphStore for PostgresStore {
+    fn post(&self, id: i32) -> Result<Option<PostRecord>> {
+        self.query("post", |connection| {
+            post::table
+                .filter(post::id.eq(&id))
+                .first::<PostRecord>(connection)
+                .optional()
+        })
+    }
+
+    fn comment(&self, id: i32) -> Result<Option<CommentRecord>> {
+        self.query("comment", |connection| {
+            comment::table
+                .filter(comment::id.eq(&id))
+                .first::<CommentRecord>(connection)
+                .optional()
+        })
+    }
+
+    fn comments(&self, ids: &[i32]) -> Result<Vec<CommentRecord>> {
+        self.query("comments", |connection| {
+            comment::table
+                .filter(comment::id.eq_any(ids))
+                .load::<CommentRecord>(connection)
+        })
+    }
+
+    fn comments_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<CommentRecord>> {
+        self.query("comments_between", |connection| {
+            comment::table
+                .filter(comment::creation_date.ge(&from))
+                .filter(comment::creation_date.lt(&to))
+                .load::<CommentRecord>(connection)
+        })
+    }
+
+    fn likes_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<LikeRecord>> {
+        self.query("likes_between", |connection| {
+            like_::table
+                .filter(like_::creation_date.ge(&from))
+                .filter(like_::creation_date.lt(&to))
+                .load::<LikeRecord>(connection)
+        })
+    }
+
+    fn acquaintances(&self, person_id: i32) -> Result<Vec<i32>> {
+        self.query("acquaintances", |connection| {
+            person_knows::table
+                .filter(person_knows::person_id.eq(&person_id))
+                .load::<PersonKnowsRecord>(connection)
+        })
+        .map(|records| {
+            records
+                .into_iter()
+                .map(|record| record.acquaintance_id)
+                .collect()
+        })
+    }
+
+    fn tag_name(&self, tag_id: i32) -> Result<Option<String>> {
+        self.query("tag_name", |connection| {
+            tag::table
+                .filter(tag::id.eq(&tag_id))
+                .first::<TagRecord>(connection)
+                .optional()
+        })
+        .map(|record| record.map(|record| record.name))
+    }
+
+    fn tag_classes(&self, tag_id: i32) -> Result<Vec<i32>> {
+        self.query("tag_classes", |connection| {
+            tag_has_type::table
+                .filter(tag_has_type::tag_id.eq(&tag_id))
+                .load::<TagHasTypeRecord>(connection)
+        })
+        .map(|records| {
+            records
+                .into_iter()
+                .map(|record| record.tag_class_id)
+                .collect()
+        })
+    }
+
+    fn parent_classes(&self, tag_class_id: i32) -> Result<Vec<i32>> {
+        self.query("parent_classes", |connection| {
+            tag_class_is_subclass_of::table
+                .filter(tag_class_is_subclass_of::tag_class_id.eq(&tag_class_id))
+                .load::<TagClassIsSubclassOfRecord>(connection)
+        })
+        .map(|records| records.into_iter().map(|record| record.parent_id).collect())
+    }
+}
@@ -0,0 +1,191 @@
+//! Subscriptions to the broker.
+//!
+//! Consumers connect a DEALER socket to the data socket and subscribe to topic prefixes. The
+//! broker keeps a queue per subscriber and applies its overflow policy once the queue reaches
+//! the high-water mark. Subscribers using flow control grant the broker credits, one per message
+//! it may send them, as they process the received messages.
+
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+use structopt::StructOpt;
+use zmq::{Context, Socket, SocketType, DONTWAIT};
+
+use crate::envelope::Frames;
+use crate::{DATA_SOCKET, SOCKET_TIMEOUT};
+
+/// Handling of messages for a subscriber whose queue is full
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Policy {
+    /// Stop receiving from the producers until the subscriber catches up
+    Block,
+    /// Drop the oldest queued message
+    DropOldest,
+    /// Drop the new message
+    DropNewest,
+    /// Queue the message on disk
+    Spill,
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Policy::Block => write!(f, "block"),
+            Policy::DropOldest => write!(f, "drop-oldest"),
+            Policy::DropNewest => write!(f, "drop-newest"),
+            Policy::Spill => write!(f, "spill"),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "block" => Ok(Policy::Block),
+            "drop-oldest" => Ok(Policy::DropOldest),
+            "drop-newest" => Ok(Policy::DropNewest),
+            "spill" => Ok(Policy::Spill),
+            _ => Err(format!("Unknown policy: {}", value)),
+        }
+    }
+}
+
+/// Request of a subscriber to the broker
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    Subscribe {
+        /// Name of the subscriber in the metrics of the broker
+        name: String,
+        /// Topic prefixes to receive
+        topics: Vec<String>,
+        /// Overflow policy, the default of the broker if not given
+        policy: Option<Policy>,
+        /// Initial credits if the subscriber uses flow control
+        credit: Option<u64>,
+    },
+    /// The broker may send the given number of additional messages
+    Credit(u64),
+    Unsubscribe,
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Failed to encode request")
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        bincode::deserialize(data).map_err(|error| error.to_string())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SubscriptionArgs {
+    #[structopt(long = "policy")]
+    /// Overflow policy of the broker queue: block, drop-oldest, drop-newest or spill (default: as configured in the broker)
+    pub policy: Option<Policy>,
+    #[structopt(long = "credit")]
+    /// Number of messages the broker may send ahead of processing, enables credit-based flow control
+    pub credit: Option<u64>,
+    #[structopt(long = "producers", default_value = "3")]
+    /// Number of producers publishing on every partition, the input frontier is held until all of them are known. Every `dspa-source` worker publishes posts, comments and likes as separate producers.
+    pub producers: usize,
+}
+
+impl SubscriptionArgs {
+    /// Subscribe to the topic prefixes, named after the given worker
+    pub fn subscribe(&self, ctx: &Context, idx: usize, topics: &[String]) -> Subscriber {
+        Subscriber::connect(ctx, &subscriber_name(idx), topics, self.policy, self.credit)
+    }
+}
+
+/// Name of a worker of this process, consisting of the binary, its process id and the worker
+pub fn subscriber_name(idx: usize) -> String {
+    let program = env::args()
+        .next()
+        .as_ref()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|program| program.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    format!("{}/{}/{}", program, process::id(), idx)
+}
+
+/// Receiving side of a subscription, granting credits as received messages are processed
+pub struct Subscriber {
+    socket: Socket,
+    window: Option<u64>,
+    // Messages received, but not processed yet
+    received: u64,
+    // Messages processed, but not granted as credits yet
+    processed: u64,
+}
+
+impl Subscriber {
+    pub fn connect(
+        ctx: &Context,
+        name: &str,
+        topics: &[String],
+        policy: Option<Policy>,
+        credit: Option<u64>,
+    ) -> Self {
+        let socket = ctx.socket(SocketType::DEALER).unwrap();
+        socket.set_rcvtimeo(SOCKET_TIMEOUT).unwrap();
+        socket
+            .connect(&format!("ipc://{}", DATA_SOCKET))
+            .expect("Failed to connect!");
+
+        let request = Request::Subscribe {
+            name: name.to_owned(),
+            topics: topics.to_vec(),
+            policy,
+            credit,
+        };
+        socket.send(request.encode(), 0).unwrap();
+
+        Subscriber {
+            socket,
+            window: credit,
+            received: 0,
+            processed: 0,
+        }
+    }
+
+    /// Receive the next message (topic, frames), if one arrives within the socket timeout. Its
+    /// credit is only granted once it is marked as processed.
+    pub fn recv(&mut self) -> Option<(String, Frames)> {
+        let mut frames = self.socket.recv_multipart(0).ok()?;
+        self.received += 1;
+        if frames.len() < 2 {
+            return None;
+        }
+
+        let topic = String::from_utf8(frames.remove(0)).ok()?;
+        Some((topic, frames))
+    }
+
+    /// Mark the messages received so far as processed, granting their credits in batches of half
+    /// the window
+    pub fn processed(&mut self) {
+        self.processed += self.received;
+        self.received = 0;
+        if let Some(window) = self.window {
+            if self.processed >= (window / 2).max(1) {
+                self.socket
+                    .send(Request::Credit(self.processed).encode(), 0)
+                    .unwrap();
+                self.processed = 0;
+            }
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        let _ = self.socket.send(Request::Unsubscribe.encode(), DONTWAIT);
+    }
+}
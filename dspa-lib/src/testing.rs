@@ -0,0 +1,227 @@
+//! Deterministic dataflow harness for testing operators without ZeroMQ or PostgreSQL.
+//!
+//! Events are fed by worker 0 in script order, exactly like `streams` does. Events with a time
+//! before the current frontier are discarded and reported as late. After every frontier advance
+//! all workers step until the output frontier has passed, so operators observe the same
+//! progression of time on every run.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use chrono::{TimeZone, Utc};
+use timely::communication::Allocator;
+use timely::dataflow::operators::{Inspect, Probe, UnorderedInput};
+use timely::dataflow::scopes::Child;
+use timely::dataflow::{ProbeHandle, Stream};
+use timely::worker::Worker;
+use timely::{Configuration, ExchangeData};
+
+use crate::operators::split;
+use crate::records::{CommentRecord, LikeRecord, PostRecord};
+use crate::StreamEvent;
+
+pub type TestScope<'a> = Child<'a, Worker<Allocator>, u64>;
+
+pub type TestStreams<'a> = (
+    Stream<TestScope<'a>, PostRecord>,
+    Stream<TestScope<'a>, CommentRecord>,
+    Stream<TestScope<'a>, LikeRecord>,
+);
+
+#[derive(Clone, Debug)]
+pub enum Input {
+    /// Event at the given time
+    Event(u64, StreamEvent),
+    /// Advance the input frontier to the given time
+    Advance(u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct Output<D> {
+    /// Time => Records, in the order they were produced on each worker
+    pub results: BTreeMap<u64, Vec<D>>,
+    /// Events that arrived after the frontier had passed their time
+    pub discarded: Vec<(u64, StreamEvent)>,
+}
+
+impl<D> Output<D> {
+    pub fn at(&self, time: u64) -> &[D] {
+        self.results.get(&time).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+impl<D: Ord> Output<D> {
+    /// Sort the records of every time, for comparisons independent of worker interleaving
+    pub fn sorted(mut self) -> Self {
+        self.results.values_mut().for_each(|records| records.sort());
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Harness {
+    workers: usize,
+    inputs: Vec<Input>,
+}
+
+impl Harness {
+    pub fn new(events: Vec<(u64, StreamEvent)>) -> Self {
+        Harness {
+            workers: 1,
+            inputs: events
+                .into_iter()
+                .map(|(time, event)| Input::Event(time, event))
+                .collect(),
+        }
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    pub fn event<E: Into<StreamEvent>>(mut self, time: u64, event: E) -> Self {
+        self.inputs.push(Input::Event(time, event.into()));
+        self
+    }
+
+    pub fn advance_to(mut self, time: u64) -> Self {
+        self.inputs.push(Input::Advance(time));
+        self
+    }
+
+    /// Build the dataflow on every worker, feed the inputs and collect the outputs per time
+    pub fn run<D, F>(self, build: F) -> Output<D>
+    where
+        D: ExchangeData,
+        F: for<'a> Fn(TestStreams<'a>) -> Stream<TestScope<'a>, D> + Send + Sync + 'static,
+    {
+        let Harness { workers, inputs } = self;
+        let configuration = if workers > 1 {
+            Configuration::Process(workers)
+        } else {
+            Configuration::Thread
+        };
+
+        let discarded = Arc::new(Mutex::new(Vec::new()));
+        let results = {
+            let discarded = discarded.clone();
+
+            timely::execute(configuration, move |worker| {
+                let idx = worker.index();
+                let results: Rc<RefCell<BTreeMap<u64, Vec<D>>>> = Default::default();
+                let mut probe = ProbeHandle::new();
+
+                let (mut input, cap) = worker.dataflow::<u64, _, _>(|scope| {
+                    let (input, events) = scope.new_unordered_input::<StreamEvent>();
+
+                    let results = results.clone();
+                    build(split(&events))
+                        .inspect_time(move |time, record| {
+                            results
+                                .borrow_mut()
+                                .entry(*time)
+                                .or_default()
+                                .push(record.clone())
+                        })
+                        .probe_with(&mut probe);
+
+                    input
+                });
+
+                let mut cap = cap;
+                for input_event in inputs.iter().cloned() {
+                    match input_event {
+                        Input::Event(time, event) => {
+                            if idx != 0 {
+                                continue;
+                            }
+
+                            if time >= *cap.time() {
+                                input.session(cap.delayed(&time)).give(event);
+                            } else {
+                                discarded.lock().unwrap().push((time, event));
+                            }
+                        }
+                        Input::Advance(time) => {
+                            if time > *cap.time() {
+                                cap.downgrade(&time);
+                            }
+                            worker.step_while(|| probe.less_than(&time));
+                        }
+                    }
+                }
+
+                // Close the input and drain the dataflow
+                drop(cap);
+                worker.step_while(|| !probe.done());
+
+                let results = results.borrow().clone();
+                results
+            })
+            .expect("Failed to execute dataflow")
+            .join()
+        };
+
+        let mut output = Output {
+            results: BTreeMap::new(),
+            discarded: discarded.lock().unwrap().clone(),
+        };
+        for worker_results in results {
+            for (time, records) in worker_results.expect("Worker panicked") {
+                output.results.entry(time).or_default().extend(records);
+            }
+        }
+        output
+    }
+}
+
+pub fn post(id: i32, person_id: i32, time: u64) -> PostRecord {
+    PostRecord {
+        id,
+        person_id,
+        creation_date: Utc.timestamp(time as i64, 0),
+        image_file: None,
+        location_ip: "127.0.0.1".to_owned(),
+        browser_used: "Firefox".to_owned(),
+        language: None,
+        content: None,
+        tags: Vec::new(),
+        forum_id: 0,
+        place_id: 0,
+    }
+}
+
+/// Comment on a post
+pub fn comment(id: i32, person_id: i32, post_id: i32, time: u64) -> CommentRecord {
+    CommentRecord {
+        id,
+        person_id,
+        creation_date: Utc.timestamp(time as i64, 0),
+        location_ip: "127.0.0.1".to_owned(),
+        browser_used: "Firefox".to_owned(),
+        content: String::new(),
+        reply_to_post_id: Some(post_id),
+        reply_to_comment_id: None,
+        place_id: 0,
+    }
+}
+
+/// Reply to another comment
+pub fn reply(id: i32, person_id: i32, comment_id: i32, time: u64) -> CommentRecord {
+    CommentRecord {
+        reply_to_post_id: None,
+        reply_to_comment_id: Some(comment_id),
+        ..comment(id, person_id, 0, time)
+    }
+}
+
+pub fn like(person_id: i32, post_id: i32, time: u64) -> LikeRecord {
+    LikeRecord {
+        person_id,
+        post_id,
+        creation_date: Utc.timestamp(time as i64, 0),
+    }
+}
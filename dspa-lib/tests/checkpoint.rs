@@ -0,0 +1,57 @@
+use std::env::temp_dir;
+use std::fs::{remove_dir_all, File};
+use std::path::PathBuf;
+use std::process;
+
+use bincode::serialize_into;
+
+use dspa_lib::checkpoint::{Checkpoints, SNAPSHOT_VERSION};
+
+/// Directory with a complete snapshot at 20
+fn snapshot(name: &str) -> PathBuf {
+    let dir = temp_dir().join(format!("dspa-checkpoint-{}-{}", name, process::id()));
+    let checkpoints = Checkpoints::new(dir.clone(), 10, false);
+    checkpoints.save("state", 0, 20, &42u64);
+    checkpoints.complete(20);
+    dir
+}
+
+#[test]
+fn disabled_checkpoints_keep_snapshots() {
+    let dir = snapshot("disabled");
+
+    Checkpoints::new(dir.clone(), 0, false);
+    let restored = Checkpoints::new(dir.clone(), 10, true);
+    let state = restored.load::<u64>("state", 0);
+    remove_dir_all(dir).unwrap();
+
+    assert_eq!(restored.restored(), Some(20));
+    assert_eq!(state, Some(42));
+}
+
+#[test]
+fn new_run_discards_snapshots() {
+    let dir = snapshot("fresh");
+
+    Checkpoints::new(dir.clone(), 10, false);
+    let restored = Checkpoints::new(dir.clone(), 10, true);
+    remove_dir_all(dir).unwrap();
+
+    assert_eq!(restored.restored(), None);
+}
+
+#[test]
+fn snapshots_of_other_versions_are_discarded() {
+    let dir = snapshot("version");
+    let file = File::create(dir.join("20").join("state-0")).unwrap();
+    serialize_into(file, &(SNAPSHOT_VERSION - 1, 42u64)).unwrap();
+
+    let restored = Checkpoints::new(dir.clone(), 10, true);
+    let state = restored.load::<u64>("state", 0);
+    let discarded = !dir.join("20").exists();
+    remove_dir_all(dir).unwrap();
+
+    assert_eq!(restored.restored(), None);
+    assert_eq!(state, None);
+    assert!(discarded);
+}
@@ -0,0 +1,42 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use dspa_lib::deadletter::{DeadLetter, DeadLetterArgs};
+
+#[test]
+fn dead_letters_are_encoded_for_the_topic() {
+    let letter = DeadLetter::new("post.1", &"Invalid envelope", &[vec![1, 2], vec![3]]);
+    assert_eq!(DeadLetter::decode(&letter.encode()), Ok(letter.clone()));
+    assert_eq!(
+        letter.to_string(),
+        "Dead letter on post.1 (3 bytes): Invalid envelope"
+    );
+    assert!(DeadLetter::decode(&[0xff]).is_err());
+}
+
+#[test]
+fn dead_letters_are_appended_to_a_file_per_receiver() {
+    let dir = env::temp_dir().join(format!("dspa-deadletter-tests-{}", process::id()));
+    let args = DeadLetterArgs {
+        deadletter_dir: dir.clone(),
+    };
+
+    let first = DeadLetter::new("post.1", &"Invalid envelope", &[vec![1, 2, 3]]);
+    let second = DeadLetter::new("like.0", &"Invalid record", &[]);
+    args.dead_letters("dspa-anomalies/1/0")
+        .write(&first)
+        .unwrap();
+    args.dead_letters("dspa-anomalies/1/0")
+        .write(&second)
+        .unwrap();
+
+    let contents = fs::read_to_string(dir.join("dspa-anomalies-1-0.jsonl")).unwrap();
+    let letters: Vec<DeadLetter> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(letters, vec![first, second]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,190 @@
+use dspa_lib::codec::Codec;
+use dspa_lib::envelope::{Envelope, Sequences, SCHEMA_VERSION};
+use dspa_lib::error::DspaError;
+use dspa_lib::testing::{like, post, reply};
+use dspa_lib::{Control, StreamEvent};
+
+#[test]
+fn envelopes_roundtrip() {
+    let record = like(1, 2, 100);
+    let envelope = Envelope::new(Codec::Bincode, "producer", 7, &record).with_header("trace", "42");
+
+    let decoded = Envelope::decode("like", &envelope.encode()).unwrap();
+    assert_eq!(decoded.version, SCHEMA_VERSION);
+    assert_eq!(decoded.producer, "producer");
+    assert_eq!(decoded.sequence, 7);
+    assert_eq!(decoded.event_time, 100);
+    assert_eq!(decoded.headers["trace"], "42");
+
+    match decoded.event("like").unwrap() {
+        StreamEvent::Like(decoded) => assert_eq!(decoded.post_id, record.post_id),
+        event => panic!("Unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn incompatible_versions_are_rejected() {
+    let mut envelope = Envelope::new(Codec::Bincode, "producer", 0, &like(1, 2, 100));
+    envelope.version = SCHEMA_VERSION + 1;
+
+    assert_eq!(
+        Envelope::decode("like", &envelope.encode()).unwrap_err(),
+        DspaError::IncompatibleVersion {
+            topic: "like".to_owned(),
+            version: SCHEMA_VERSION + 1,
+        }
+    );
+}
+
+#[test]
+fn sequence_gaps_are_reported_per_producer_and_topic() {
+    let mut sequences = Sequences::default();
+    let record = like(1, 2, 100);
+
+    assert!(sequences
+        .observe("like", &Envelope::new(Codec::Bincode, "a", 0, &record))
+        .is_ok());
+    assert!(sequences
+        .observe("like", &Envelope::new(Codec::Bincode, "b", 0, &record))
+        .is_ok());
+    assert!(sequences
+        .observe("post", &Envelope::new(Codec::Bincode, "a", 0, &record))
+        .is_ok());
+
+    // Messages 1 and 2 of producer a were lost
+    assert_eq!(
+        sequences.observe("like", &Envelope::new(Codec::Bincode, "a", 3, &record)),
+        Err(DspaError::SequenceGap {
+            topic: "like".to_owned(),
+            producer: "a".to_owned(),
+            expected: 1,
+            received: 3,
+        })
+    );
+    assert!(sequences
+        .observe("like", &Envelope::new(Codec::Bincode, "a", 4, &record))
+        .is_ok());
+
+    // Late message 2 arrives out of order
+    assert_eq!(
+        sequences.observe("like", &Envelope::new(Codec::Bincode, "a", 2, &record)),
+        Err(DspaError::SequenceGap {
+            topic: "like".to_owned(),
+            producer: "a".to_owned(),
+            expected: 5,
+            received: 2,
+        })
+    );
+    assert!(sequences
+        .observe("like", &Envelope::new(Codec::Bincode, "a", 5, &record))
+        .is_ok());
+}
+
+#[test]
+fn sequences_start_at_the_first_observed_message() {
+    let mut sequences = Sequences::default();
+    let record = like(1, 2, 100);
+
+    // Joined after the first 41 messages of producer a
+    assert!(sequences
+        .observe("like", &Envelope::new(Codec::Bincode, "a", 41, &record))
+        .is_ok());
+    assert!(sequences
+        .observe("like", &Envelope::new(Codec::Bincode, "a", 42, &record))
+        .is_ok());
+
+    // Later jumps are still gaps
+    assert_eq!(
+        sequences.observe("like", &Envelope::new(Codec::Bincode, "a", 44, &record)),
+        Err(DspaError::SequenceGap {
+            topic: "like".to_owned(),
+            producer: "a".to_owned(),
+            expected: 43,
+            received: 44,
+        })
+    );
+}
+
+#[test]
+fn records_and_control_messages_roundtrip_with_every_codec() {
+    let codecs = [
+        Codec::Bincode,
+        Codec::Json,
+        Codec::MessagePack,
+        Codec::Protobuf,
+    ];
+    let post = post(1, 2, 100);
+    let reply = reply(3, 4, 1, 200);
+
+    for codec in codecs.iter().cloned() {
+        let envelope = Envelope::new(codec, "producer", 1, &post).with_header("trace", "42");
+        let decoded = Envelope::decode("post", &envelope.encode()).unwrap();
+        assert_eq!(decoded.codec, codec);
+        assert_eq!(decoded.headers["trace"], "42");
+        match decoded.event("post").unwrap() {
+            StreamEvent::Post(decoded) => {
+                assert_eq!(decoded.id, post.id);
+                assert_eq!(decoded.creation_date, post.creation_date);
+                assert_eq!(decoded.tags, post.tags);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let envelope = Envelope::new(codec, "producer", 2, &reply);
+        let decoded = Envelope::decode("comment", &envelope.encode()).unwrap();
+        match decoded.event("comment").unwrap() {
+            StreamEvent::Comment(decoded) => {
+                assert_eq!(decoded.reply_to_comment_id, reply.reply_to_comment_id);
+                assert_eq!(decoded.reply_to_post_id, None);
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        for control in &[Control::Register, Control::Eos, Control::Watermark(300)] {
+            let topic = control.topic().to_string();
+            let envelope = Envelope::control(codec, "producer", 0, control);
+            let decoded = Envelope::decode(&topic, &envelope.encode()).unwrap();
+            assert_eq!(&decoded.control_message(&topic).unwrap(), control);
+        }
+    }
+}
+
+#[test]
+fn envelopes_are_transcoded_between_endpoints() {
+    let envelope = Envelope::new(Codec::Bincode, "producer", 5, &like(1, 2, 100));
+    let transcoded = envelope.transcode("like", Codec::Json).unwrap();
+    assert_eq!(transcoded.codec, Codec::Json);
+    assert_eq!(transcoded.sequence, 5);
+
+    let decoded = Envelope::decode("like", &transcoded.encode()).unwrap();
+    match decoded.event("like").unwrap() {
+        StreamEvent::Like(decoded) => assert_eq!(decoded.post_id, 2),
+        event => panic!("Unexpected event {:?}", event),
+    }
+
+    let envelope = Envelope::control(Codec::Protobuf, "producer", 0, &Control::Watermark(60));
+    let transcoded = envelope.transcode("watermark", Codec::MessagePack).unwrap();
+    assert_eq!(
+        transcoded.control_message("watermark").unwrap(),
+        Control::Watermark(60)
+    );
+}
+
+#[test]
+fn payloads_are_encoded_natively_in_their_own_frame() {
+    let envelope = Envelope::new(Codec::Json, "producer", 0, &like(1, 2, 100));
+    let frames = envelope.encode();
+
+    assert_eq!(frames.len(), 3);
+    assert_eq!(
+        frames[0],
+        [&SCHEMA_VERSION.to_le_bytes()[..], &[1]].concat()
+    );
+    let payload: serde_json::Value = serde_json::from_slice(&frames[2]).unwrap();
+    assert_eq!(payload["Post.id"], 2);
+
+    assert!(matches!(
+        Envelope::decode("like", &frames[..2]),
+        Err(DspaError::Decode { .. })
+    ));
+}
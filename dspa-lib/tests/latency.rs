@@ -0,0 +1,84 @@
+use timely::dataflow::operators::{Filter, Map};
+
+use dspa_lib::latency::{Histogram, LatencyTracker, PartialLatency, Percentiles, Stamp};
+use dspa_lib::operators::{Gather, TrackLatency};
+use dspa_lib::testing::{post, Harness};
+
+#[test]
+fn latency_is_split_into_delay_and_processing() {
+    let mut tracker = LatencyTracker::default();
+
+    // Emitted at 0, received at 10 and waiting for the frontier to pass 100 until 50
+    tracker.received(
+        100,
+        PartialLatency::new(&[Stamp {
+            emitted: 0,
+            received: 10,
+            passed: 50,
+        }]),
+    );
+
+    let latency = tracker.complete(100, 60).unwrap();
+    assert_eq!(latency.records, 1);
+    assert_eq!(latency.total.p99, 60);
+    assert_eq!(latency.delay.p99, 40);
+    assert_eq!(latency.processing.p99, 20);
+
+    // Records are only reported in the first batch at or after their time
+    assert_eq!(tracker.complete(200, 70), None);
+}
+
+#[test]
+fn percentiles_use_nearest_rank() {
+    let mut tracker = LatencyTracker::default();
+    let stamps: Vec<_> = (0..100).map(|emitted| Stamp::new(emitted, 100)).collect();
+    tracker.received(10, PartialLatency::new(&stamps));
+
+    let latency = tracker.complete(10, 100).unwrap();
+    assert_eq!(latency.records, 100);
+    assert_eq!(
+        latency.total,
+        Percentiles {
+            p50: 50,
+            p95: 95,
+            p99: 99
+        }
+    );
+    assert_eq!(latency.delay.p99, 0);
+}
+
+#[test]
+fn large_latencies_are_rounded_down() {
+    let mut histogram = Histogram::default();
+    histogram.insert(12_345);
+    assert_eq!(histogram.count(), 1);
+
+    // Rounded once on the receiving worker and once after waiting for the batch
+    let mut tracker = LatencyTracker::default();
+    tracker.received(10, PartialLatency::new(&[Stamp::new(0, 12_345)]));
+    let p99 = tracker.complete(10, 13_345).unwrap().total.p99;
+
+    assert!(p99 <= 13_345 && p99 as f64 >= 13_345.0 * 0.98);
+}
+
+#[test]
+fn stamps_are_reported_at_the_next_written_batch() {
+    let output = Harness::default()
+        .event(10, post(1, 1, 10))
+        .event(20, post(2, 1, 20))
+        .event(30, post(3, 1, 30))
+        .advance_to(40)
+        .workers(2)
+        .run(|(posts, _, _)| {
+            let stamps = posts.map(|_| Stamp::new(0, 0));
+            // Only the posts at 20 and 30 produce a batch
+            let written = posts.filter(|post| post.id != 1).gather();
+            stamps
+                .track_latency(&written)
+                .map(|latency| latency.records)
+        });
+
+    assert_eq!(output.at(10), &[] as &[usize]);
+    assert_eq!(output.at(20), &[2]);
+    assert_eq!(output.at(30), &[1]);
+}
@@ -0,0 +1,33 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use dspa_lib::metrics::{serve, METRICS_ADDRESS, RECORDS_RECEIVED};
+
+fn get(port: u16, path: &str) -> String {
+    let mut stream = TcpStream::connect((METRICS_ADDRESS, port)).unwrap();
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    )
+    .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn endpoint_serves_registered_metrics() {
+    let port = 19200;
+    serve(port);
+
+    RECORDS_RECEIVED.with_label_values(&["post"]).inc_by(3);
+
+    let response = get(port, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("dspa_records_received_total{topic=\"post\"} 3"));
+
+    let response = get(port, "/unknown");
+    assert!(response.starts_with("HTTP/1.1 404"));
+}
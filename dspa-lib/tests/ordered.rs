@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use timely::dataflow::operators::{Concat, Map};
+
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::error::{DspaError, Result};
+use dspa_lib::operators::Ordered;
+use dspa_lib::records::{CommentRecord, PostRecord};
+use dspa_lib::store::{MemoryStore, SocialGraphStore};
+use dspa_lib::testing::{comment, like, post, reply, Harness};
+
+fn ordered_comments(harness: Harness) -> Vec<(u64, Vec<i32>)> {
+    let store: Arc<dyn SocialGraphStore> = Arc::new(MemoryStore::new());
+
+    harness
+        .run(move |(posts, comments, _)| {
+            let scope = posts.scope();
+            comments
+                .ordered(
+                    scope.index(),
+                    scope.peers(),
+                    store.clone(),
+                    &posts,
+                    &Checkpoints::default(),
+                )
+                .0
+                .map(|comment| comment.id)
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+fn ordered_likes(harness: Harness) -> Vec<(u64, Vec<(i32, i32)>)> {
+    let store: Arc<dyn SocialGraphStore> = Arc::new(MemoryStore::new());
+
+    harness
+        .run(move |(posts, _, likes)| {
+            let scope = posts.scope();
+            likes
+                .ordered(
+                    scope.index(),
+                    scope.peers(),
+                    store.clone(),
+                    &posts,
+                    &Checkpoints::default(),
+                )
+                .0
+                .map(|like| (like.person_id, like.post_id))
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn comment_after_post_is_released_immediately() {
+    let harness = Harness::default()
+        .event(10, post(1, 100, 10))
+        .advance_to(20)
+        .event(20, comment(2, 101, 1, 20));
+
+    assert_eq!(ordered_comments(harness), vec![(20, vec![2])]);
+}
+
+#[test]
+fn comment_before_post_waits_for_post() {
+    let harness = Harness::default()
+        .event(10, comment(2, 101, 1, 10))
+        .advance_to(20)
+        .event(30, post(1, 100, 30));
+
+    assert_eq!(ordered_comments(harness), vec![(30, vec![2])]);
+}
+
+#[test]
+fn reply_chain_is_released_with_root_post() {
+    let harness = Harness::default()
+        .event(10, reply(4, 103, 3, 10))
+        .advance_to(20)
+        .event(20, reply(3, 102, 2, 20))
+        .advance_to(30)
+        .event(30, comment(2, 101, 1, 30))
+        .advance_to(40)
+        .event(40, post(1, 100, 40))
+        .advance_to(50)
+        .event(50, reply(5, 104, 4, 50));
+
+    assert_eq!(
+        ordered_comments(harness),
+        vec![(40, vec![2, 3, 4]), (50, vec![5])]
+    );
+}
+
+#[test]
+fn comments_are_released_once_on_multiple_workers() {
+    let harness = Harness::default()
+        .event(10, comment(2, 101, 1, 10))
+        .event(10, reply(3, 102, 2, 10))
+        .event(10, comment(4, 103, 1, 10))
+        .advance_to(20)
+        .event(20, post(1, 100, 20))
+        .advance_to(30)
+        .event(30, reply(5, 104, 3, 30))
+        .workers(3);
+
+    assert_eq!(
+        ordered_comments(harness),
+        vec![(20, vec![2, 3, 4]), (30, vec![5])]
+    );
+}
+
+#[test]
+fn like_before_post_waits_for_post() {
+    let harness = Harness::default()
+        .event(10, like(101, 1, 10))
+        .event(10, like(102, 2, 10))
+        .advance_to(20)
+        .event(20, post(1, 100, 20))
+        .advance_to(30)
+        .event(30, like(103, 1, 30))
+        .workers(2);
+
+    assert_eq!(
+        ordered_likes(harness),
+        vec![(20, vec![(101, 1)]), (30, vec![(103, 1)])]
+    );
+}
+
+#[test]
+fn late_events_are_discarded() {
+    let harness = Harness::default()
+        .event(10, post(1, 100, 10))
+        .advance_to(20)
+        .event(15, comment(2, 101, 1, 15))
+        .event(25, comment(3, 101, 1, 25));
+
+    let store: Arc<dyn SocialGraphStore> = Arc::new(MemoryStore::new());
+    let output = harness.run(move |(posts, comments, _)| {
+        comments
+            .ordered(0, 1, store.clone(), &posts, &Checkpoints::default())
+            .0
+            .map(|comment| comment.id)
+    });
+
+    assert_eq!(output.at(25), &[3]);
+    assert_eq!(output.discarded.len(), 1);
+    assert_eq!(output.discarded[0].1.id(), Some(2));
+}
+
+/// Store whose database is unreachable
+struct FailingStore;
+
+impl SocialGraphStore for FailingStore {
+    fn post(&self, _id: i32) -> Result<Option<PostRecord>> {
+        Err(DspaError::Unavailable("connection refused".to_owned()))
+    }
+
+    fn comment(&self, _id: i32) -> Result<Option<CommentRecord>> {
+        Err(DspaError::Unavailable("connection refused".to_owned()))
+    }
+
+    fn acquaintances(&self, _person_id: i32) -> Result<Vec<i32>> {
+        Err(DspaError::Unavailable("connection refused".to_owned()))
+    }
+}
+
+#[test]
+fn failed_lookups_are_reported_once() {
+    let harness = Harness::default()
+        .event(10, comment(2, 101, 1, 10))
+        .event(10, like(102, 1, 10))
+        .advance_to(20);
+
+    let store: Arc<dyn SocialGraphStore> = Arc::new(FailingStore);
+    let output = harness.workers(2).run(move |(posts, comments, likes)| {
+        let scope = posts.scope();
+        let (_, failed_comments) = comments.ordered(
+            scope.index(),
+            scope.peers(),
+            store.clone(),
+            &posts,
+            &Checkpoints::default(),
+        );
+        let (_, failed_likes) = likes.ordered(
+            scope.index(),
+            scope.peers(),
+            store.clone(),
+            &posts,
+            &Checkpoints::default(),
+        );
+
+        failed_comments
+            .concat(&failed_likes)
+            .map(|failure| failure.error)
+    });
+
+    let error = DspaError::Unavailable("connection refused".to_owned());
+    assert_eq!(output.at(10), &[error.clone(), error]);
+}
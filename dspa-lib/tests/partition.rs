@@ -0,0 +1,61 @@
+use dspa_lib::partition::{partition, split_topic, worker_partitions, Watermarks};
+use dspa_lib::Topic;
+
+#[test]
+fn topics_are_split_into_name_and_partition() {
+    assert_eq!(Topic::Post.partition(3), "post.3");
+    assert_eq!(split_topic("post.3"), ("post", Some(3)));
+    assert_eq!(split_topic("watermark.12"), ("watermark", Some(12)));
+    assert_eq!(split_topic("eos"), ("eos", None));
+
+    assert_eq!(partition(7, 4), 3);
+    assert_eq!(partition(-1, 4), 3);
+}
+
+#[test]
+fn partitions_are_assigned_round_robin_to_workers() {
+    assert_eq!(worker_partitions(0, 2, 5), vec![0, 2, 4]);
+    assert_eq!(worker_partitions(1, 2, 5), vec![1, 3]);
+    assert_eq!(worker_partitions(3, 4, 2), Vec::<usize>::new());
+}
+
+#[test]
+fn watermarks_are_merged_across_partitions_and_producers() {
+    let mut watermarks = Watermarks::new(&[0, 2], 1);
+    assert_eq!(watermarks.watermark(), None);
+
+    // Partition 2 has not heard from producer a yet
+    watermarks.observe(0, "a", 100);
+    assert_eq!(watermarks.watermark(), Some(0));
+
+    watermarks.observe(2, "a", 200);
+    assert_eq!(watermarks.watermark(), Some(100));
+
+    // Producer b published a record on partition 0, but no watermark yet
+    watermarks.producer(0, "b");
+    assert_eq!(watermarks.watermark(), Some(0));
+
+    // Producer b holds back partition 2 until it reports there
+    watermarks.observe(0, "b", 300);
+    watermarks.observe(0, "a", 250);
+    assert_eq!(watermarks.watermark(), Some(0));
+
+    watermarks.observe(2, "b", 300);
+    assert_eq!(watermarks.watermark(), Some(200));
+
+    // Watermarks never regress and other partitions are ignored
+    watermarks.observe(2, "a", 150);
+    watermarks.observe(1, "a", 0);
+    assert_eq!(watermarks.watermark(), Some(200));
+}
+
+#[test]
+fn watermarks_are_held_until_the_expected_producers_are_known() {
+    let mut watermarks = Watermarks::new(&[0], 2);
+
+    watermarks.observe(0, "a", 100);
+    assert_eq!(watermarks.watermark(), None);
+
+    watermarks.observe(0, "b", 50);
+    assert_eq!(watermarks.watermark(), Some(50));
+}
@@ -0,0 +1,66 @@
+use std::cell::Cell;
+
+use diesel::result::{DatabaseErrorKind, Error};
+
+use dspa_lib::error::DspaError;
+use dspa_lib::store::retry;
+
+#[test]
+fn transient_errors_are_unavailable() {
+    let conflict = Error::DatabaseError(
+        DatabaseErrorKind::SerializationFailure,
+        Box::new("could not serialize access".to_owned()),
+    );
+    let lost = Error::DatabaseError(
+        DatabaseErrorKind::UnableToSendCommand,
+        Box::new("server closed the connection unexpectedly".to_owned()),
+    );
+
+    assert!(matches!(
+        DspaError::from(conflict),
+        DspaError::Unavailable(_)
+    ));
+    assert!(matches!(DspaError::from(lost), DspaError::Unavailable(_)));
+}
+
+#[test]
+fn dropped_connections_are_unavailable() {
+    let dropped = Error::DatabaseError(
+        DatabaseErrorKind::__Unknown,
+        Box::new(
+            "server closed the connection unexpectedly\n\tThis probably means the server \
+             terminated abnormally"
+                .to_owned(),
+        ),
+    );
+    let syntax = Error::DatabaseError(
+        DatabaseErrorKind::__Unknown,
+        Box::new("syntax error at or near \"SELEC\"".to_owned()),
+    );
+
+    assert!(matches!(
+        DspaError::from(dropped),
+        DspaError::Unavailable(_)
+    ));
+    assert!(matches!(DspaError::from(syntax), DspaError::Database(_)));
+}
+
+#[test]
+fn query_errors_are_not_retried() {
+    let violation = Error::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new("duplicate key value".to_owned()),
+    );
+
+    for error in vec![Error::NotFound, violation] {
+        let attempts = Cell::new(0);
+        let error = DspaError::from(error);
+        let result: Result<(), _> = retry("test", || {
+            attempts.set(attempts.get() + 1);
+            Err(error.clone())
+        });
+
+        assert!(matches!(result, Err(DspaError::Database(_))));
+        assert_eq!(attempts.get(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+use std::env::temp_dir;
+use std::fmt;
+use std::fs::{read_to_string, remove_file};
+use std::path::PathBuf;
+
+use serde_derive::Serialize;
+
+use dspa_lib::sink::{CsvSink, JsonSink, Sink, TextSink};
+
+#[derive(Serialize)]
+struct Row {
+    timestamp: u64,
+    id: i32,
+    name: String,
+}
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Row {{ id: {}, name: {} }}", self.id, self.name)
+    }
+}
+
+fn rows(timestamp: u64) -> Vec<Row> {
+    vec![
+        Row {
+            timestamp,
+            id: 1,
+            name: "first".to_owned(),
+        },
+        Row {
+            timestamp,
+            id: 2,
+            name: "second".to_owned(),
+        },
+    ]
+}
+
+fn path(name: &str) -> PathBuf {
+    temp_dir().join(format!("dspa-sink-{}-{}", std::process::id(), name))
+}
+
+fn written<S: Sink<Row>>(mut sink: S, path: &PathBuf) -> String {
+    sink.write(1800, &rows(1800)).unwrap();
+    sink.write(3600, &[]).unwrap();
+    sink.write(5400, &rows(5400)[..1]).unwrap();
+    drop(sink);
+
+    let contents = read_to_string(path).unwrap();
+    remove_file(path).unwrap();
+    contents
+}
+
+#[test]
+fn json_sink_writes_one_object_per_row() {
+    let path = path("rows.jsonl");
+    let contents = written(JsonSink::new(Some(path.clone())), &path);
+
+    assert_eq!(
+        contents,
+        "{\"timestamp\":1800,\"id\":1,\"name\":\"first\"}\n\
+         {\"timestamp\":1800,\"id\":2,\"name\":\"second\"}\n\
+         {\"timestamp\":5400,\"id\":1,\"name\":\"first\"}\n"
+    );
+}
+
+#[test]
+fn csv_sink_writes_header_once() {
+    let path = path("rows.csv");
+    let contents = written(CsvSink::new(Some(path.clone())), &path);
+
+    assert_eq!(
+        contents,
+        "timestamp,id,name\n1800,1,first\n1800,2,second\n5400,1,first\n"
+    );
+}
+
+#[test]
+fn text_sink_skips_empty_timestamps() {
+    let path = path("rows.txt");
+    let contents = written(TextSink::new(Some(path.clone())), &path);
+
+    assert_eq!(
+        contents
+            .lines()
+            .filter(|line| !line.starts_with('\t'))
+            .count(),
+        2
+    );
+    assert!(contents.contains("\tRow { id: 2, name: second }\n"));
+}
+
+#[test]
+fn sinks_without_results_create_no_file() {
+    let path = path("empty.jsonl");
+    let mut sink = JsonSink::new(Some(path.clone()));
+    Sink::<Row>::write(&mut sink, 1800, &[]).unwrap();
+
+    assert!(!path.exists());
+}
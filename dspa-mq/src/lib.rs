@@ -0,0 +1,5 @@
+mod producers;
+mod subscribers;
+
+pub use producers::*;
+pub use subscribers::*;
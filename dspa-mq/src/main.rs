@@ -1,19 +1,270 @@
+use std::fmt;
 use std::fs::create_dir_all;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use bincode::deserialize;
-use zmq::{Context, SocketType, SNDMORE};
+use structopt::StructOpt;
+use zmq::{Context, Socket, SocketType, DONTWAIT, POLLIN};
 
-use dspa_lib::records::{CommentRecord, LikeRecord, PostRecord, StreamRecord};
-use dspa_lib::{Topic, DATA_SOCKET, SOURCE_SOCKET};
+use dspa_lib::codec::Codec;
+use dspa_lib::deadletter::{DeadLetter, DeadLetterArgs, DeadLetters};
+use dspa_lib::envelope::{Envelope, Frames, Sequences};
+use dspa_lib::latency::wall_clock;
+use dspa_lib::metrics::{
+    discard_reason, MetricsArgs, DISCARDED_EVENTS, FORWARD_LATENCY, MQ_METRICS_PORT,
+    RECORDS_EMITTED, RECORDS_RECEIVED,
+};
+use dspa_lib::partition::split_topic;
+use dspa_lib::shutdown;
+use dspa_lib::subscription::{Policy, Request};
+use dspa_lib::{Control, Topic, DATA_SOCKET, SOCKET_TIMEOUT, SOURCE_SOCKET};
+
+use dspa_mq::{Delivery, Producers, Subscribers};
+
+// Time without progress after which undelivered messages are given up when exiting
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+// Interval in milliseconds to retry sending to subscribers whose socket was full
+const RETRY_INTERVAL: i64 = 10;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "dspa-mq")]
+struct Args {
+    #[structopt(long = "codec")]
+    /// Serialization of the forwarded messages: bincode, json, msgpack or protobuf (default: as received)
+    codec: Option<Codec>,
+    #[structopt(long = "producers", default_value = "1")]
+    /// Number of producers whose end of stream is awaited, in addition to the registered ones
+    producers: usize,
+    #[structopt(long = "hwm", default_value = "10000")]
+    /// Number of messages queued in memory per subscriber before its policy applies
+    hwm: usize,
+    #[structopt(long = "policy", default_value = "drop-newest")]
+    /// Policy for subscribers that do not request one: block, drop-oldest, drop-newest or spill
+    policy: Policy,
+    #[structopt(
+        long = "spill-dir",
+        default_value = "/tmp/dspa/spill",
+        parse(from_os_str)
+    )]
+    /// Directory to spill messages of subscribers with the spill policy to
+    spill_dir: PathBuf,
+    #[structopt(flatten)]
+    deadletter: DeadLetterArgs,
+    #[structopt(flatten)]
+    metrics: MetricsArgs,
+}
+
+struct Broker {
+    codec: Option<Codec>,
+    recv_socket: Socket,
+    data_socket: Socket,
+    sequences: Sequences,
+    producers: Producers,
+    subscribers: Subscribers,
+    dead_letters: DeadLetters,
+}
+
+impl Broker {
+    /// Wait for requests of the subscribers and, if requested, messages of the producers. Returns
+    /// whether a message of the producers is ready.
+    fn poll(&self, producers: bool) -> bool {
+        // Subscribers whose socket was full are retried, anything else wakes up the broker
+        let timeout = if self.subscribers.congested() {
+            RETRY_INTERVAL
+        } else {
+            i64::from(SOCKET_TIMEOUT)
+        };
+
+        let mut items = [
+            self.data_socket.as_poll_item(POLLIN),
+            self.recv_socket.as_poll_item(POLLIN),
+        ];
+        let polled = if producers { 2 } else { 1 };
+        zmq::poll(&mut items[..polled], timeout).unwrap();
+        producers && items[1].is_readable()
+    }
+
+    /// Receive a message (topic, frames) of the producers without waiting
+    fn recv(&self) -> Option<(Vec<u8>, Frames)> {
+        // Frames of a message arrive together
+        let mut frames = self.recv_socket.recv_multipart(DONTWAIT).ok()?;
+        let topic = frames.remove(0);
+        Some((topic, frames))
+    }
+
+    /// Handle the pending requests of the subscribers
+    fn requests(&mut self) {
+        while let Ok(frames) = self.data_socket.recv_multipart(DONTWAIT) {
+            if frames.len() != 2 {
+                continue;
+            }
+            match Request::decode(&frames[1]) {
+                Ok(request) => self.subscribers.request(&frames[0], request),
+                Err(error) => eprintln!("Dropping request: {}", error),
+            }
+        }
+    }
+
+    /// Send queued messages to the subscribers, returns the number of sent messages
+    fn flush(&mut self) -> usize {
+        let data_socket = &self.data_socket;
+        self.subscribers.flush(|identity, topic, frames| {
+            let mut message = vec![identity, topic.as_bytes()];
+            message.extend(frames.iter().map(Vec::as_slice));
+            match data_socket.send_multipart(message, DONTWAIT) {
+                Ok(()) => Delivery::Sent,
+                Err(zmq::Error::EAGAIN) => Delivery::Full,
+                Err(_) => Delivery::Gone,
+            }
+        })
+    }
+
+    /// Deliver the queued messages until the subscribers received them or stop making progress
+    fn deliver(&mut self) {
+        let mut progress = Instant::now();
+        while !self.subscribers.is_empty() && progress.elapsed() < DRAIN_TIMEOUT {
+            self.poll(false);
+            self.requests();
+            if self.flush() > 0 {
+                progress = Instant::now();
+            }
+        }
+    }
+
+    /// Publish a rejected message on the dead-letter topic and write it to the local file
+    fn dead_letter(&mut self, topic: &str, error: &dyn fmt::Display, frames: &[Vec<u8>]) {
+        let letter = DeadLetter::new(topic, error, frames);
+        eprintln!("{}", letter);
+        if let Err(error) = self.dead_letters.write(&letter) {
+            eprintln!("Failed to write dead letter: {}", error);
+        }
+        self.subscribers
+            .publish(&Topic::DeadLetter.to_string(), &[letter.encode()]);
+    }
+
+    /// Forward a message (topic, frames), returns whether all producers finished
+    fn forward(&mut self, topic: Vec<u8>, frames: Frames) -> bool {
+        let topic = match String::from_utf8(topic) {
+            Ok(topic) => topic,
+            Err(error) => {
+                DISCARDED_EVENTS
+                    .with_label_values(&["", "invalid_topic"])
+                    .inc();
+                let topic = String::from_utf8_lossy(error.as_bytes()).into_owned();
+                self.dead_letter(&topic, &error, &frames);
+                return false;
+            }
+        };
+
+        RECORDS_RECEIVED.with_label_values(&[&topic]).inc();
+
+        // Only forward messages that consumers are able to decode
+        let name = split_topic(&topic).0;
+        let control = name == Topic::EOS.to_string()
+            || name == Topic::Watermark.to_string()
+            || name == Topic::Register.to_string();
+        let decoded = Envelope::decode(&topic, &frames).and_then(|envelope| {
+            if control {
+                envelope.control_message(&topic)?;
+            } else {
+                let event = envelope.event(&topic)?;
+                println!(
+                    "{} record {{ timestamp: {}, id: {:?} }}",
+                    topic,
+                    event.timestamp(),
+                    event.id()
+                );
+            }
+            Ok(envelope)
+        });
+        let envelope = match decoded {
+            Ok(envelope) => envelope,
+            Err(error) => {
+                DISCARDED_EVENTS
+                    .with_label_values(&[&topic, discard_reason(&error)])
+                    .inc();
+                self.dead_letter(&topic, &error, &frames);
+                return false;
+            }
+        };
+
+        // Gaps are reported, the message itself is still forwarded
+        if let Err(error) = self.sequences.observe(&topic, &envelope) {
+            eprintln!("{}", error);
+        }
+
+        // Registrations are kept by the broker, the end of stream is only forwarded once all
+        // producers finished
+        if topic == Topic::Register.to_string() {
+            eprintln!("Producer {} registered", envelope.producer);
+            self.producers.register(&envelope.producer);
+            return false;
+        }
+        if topic == Topic::EOS.to_string() {
+            self.producers.finish(&envelope.producer);
+            if !self.producers.done() {
+                eprintln!(
+                    "Producer {} finished, waiting for {} more",
+                    envelope.producer,
+                    self.producers.pending()
+                );
+                return false;
+            }
+        }
+
+        let emitted = envelope.emitted;
+
+        // Messages are forwarded as received unless the data socket uses another codec
+        let frames = match self.codec {
+            Some(codec) if codec != envelope.codec => match envelope.transcode(&topic, codec) {
+                Ok(envelope) => envelope.encode(),
+                Err(error) => {
+                    self.dead_letter(&topic, &error, &frames);
+                    return false;
+                }
+            },
+            _ => frames,
+        };
+
+        // Forward message with given topic
+        self.subscribers.publish(&topic, &frames);
+        RECORDS_EMITTED.with_label_values(&[&topic]).inc();
+        if !control {
+            let latency = wall_clock().saturating_sub(emitted);
+            FORWARD_LATENCY
+                .with_label_values(&[&topic])
+                .observe(latency as f64 / 1000.0);
+        }
+
+        topic == Topic::EOS.to_string()
+    }
+
+    /// Forward the end of stream on behalf of the producers that did not finish
+    fn end_of_stream(&mut self) {
+        let control = Control::Eos;
+        let envelope =
+            Envelope::control(self.codec.unwrap_or(Codec::Bincode), "dspa-mq", 0, &control);
+        self.subscribers
+            .publish(&control.topic().to_string(), &envelope.encode());
+        RECORDS_EMITTED
+            .with_label_values(&[&control.topic().to_string()])
+            .inc();
+    }
+}
 
 fn main() {
+    let args = Args::from_args();
+    args.metrics.serve(MQ_METRICS_PORT, 0);
+    shutdown::handle_signals();
+
     // Create context
     let ctx = Context::new();
     create_dir_all("/tmp/dspa").expect("Failed to create temp dir");
 
-    // Create publish socket
-    let send_socket = ctx.socket(SocketType::PUB).unwrap();
-    send_socket
+    // Create router socket, subscribers connect and request their topics
+    let data_socket = ctx.socket(SocketType::ROUTER).unwrap();
+    data_socket.set_router_mandatory(true).unwrap();
+    data_socket
         .bind(&format!("ipc://{}", DATA_SOCKET))
         .expect("Send socket failed to bind");
 
@@ -23,47 +274,48 @@ fn main() {
         .bind(&format!("ipc://{}", SOURCE_SOCKET))
         .expect("Recv socket failed to bind");
 
-    // Listen for message pairs (topic, data)
-    while let (Ok(topic), Ok(data)) = (recv_socket.recv_bytes(0), recv_socket.recv_bytes(0)) {
-        // Forward message with given topic
-        send_socket.send(&topic, SNDMORE).unwrap();
-        let topic = String::from_utf8(topic).unwrap();
+    let mut broker = Broker {
+        codec: args.codec,
+        recv_socket,
+        data_socket,
+        sequences: Sequences::default(),
+        producers: Producers::new(args.producers),
+        subscribers: Subscribers::new(args.hwm, args.policy, args.spill_dir),
+        dead_letters: args.deadletter.dead_letters("dspa-mq"),
+    };
 
-        match topic.as_str() {
-            "post" => {
-                let record = deserialize::<PostRecord>(&data).unwrap();
-                println!(
-                    "{} record {{ timestamp: {}, id: {:?} }}",
-                    topic,
-                    record.timestamp(),
-                    record.id()
-                );
-            }
-            "comment" => {
-                let record = deserialize::<CommentRecord>(&data).unwrap();
-                println!(
-                    "{} record {{ timestamp: {}, id: {:?} }}",
-                    topic,
-                    record.timestamp(),
-                    record.id()
-                );
+    // Listen for messages (topic, frames)
+    loop {
+        broker.requests();
+        broker.flush();
+
+        if shutdown::requested() {
+            // Drain the messages in flight, then let the consumers finish
+            let mut drained = 0;
+            let mut done = false;
+            while let Some((topic, frames)) = broker.recv() {
+                drained += 1;
+                if broker.forward(topic, frames) {
+                    done = true;
+                    break;
+                }
             }
-            "like" => {
-                let record = deserialize::<LikeRecord>(&data).unwrap();
-                println!(
-                    "{} record {{ timestamp: {}, id: {:?} }}",
-                    topic,
-                    record.timestamp(),
-                    record.id()
-                );
+            eprintln!("Shutting down after draining {} messages", drained);
+            if !done {
+                broker.end_of_stream();
             }
-            _ => {}
+            break;
         }
-        send_socket.send(data, 0).unwrap();
 
-        // println!("{:?}", topic);
-        if topic == Topic::EOS.to_string() {
-            break;
+        // Producers wait while a blocking subscriber catches up
+        if broker.poll(!broker.subscribers.blocked()) {
+            if let Some((topic, frames)) = broker.recv() {
+                if broker.forward(topic, frames) {
+                    break;
+                }
+            }
         }
     }
+
+    broker.deliver();
 }
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+/// End of stream accounting of the producers publishing to the broker
+#[derive(Debug)]
+pub struct Producers {
+    expected: usize,
+    // Producer => Finished
+    producers: HashMap<String, bool>,
+}
+
+impl Producers {
+    /// Track producers, waiting for at least the expected number of them to finish
+    pub fn new(expected: usize) -> Self {
+        Producers {
+            expected,
+            producers: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, producer: &str) {
+        if !self.producers.contains_key(producer) {
+            self.producers.insert(producer.to_owned(), false);
+        }
+    }
+
+    /// Mark a producer as finished, producers that never registered are registered implicitly
+    pub fn finish(&mut self, producer: &str) {
+        self.producers.insert(producer.to_owned(), true);
+    }
+
+    /// Number of producers the end of stream is still waiting for
+    pub fn pending(&self) -> usize {
+        let finished = self
+            .producers
+            .values()
+            .filter(|finished| **finished)
+            .count();
+        let unfinished = self.producers.len() - finished;
+        unfinished.max(self.expected.saturating_sub(finished))
+    }
+
+    /// Whether every registered producer, and at least the expected number of them, finished
+    pub fn done(&self) -> bool {
+        self.pending() == 0
+    }
+}
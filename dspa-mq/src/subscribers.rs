@@ -0,0 +1,355 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use dspa_lib::envelope::Frames;
+use dspa_lib::metrics::{DROPPED_MESSAGES, QUEUED_MESSAGES, SPILLED_MESSAGES};
+use dspa_lib::partition::split_topic;
+use dspa_lib::subscription::{Policy, Request};
+
+/// Outcome of sending a message to a subscriber
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    /// The socket of the subscriber does not accept more messages right now
+    Full,
+    /// The subscriber disconnected
+    Gone,
+}
+
+/// Messages of a subscriber that did not fit its queue, in the order they were published
+struct Spill {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+    pending: usize,
+}
+
+impl Spill {
+    fn create(path: PathBuf) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let writer = BufWriter::new(File::create(&path)?);
+        let reader = BufReader::new(OpenOptions::new().read(true).open(&path)?);
+        Ok(Spill {
+            path,
+            writer,
+            reader,
+            pending: 0,
+        })
+    }
+
+    fn push(&mut self, topic: &str, frames: &[Vec<u8>]) -> io::Result<()> {
+        bincode::serialize_into(&mut self.writer, &(topic, frames)).map_err(io::Error::other)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> io::Result<Option<(String, Frames)>> {
+        if self.pending == 0 {
+            return Ok(None);
+        }
+
+        self.writer.flush()?;
+        let message = bincode::deserialize_from(&mut self.reader).map_err(io::Error::other)?;
+        self.pending -= 1;
+
+        // Start over once all messages were read, such that the file does not keep growing
+        if self.pending == 0 {
+            self.writer.get_ref().set_len(0)?;
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.reader.seek(SeekFrom::Start(0))?;
+        }
+        Ok(Some(message))
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether the topic carries control messages of the producers, which are never dropped
+fn is_control(topic: &str) -> bool {
+    matches!(split_topic(topic).0, "register" | "eos" | "watermark")
+}
+
+struct Subscriber {
+    name: String,
+    topics: Vec<String>,
+    policy: Policy,
+    messages: VecDeque<(String, Frames)>,
+    spill: Option<Spill>,
+    /// Remaining credits if the subscriber uses flow control
+    credit: Option<u64>,
+    dropped: u64,
+}
+
+impl Subscriber {
+    fn subscribed(&self, topic: &str) -> bool {
+        self.topics.iter().any(|prefix| topic.starts_with(prefix))
+    }
+
+    fn queued(&self) -> usize {
+        self.messages.len() + self.spill.as_ref().map_or(0, |spill| spill.pending)
+    }
+
+    fn drop_message(&mut self, topic: &str) {
+        self.dropped += 1;
+        DROPPED_MESSAGES
+            .with_label_values(&[&self.name, topic])
+            .inc();
+    }
+}
+
+/// Queues of the subscribers of the broker
+pub struct Subscribers {
+    hwm: usize,
+    policy: Policy,
+    spill_dir: PathBuf,
+    // Socket identity => Subscriber
+    subscribers: HashMap<Vec<u8>, Subscriber>,
+    // Whether the socket of a subscriber was full at the last flush
+    congested: bool,
+}
+
+impl Subscribers {
+    /// Queues holding up to `hwm` messages in memory, using the given policy for subscribers that
+    /// do not request one
+    pub fn new(hwm: usize, policy: Policy, spill_dir: PathBuf) -> Self {
+        Subscribers {
+            hwm,
+            policy,
+            spill_dir,
+            subscribers: HashMap::new(),
+            congested: false,
+        }
+    }
+
+    pub fn request(&mut self, identity: &[u8], request: Request) {
+        match request {
+            Request::Subscribe {
+                name,
+                topics,
+                policy,
+                credit,
+            } => {
+                let policy = policy.unwrap_or(self.policy);
+                eprintln!(
+                    "Subscriber {} subscribed to {:?} ({}{})",
+                    name,
+                    topics,
+                    policy,
+                    credit.map_or(String::new(), |credit| format!(", {} credits", credit))
+                );
+                self.subscribers.insert(
+                    identity.to_vec(),
+                    Subscriber {
+                        name,
+                        topics,
+                        policy,
+                        messages: VecDeque::new(),
+                        spill: None,
+                        credit,
+                        dropped: 0,
+                    },
+                );
+            }
+            Request::Credit(credit) => {
+                if let Some(subscriber) = self.subscribers.get_mut(identity) {
+                    if let Some(remaining) = subscriber.credit.as_mut() {
+                        *remaining += credit;
+                    }
+                }
+            }
+            Request::Unsubscribe => self.remove(identity),
+        }
+    }
+
+    fn remove(&mut self, identity: &[u8]) {
+        if let Some(subscriber) = self.subscribers.remove(identity) {
+            eprintln!(
+                "Subscriber {} unsubscribed, {} messages dropped, {} undelivered",
+                subscriber.name,
+                subscriber.dropped,
+                subscriber.queued()
+            );
+            QUEUED_MESSAGES
+                .with_label_values(&[&subscriber.name])
+                .set(0);
+        }
+    }
+
+    /// Queue a message for every subscriber of its topic, applying their policy to full queues.
+    /// Control messages are queued beyond the limit instead of being dropped, since consumers
+    /// would wait for a lost end of stream or watermark forever.
+    pub fn publish(&mut self, topic: &str, frames: &[Vec<u8>]) {
+        let hwm = self.hwm;
+        let spill_dir = &self.spill_dir;
+        let control = is_control(topic);
+
+        for subscriber in self.subscribers.values_mut() {
+            if !subscriber.subscribed(topic) {
+                continue;
+            }
+
+            let spilling = subscriber
+                .spill
+                .as_ref()
+                .map_or(false, |spill| spill.pending > 0);
+            if subscriber.messages.len() < hwm && !spilling {
+                subscriber
+                    .messages
+                    .push_back((topic.to_owned(), frames.to_vec()));
+                continue;
+            }
+
+            match subscriber.policy {
+                // The broker stops receiving while the queue is full
+                Policy::Block => subscriber
+                    .messages
+                    .push_back((topic.to_owned(), frames.to_vec())),
+                Policy::DropOldest | Policy::DropNewest if control => subscriber
+                    .messages
+                    .push_back((topic.to_owned(), frames.to_vec())),
+                Policy::DropOldest => {
+                    let oldest = subscriber
+                        .messages
+                        .iter()
+                        .position(|(queued, _)| !is_control(queued));
+                    if let Some((dropped, _)) =
+                        oldest.and_then(|oldest| subscriber.messages.remove(oldest))
+                    {
+                        subscriber.drop_message(&dropped);
+                    }
+                    subscriber
+                        .messages
+                        .push_back((topic.to_owned(), frames.to_vec()));
+                }
+                Policy::DropNewest => subscriber.drop_message(topic),
+                Policy::Spill => {
+                    if subscriber.spill.is_none() {
+                        let path =
+                            spill_dir.join(format!("{}.spill", subscriber.name.replace('/', "-")));
+                        match Spill::create(path) {
+                            Ok(spill) => subscriber.spill = Some(spill),
+                            Err(error) => eprintln!("Failed to create spill file: {}", error),
+                        }
+                    }
+                    let spilled = match subscriber.spill.as_mut() {
+                        Some(spill) => spill.push(topic, frames),
+                        None => Err(io::Error::other("No spill file")),
+                    };
+                    match spilled {
+                        Ok(()) => SPILLED_MESSAGES
+                            .with_label_values(&[&subscriber.name])
+                            .inc(),
+                        // Out of order, but still delivered
+                        Err(_) if control => subscriber
+                            .messages
+                            .push_back((topic.to_owned(), frames.to_vec())),
+                        Err(_) => subscriber.drop_message(topic),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a subscriber with the blocking policy has a full queue
+    pub fn blocked(&self) -> bool {
+        self.subscribers.values().any(|subscriber| {
+            subscriber.policy == Policy::Block && subscriber.messages.len() >= self.hwm
+        })
+    }
+
+    /// Whether all queued messages were delivered
+    pub fn is_empty(&self) -> bool {
+        self.subscribers
+            .values()
+            .all(|subscriber| subscriber.queued() == 0)
+    }
+
+    /// Whether the socket of a subscriber did not accept all its messages at the last flush, such
+    /// that the flush has to be retried
+    pub fn congested(&self) -> bool {
+        self.congested
+    }
+
+    /// Messages dropped for a subscriber so far
+    pub fn dropped(&self, identity: &[u8]) -> Option<u64> {
+        self.subscribers
+            .get(identity)
+            .map(|subscriber| subscriber.dropped)
+    }
+
+    /// Send queued messages as long as the subscribers have credits and their sockets accept
+    /// them, returns the number of sent messages
+    pub fn flush<F>(&mut self, mut send: F) -> usize
+    where
+        F: FnMut(&[u8], &str, &[Vec<u8>]) -> Delivery,
+    {
+        let hwm = self.hwm;
+        let mut sent = 0;
+        let mut gone = Vec::new();
+        self.congested = false;
+
+        for (identity, subscriber) in self.subscribers.iter_mut() {
+            while subscriber.credit != Some(0) {
+                let delivery = match subscriber.messages.front() {
+                    Some((topic, frames)) => send(identity, topic, frames),
+                    None => break,
+                };
+
+                match delivery {
+                    Delivery::Sent => {
+                        subscriber.messages.pop_front();
+                        if let Some(credit) = subscriber.credit.as_mut() {
+                            *credit -= 1;
+                        }
+                        sent += 1;
+                    }
+                    Delivery::Full => {
+                        self.congested = true;
+                        break;
+                    }
+                    Delivery::Gone => {
+                        gone.push(identity.clone());
+                        break;
+                    }
+                }
+            }
+
+            // Refill the queue from disk, an unreadable spill file loses its messages
+            let mut lost = 0;
+            if let Some(spill) = subscriber.spill.as_mut() {
+                while subscriber.messages.len() < hwm {
+                    match spill.pop() {
+                        Ok(Some(message)) => subscriber.messages.push_back(message),
+                        Ok(None) => break,
+                        Err(error) => {
+                            eprintln!("Failed to read spill file: {}", error);
+                            lost = spill.pending;
+                            subscriber.spill = None;
+                            break;
+                        }
+                    }
+                }
+            }
+            for _ in 0..lost {
+                subscriber.drop_message("spill");
+            }
+
+            QUEUED_MESSAGES
+                .with_label_values(&[&subscriber.name])
+                .set(subscriber.queued() as i64);
+        }
+
+        for identity in gone {
+            self.remove(&identity);
+        }
+        sent
+    }
+}
@@ -0,0 +1,32 @@
+use dspa_mq::Producers;
+
+#[test]
+fn end_of_stream_waits_for_all_registered_producers() {
+    let mut producers = Producers::new(1);
+    assert!(!producers.done());
+
+    producers.register("a");
+    producers.register("b");
+    producers.finish("a");
+    assert_eq!(producers.pending(), 1);
+
+    // Registering again does not reset a producer
+    producers.register("a");
+    producers.finish("b");
+    assert!(producers.done());
+}
+
+#[test]
+fn end_of_stream_waits_for_expected_producers() {
+    let mut producers = Producers::new(3);
+
+    // Producers that did not register are counted by their end of stream
+    producers.finish("a");
+    producers.register("b");
+    assert_eq!(producers.pending(), 2);
+
+    producers.finish("b");
+    assert_eq!(producers.pending(), 1);
+    producers.finish("c");
+    assert!(producers.done());
+}
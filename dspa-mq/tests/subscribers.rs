@@ -0,0 +1,151 @@
+use std::env;
+use std::fs;
+
+use dspa_lib::subscription::{Policy, Request};
+use dspa_mq::{Delivery, Subscribers};
+
+fn subscribe(subscribers: &mut Subscribers, identity: &[u8], policy: Policy, credit: Option<u64>) {
+    let request = Request::Subscribe {
+        name: format!("test-{}-{}", policy, identity[0]),
+        topics: vec!["post".to_owned(), "eos".to_owned()],
+        policy: Some(policy),
+        credit,
+    };
+    subscribers.request(identity, Request::decode(&request.encode()).unwrap());
+}
+
+fn publish(subscribers: &mut Subscribers, messages: &[u8]) {
+    for message in messages {
+        subscribers.publish(&format!("post.{}", message), &[vec![*message]]);
+    }
+}
+
+/// Deliver all messages the subscribers accept, returns them per subscriber
+fn deliver(subscribers: &mut Subscribers) -> Vec<(u8, u8)> {
+    let mut delivered = Vec::new();
+    subscribers.flush(|identity, _, frames| {
+        delivered.push((identity[0], frames[0][0]));
+        Delivery::Sent
+    });
+    delivered
+}
+
+fn subscribers() -> Subscribers {
+    Subscribers::new(2, Policy::DropNewest, env::temp_dir().join("dspa-mq-tests"))
+}
+
+#[test]
+fn full_queues_drop_according_to_policy() {
+    let mut subscribers = subscribers();
+    subscribe(&mut subscribers, &[0], Policy::DropNewest, None);
+    subscribe(&mut subscribers, &[1], Policy::DropOldest, None);
+
+    // Other topics are not queued
+    subscribers.publish("like.0", &[vec![9]]);
+    publish(&mut subscribers, &[1, 2, 3, 4]);
+    assert_eq!(subscribers.dropped(&[0]), Some(2));
+    assert_eq!(subscribers.dropped(&[1]), Some(2));
+
+    let mut delivered = deliver(&mut subscribers);
+    delivered.sort();
+    assert_eq!(delivered, vec![(0, 1), (0, 2), (1, 3), (1, 4)]);
+    assert!(subscribers.is_empty());
+}
+
+#[test]
+fn blocking_subscribers_hold_back_the_broker() {
+    let mut subscribers = subscribers();
+    subscribe(&mut subscribers, &[0], Policy::Block, None);
+
+    publish(&mut subscribers, &[1]);
+    assert!(!subscribers.blocked());
+    publish(&mut subscribers, &[2]);
+    assert!(subscribers.blocked());
+
+    // Sockets that do not accept messages keep them queued
+    assert_eq!(subscribers.flush(|_, _, _| Delivery::Full), 0);
+    assert!(subscribers.blocked());
+
+    assert_eq!(deliver(&mut subscribers), vec![(0, 1), (0, 2)]);
+    assert!(!subscribers.blocked());
+    assert_eq!(subscribers.dropped(&[0]), Some(0));
+}
+
+#[test]
+fn spilled_messages_are_delivered_in_order() {
+    let mut subscribers = subscribers();
+    subscribe(&mut subscribers, &[7], Policy::Spill, None);
+
+    publish(&mut subscribers, &[1, 2, 3, 4, 5]);
+    assert_eq!(subscribers.dropped(&[7]), Some(0));
+
+    let mut delivered = Vec::new();
+    while !subscribers.is_empty() {
+        delivered.extend(deliver(&mut subscribers));
+        // Messages published while spilling queue up behind the spilled ones
+        if delivered.len() == 2 {
+            publish(&mut subscribers, &[6]);
+        }
+    }
+    assert_eq!(
+        delivered.iter().map(|(_, data)| *data).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4, 5, 6]
+    );
+
+    // The drained spill file is truncated
+    let spill = env::temp_dir()
+        .join("dspa-mq-tests")
+        .join("test-spill-7.spill");
+    assert_eq!(fs::metadata(spill).unwrap().len(), 0);
+}
+
+#[test]
+fn control_messages_are_never_dropped() {
+    let mut subscribers = subscribers();
+    subscribe(&mut subscribers, &[0], Policy::DropNewest, None);
+    subscribe(&mut subscribers, &[1], Policy::DropOldest, None);
+
+    subscribers.publish("eos", &[vec![0]]);
+    publish(&mut subscribers, &[1, 2, 3]);
+    subscribers.publish("eos", &[vec![4]]);
+    assert_eq!(subscribers.dropped(&[0]), Some(2));
+    assert_eq!(subscribers.dropped(&[1]), Some(2));
+
+    let mut delivered = deliver(&mut subscribers);
+    delivered.sort();
+    assert_eq!(
+        delivered,
+        vec![(0, 0), (0, 1), (0, 4), (1, 0), (1, 3), (1, 4)]
+    );
+}
+
+#[test]
+fn credits_limit_the_messages_in_flight() {
+    let mut subscribers = subscribers();
+    subscribe(&mut subscribers, &[0], Policy::DropNewest, Some(1));
+
+    publish(&mut subscribers, &[1, 2]);
+    assert_eq!(deliver(&mut subscribers), vec![(0, 1)]);
+    assert!(deliver(&mut subscribers).is_empty());
+
+    // Waiting for credits is not retried, only full sockets are
+    assert!(!subscribers.congested());
+    subscribers.request(&[0], Request::Credit(1));
+    assert_eq!(subscribers.flush(|_, _, _| Delivery::Full), 0);
+    assert!(subscribers.congested());
+
+    assert_eq!(deliver(&mut subscribers), vec![(0, 2)]);
+    assert!(!subscribers.congested());
+}
+
+#[test]
+fn disconnected_subscribers_are_removed() {
+    let mut subscribers = subscribers();
+    subscribe(&mut subscribers, &[0], Policy::Block, None);
+    publish(&mut subscribers, &[1, 2]);
+
+    subscribers.flush(|_, _, _| Delivery::Gone);
+    assert_eq!(subscribers.dropped(&[0]), None);
+    assert!(!subscribers.blocked());
+    assert!(subscribers.is_empty());
+}
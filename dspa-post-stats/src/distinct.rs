@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Smallest and largest supported precision of `HyperLogLog`
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 16;
+
+/// Stable 64-bit hash of a person id, such that sketches agree across workers and restores
+#[inline]
+fn hash(value: i32) -> u64 {
+    // Finalizer of SplitMix64
+    let mut z = (value as u32 as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Approximate distinct counter with `2^precision` registers and a standard error of about
+/// `1.04 / sqrt(2^precision)`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Registers,
+}
+
+/// Registers of a `HyperLogLog`, only the set ones are stored until a sixteenth of them are set.
+/// The representation only depends on the set registers, such that equal sketches compare equal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Registers {
+    // Index => Rank of the set registers
+    Sparse(BTreeMap<u16, u8>),
+    Dense(Vec<u8>),
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Self {
+        assert!(
+            precision >= MIN_PRECISION && precision <= MAX_PRECISION,
+            "The precision must be between {} and {}",
+            MIN_PRECISION,
+            MAX_PRECISION
+        );
+
+        HyperLogLog {
+            precision,
+            registers: Registers::Sparse(BTreeMap::new()),
+        }
+    }
+
+    /// Number of registers
+    fn len(&self) -> usize {
+        1 << self.precision
+    }
+
+    /// Raise a register to the given rank
+    fn set(&mut self, index: u16, rank: u8) {
+        let len = self.len();
+        match &mut self.registers {
+            Registers::Sparse(registers) => {
+                let register = registers.entry(index).or_insert(0);
+                *register = (*register).max(rank);
+
+                if registers.len() > len / 16 {
+                    let mut dense = vec![0; len];
+                    for (index, rank) in registers.iter() {
+                        dense[*index as usize] = *rank;
+                    }
+                    self.registers = Registers::Dense(dense);
+                }
+            }
+            Registers::Dense(registers) => {
+                let register = &mut registers[index as usize];
+                *register = (*register).max(rank);
+            }
+        }
+    }
+
+    /// Index and rank of the set registers
+    fn registers(&self) -> Box<dyn Iterator<Item = (u16, u8)> + '_> {
+        match &self.registers {
+            Registers::Sparse(registers) => {
+                Box::new(registers.iter().map(|(index, rank)| (*index, *rank)))
+            }
+            Registers::Dense(registers) => Box::new(
+                registers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, rank)| **rank > 0)
+                    .map(|(index, rank)| (index as u16, *rank)),
+            ),
+        }
+    }
+
+    pub fn insert(&mut self, value: i32) {
+        let hash = hash(value);
+        let index = (hash >> (64 - self.precision)) as u16;
+        // Position of the first set bit in the remaining bits, the bits of the index are set to
+        // bound the rank
+        let rank = ((hash << self.precision) | (1 << (self.precision - 1))).leading_zeros() + 1;
+
+        self.set(index, rank as u8);
+    }
+
+    /// Union with a sketch of the same precision
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        assert_eq!(
+            self.precision, other.precision,
+            "Only sketches of the same precision can be merged"
+        );
+        for (index, rank) in other.registers() {
+            self.set(index, rank);
+        }
+    }
+
+    pub fn estimate(&self) -> f64 {
+        let m = self.len() as f64;
+        let alpha = match self.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        // Registers that are not set contribute 2^0 each
+        let (mut sum, mut set) = (0.0, 0);
+        for (_, rank) in self.registers() {
+            sum += 2f64.powi(-i32::from(rank));
+            set += 1;
+        }
+        let zeros = self.len() - set;
+        sum += zeros as f64;
+        let estimate = alpha * m * m / sum;
+
+        // Linear counting for small cardinalities
+        if estimate <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+}
+
+/// Counting of the unique users of a post
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Counting {
+    Exact,
+    /// `HyperLogLog` with the given precision
+    Approximate(u8),
+    /// Exact count and `HyperLogLog` estimate with the given precision, reporting the estimate
+    Compare(u8),
+}
+
+impl Default for Counting {
+    fn default() -> Self {
+        Counting::Exact
+    }
+}
+
+/// Mode of `Counting` without its precision
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CountingMode {
+    Exact,
+    Approximate,
+    Compare,
+}
+
+impl fmt::Display for CountingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CountingMode::Exact => write!(f, "exact"),
+            CountingMode::Approximate => write!(f, "approximate"),
+            CountingMode::Compare => write!(f, "compare"),
+        }
+    }
+}
+
+impl FromStr for CountingMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "exact" => Ok(CountingMode::Exact),
+            "approximate" => Ok(CountingMode::Approximate),
+            "compare" => Ok(CountingMode::Compare),
+            _ => Err(format!("Unknown counting mode: {}", value)),
+        }
+    }
+}
+
+/// Unique users of a post, counted according to a `Counting`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Users {
+    Exact(HashSet<i32>),
+    Approximate(HyperLogLog),
+    Compare(HashSet<i32>, HyperLogLog),
+}
+
+impl Default for Users {
+    fn default() -> Self {
+        Users::new(Counting::Exact)
+    }
+}
+
+impl Users {
+    pub fn new(counting: Counting) -> Self {
+        match counting {
+            Counting::Exact => Users::Exact(HashSet::new()),
+            Counting::Approximate(precision) => Users::Approximate(HyperLogLog::new(precision)),
+            Counting::Compare(precision) => {
+                Users::Compare(HashSet::new(), HyperLogLog::new(precision))
+            }
+        }
+    }
+
+    pub fn insert(&mut self, person_id: i32) {
+        match self {
+            Users::Exact(users) => {
+                users.insert(person_id);
+            }
+            Users::Approximate(sketch) => sketch.insert(person_id),
+            Users::Compare(users, sketch) => {
+                users.insert(person_id);
+                sketch.insert(person_id);
+            }
+        }
+    }
+
+    /// Union with the users of another post counted the same way
+    pub fn merge(&mut self, other: &Users) {
+        match (self, other) {
+            (Users::Exact(users), Users::Exact(other)) => users.extend(other),
+            (Users::Approximate(sketch), Users::Approximate(other)) => sketch.merge(other),
+            (Users::Compare(users, sketch), Users::Compare(other_users, other_sketch)) => {
+                users.extend(other_users);
+                sketch.merge(other_sketch);
+            }
+            _ => panic!("Only users counted the same way can be merged"),
+        }
+    }
+
+    /// Number of unique users, estimated unless counted exactly
+    pub fn len(&self) -> usize {
+        match self {
+            Users::Exact(users) => users.len(),
+            Users::Approximate(sketch) | Users::Compare(_, sketch) => {
+                sketch.estimate().round() as usize
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Relative error of the estimate when comparing it to the exact count
+    pub fn error(&self) -> Option<f64> {
+        match self {
+            Users::Compare(users, sketch) if !users.is_empty() => {
+                Some((sketch.estimate() - users.len() as f64).abs() / users.len() as f64)
+            }
+            Users::Compare(_, _) => Some(0.0),
+            _ => None,
+        }
+    }
+}
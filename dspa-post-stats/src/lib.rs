@@ -1,20 +1,622 @@
-use std::collections::HashSet;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate lazy_static;
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 
+use chrono::{DateTime, TimeZone, Utc};
+use either::Either;
 use serde_derive::{Deserialize, Serialize};
+use structopt::clap::{Error as UsageError, ErrorKind};
+use structopt::StructOpt;
+
+use dspa_lib::checkpoint::CheckpointArgs;
+use dspa_lib::deadletter::DeadLetterArgs;
+use dspa_lib::error::{DspaError, FailedRecord, Result};
+use dspa_lib::metrics::MetricsArgs;
+use dspa_lib::partition::PartitionArgs;
+use dspa_lib::records::CommentRecord;
+use dspa_lib::schema::{post_stats, post_stats_change, rollup, top_post, trending_tag};
+use dspa_lib::sink::{ResultRow, SinkArgs};
+use dspa_lib::store::{SocialGraphStore, StoreArgs};
+use dspa_lib::subscription::SubscriptionArgs;
+use dspa_lib::TimelyArgs;
+
+use crate::distinct::{Counting, CountingMode, Users, MAX_PRECISION, MIN_PRECISION};
+use crate::live::QueryArgs;
+use crate::rollups::{Rollup, RollupArgs};
+use crate::trending::{Trending, TrendingArgs, TrendingTag};
 
+pub mod distinct;
+pub mod live;
 pub mod operators;
+pub mod rollups;
+pub mod trending;
+
+lazy_static! {
+    pub static ref ARGS: Args = Args::from_args();
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "dspa-post-stats")]
+pub struct Args {
+    #[structopt(long = "seed")]
+    /// Start from the activity in the database within the TTL before the first received event, for processors started mid-stream
+    pub seed: bool,
+    #[structopt(long = "changes")]
+    /// Only report posts that changed since their last report and posts becoming active or inactive
+    pub changes: bool,
+    #[structopt(flatten)]
+    pub ranking: RankingArgs,
+    #[structopt(flatten)]
+    pub schedule: ScheduleArgs,
+    #[structopt(flatten)]
+    pub retention: RetentionArgs,
+    #[structopt(flatten)]
+    pub counting: CountingArgs,
+    #[structopt(flatten)]
+    pub rollup: RollupArgs,
+    #[structopt(flatten)]
+    pub trending: TrendingArgs,
+    #[structopt(flatten)]
+    pub query: QueryArgs,
+    #[structopt(flatten)]
+    pub store: StoreArgs,
+    #[structopt(flatten)]
+    pub checkpoint: CheckpointArgs,
+    #[structopt(flatten)]
+    pub sink: SinkArgs,
+    #[structopt(flatten)]
+    pub metrics: MetricsArgs,
+    #[structopt(flatten)]
+    pub partition: PartitionArgs,
+    #[structopt(flatten)]
+    pub subscription: SubscriptionArgs,
+    #[structopt(flatten)]
+    pub deadletter: DeadLetterArgs,
+    #[structopt(flatten)]
+    pub timely: TimelyArgs,
+}
+
+impl Args {
+    /// Configuration of `PostStats`, exits with a usage error if the arguments are inconsistent
+    pub fn config(&self) -> PostStatsConfig {
+        PostStatsConfig {
+            schedule: self
+                .schedule
+                .schedule()
+                .unwrap_or_else(|error| error.exit()),
+            retention: self.retention.retention(),
+            counting: self
+                .counting
+                .counting()
+                .unwrap_or_else(|error| error.exit()),
+            seed: self.seed,
+        }
+    }
+
+    /// File to write the rollups to, `None` for stdout or if they are not reported. Exits with a
+    /// usage error if they would be mixed with the other results.
+    pub fn rollup_path(&self) -> Option<PathBuf> {
+        if !self.rollup.rollups {
+            return None;
+        }
+        self.sink
+            .additional_path("rollup", self.rollup.rollup_path.as_ref())
+            .unwrap_or_else(|error| error.exit())
+    }
+
+    /// File to write the trending tags to, `None` for stdout or if they are not reported. Exits
+    /// with a usage error if they would be mixed with the other results.
+    pub fn trending_path(&self) -> Option<PathBuf> {
+        self.trending.trending?;
+        self.sink
+            .additional_path("trending", self.trending.trending_path.as_ref())
+            .unwrap_or_else(|error| error.exit())
+    }
+
+    /// Scoring of the trending tags if they are reported, exits with a usage error if the
+    /// arguments are inconsistent
+    pub fn trending(&self) -> Option<Trending> {
+        self.trending.trending?;
+        Some(
+            self.trending
+                .trending()
+                .unwrap_or_else(|error| error.exit()),
+        )
+    }
+}
+
+/// Configuration of `PostStats`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PostStatsConfig {
+    pub schedule: Schedule,
+    pub retention: Retention,
+    pub counting: Counting,
+    /// Start from the activity in the store before the input frontier
+    pub seed: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CountingArgs {
+    #[structopt(long = "users-counting", default_value = "exact")]
+    /// Counting of the unique users: exact, approximate (HyperLogLog) or compare (both, reporting the estimate and its error on stderr)
+    pub mode: CountingMode,
+    #[structopt(long = "precision", default_value = "12")]
+    /// Precision of the HyperLogLog sketches, between 4 and 16, each uses up to 2^precision bytes
+    pub precision: u8,
+}
+
+impl CountingArgs {
+    /// Counting of the unique users, or a usage error if the precision is not supported
+    pub fn counting(&self) -> std::result::Result<Counting, UsageError> {
+        if self.precision < MIN_PRECISION || self.precision > MAX_PRECISION {
+            return Err(UsageError::with_description(
+                &format!(
+                    "The precision must be between {} and {}",
+                    MIN_PRECISION, MAX_PRECISION
+                ),
+                ErrorKind::ValueValidation,
+            ));
+        }
+
+        Ok(match self.mode {
+            CountingMode::Exact => Counting::Exact,
+            CountingMode::Approximate => Counting::Approximate(self.precision),
+            CountingMode::Compare => Counting::Compare(self.precision),
+        })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ScheduleArgs {
+    #[structopt(long = "ttl", default_value = "43200")]
+    /// Event time in seconds without activity after which a post becomes inactive
+    pub ttl: u64,
+    #[structopt(long = "report-interval", default_value = "1800")]
+    /// Event time in seconds between reports of the active posts
+    pub report_interval: u64,
+    #[structopt(long = "users-interval")]
+    /// Event time in seconds between updates of the unique users, a multiple of the report interval (default: every report)
+    pub users_interval: Option<u64>,
+    #[structopt(long = "counts-interval")]
+    /// Event time in seconds between updates of the counts and reply depths, a multiple of the report interval (default: every report)
+    pub counts_interval: Option<u64>,
+    #[structopt(long = "report-offset", default_value = "0")]
+    /// Event time in seconds the reports are shifted by from multiples of the report interval
+    pub report_offset: u64,
+}
+
+impl ScheduleArgs {
+    /// Schedule of the reports, or a usage error if the intervals are inconsistent
+    pub fn schedule(&self) -> std::result::Result<Schedule, UsageError> {
+        let interval = self.report_interval;
+        if interval == 0 {
+            return Err(UsageError::with_description(
+                "The report interval must be positive",
+                ErrorKind::ValueValidation,
+            ));
+        }
+
+        let users_interval = self.users_interval.unwrap_or(interval);
+        let counts_interval = self.counts_interval.unwrap_or(interval);
+        for metric_interval in &[users_interval, counts_interval] {
+            if *metric_interval == 0 || *metric_interval % interval != 0 {
+                return Err(UsageError::with_description(
+                    "Metric intervals must be multiples of the report interval",
+                    ErrorKind::ValueValidation,
+                ));
+            }
+        }
+
+        Ok(Schedule {
+            ttl: self.ttl,
+            interval,
+            users_interval,
+            counts_interval,
+            offset: self.report_offset % interval,
+        })
+    }
+}
+
+/// Inactivity timeout and report times of `PostStats`, all in seconds of event time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    pub ttl: u64,
+    pub interval: u64,
+    pub users_interval: u64,
+    pub counts_interval: u64,
+    pub offset: u64,
+}
+
+impl Default for Schedule {
+    /// Reports every 30 minutes, posts become inactive after 12 hours
+    fn default() -> Self {
+        Schedule {
+            ttl: 12 * 60 * 60,
+            interval: 30 * 60,
+            users_interval: 30 * 60,
+            counts_interval: 30 * 60,
+            offset: 0,
+        }
+    }
+}
+
+impl Schedule {
+    /// First report time at or after the given time
+    pub fn next(&self, time: u64) -> u64 {
+        if time <= self.offset {
+            return self.offset;
+        }
+
+        let rem = (time - self.offset) % self.interval;
+        if rem == 0 {
+            time
+        } else {
+            time + self.interval - rem
+        }
+    }
+
+    pub fn is_report(&self, time: u64) -> bool {
+        time >= self.offset && (time - self.offset) % self.interval == 0
+    }
+
+    /// Whether the unique users are updated in the report at the given time
+    pub fn reports_users(&self, time: u64) -> bool {
+        time >= self.offset && (time - self.offset) % self.users_interval == 0
+    }
+
+    /// Whether the counts and reply depths are updated in the report at the given time
+    pub fn reports_counts(&self, time: u64) -> bool {
+        time >= self.offset && (time - self.offset) % self.counts_interval == 0
+    }
+}
+
+/// Handling of activity on posts that became inactive
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reactivation {
+    /// Posts stay inactive
+    Ignore,
+    /// Posts become active again with new counters
+    Reset,
+    /// Posts become active again with the counters of their whole lifetime
+    Keep,
+}
+
+impl fmt::Display for Reactivation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reactivation::Ignore => write!(f, "ignore"),
+            Reactivation::Reset => write!(f, "reset"),
+            Reactivation::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+impl FromStr for Reactivation {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "ignore" => Ok(Reactivation::Ignore),
+            "reset" => Ok(Reactivation::Reset),
+            "keep" => Ok(Reactivation::Keep),
+            _ => Err(format!("Unknown reactivation policy: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RetentionArgs {
+    #[structopt(long = "reactivation", default_value = "ignore")]
+    /// Handling of activity on inactive posts: ignore, reset (new counters) or keep (lifetime counters)
+    pub reactivation: Reactivation,
+    #[structopt(long = "forget-after", default_value = "604800")]
+    /// Event time in seconds after which inactive posts are forgotten and tracked like new posts, 0 never forgets them
+    pub forget_after: u64,
+}
+
+impl RetentionArgs {
+    pub fn retention(&self) -> Retention {
+        Retention {
+            reactivation: self.reactivation,
+            forget_after: self.forget_after,
+        }
+    }
+}
+
+/// Handling of inactive posts by `PostStats`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Retention {
+    pub reactivation: Reactivation,
+    /// Event time in seconds an inactive post is remembered for, 0 remembers it forever
+    pub forget_after: u64,
+}
+
+impl Default for Retention {
+    /// Inactive posts are ignored for a week
+    fn default() -> Self {
+        Retention {
+            reactivation: Reactivation::Ignore,
+            forget_after: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// Metric the active posts are ranked by
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Comments and replies
+    Comments,
+    Likes,
+    Users,
+    /// Weighted sum of the counts and users
+    Engagement,
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Metric::Comments => write!(f, "comments"),
+            Metric::Likes => write!(f, "likes"),
+            Metric::Users => write!(f, "users"),
+            Metric::Engagement => write!(f, "engagement"),
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "comments" => Ok(Metric::Comments),
+            "likes" => Ok(Metric::Likes),
+            "users" => Ok(Metric::Users),
+            "engagement" => Ok(Metric::Engagement),
+            _ => Err(format!("Unknown metric: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RankingArgs {
+    #[structopt(long = "top", raw(conflicts_with = r#""changes""#))]
+    /// Only report the given number of active posts with the highest score at every report
+    pub top: Option<usize>,
+    #[structopt(long = "rank-by", default_value = "engagement")]
+    /// Score of the posts: comments, likes, users or engagement
+    pub metric: Metric,
+    #[structopt(long = "comment-weight", default_value = "2")]
+    /// Weight of a comment on the post in the engagement score
+    pub comment_weight: f64,
+    #[structopt(long = "reply-weight", default_value = "1")]
+    /// Weight of a reply in the engagement score
+    pub reply_weight: f64,
+    #[structopt(long = "like-weight", default_value = "1")]
+    /// Weight of a like in the engagement score
+    pub like_weight: f64,
+    #[structopt(long = "user-weight", default_value = "1")]
+    /// Weight of a unique user in the engagement score
+    pub user_weight: f64,
+}
+
+impl RankingArgs {
+    pub fn ranking(&self) -> Ranking {
+        Ranking {
+            metric: self.metric,
+            comment_weight: self.comment_weight,
+            reply_weight: self.reply_weight,
+            like_weight: self.like_weight,
+            user_weight: self.user_weight,
+        }
+    }
+}
+
+/// Score of the active posts for the top posts
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ranking {
+    pub metric: Metric,
+    pub comment_weight: f64,
+    pub reply_weight: f64,
+    pub like_weight: f64,
+    pub user_weight: f64,
+}
+
+impl Default for Ranking {
+    fn default() -> Self {
+        Ranking {
+            metric: Metric::Engagement,
+            comment_weight: 2.0,
+            reply_weight: 1.0,
+            like_weight: 1.0,
+            user_weight: 1.0,
+        }
+    }
+}
+
+impl Ranking {
+    pub fn score(&self, post: &ActivePost) -> f64 {
+        match self.metric {
+            Metric::Comments => (post.comments + post.replies) as f64,
+            Metric::Likes => post.likes as f64,
+            Metric::Users => post.users() as f64,
+            Metric::Engagement => {
+                self.comment_weight * post.comments as f64
+                    + self.reply_weight * post.replies as f64
+                    + self.like_weight * post.likes as f64
+                    + self.user_weight * post.users() as f64
+            }
+        }
+    }
+
+    /// The `k` posts with the highest score in descending order, ties are broken by post id
+    pub fn top(&self, posts: Vec<ActivePost>, k: usize) -> Vec<RankedPost> {
+        let mut scored = posts
+            .into_iter()
+            .map(|post| (self.score(&post), post))
+            .collect::<Vec<_>>();
+        scored.sort_by(|(a, a_post), (b, b_post)| {
+            b.partial_cmp(a)
+                .unwrap_or(Ordering::Equal)
+                .then(a_post.id.cmp(&b_post.id))
+        });
+        scored.truncate(k);
+
+        scored
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (score, post))| RankedPost {
+                rank: rank + 1,
+                score,
+                post,
+            })
+            .collect()
+    }
+}
+
+/// Active post with its rank among the top posts of a report, starting at 1
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RankedPost {
+    pub rank: usize,
+    pub score: f64,
+    pub post: ActivePost,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ActivePostEvent {
-    Comment { post_id: i32, person_id: i32 },
-    Like { post_id: i32, person_id: i32 },
+    Comment {
+        post_id: i32,
+        person_id: i32,
+    },
+    /// Reply to a comment, with its depth below the comment on the post starting at 1
+    Reply {
+        post_id: i32,
+        person_id: i32,
+        depth: u32,
+    },
+    Like {
+        post_id: i32,
+        person_id: i32,
+    },
 }
 
 impl ActivePostEvent {
+    /// Event of a comment on its root post, replies are told apart by their parent
+    pub fn comment(comment: &CommentRecord, store: &dyn SocialGraphStore) -> Result<Self> {
+        let (post, depth) = comment.thread(store)?.ok_or(DspaError::MissingParent {
+            comment_id: comment.id,
+        })?;
+
+        Ok(match comment.parent_id() {
+            Either::Left(_) => ActivePostEvent::Comment {
+                post_id: post.id,
+                person_id: comment.person_id,
+            },
+            Either::Right(_) => ActivePostEvent::Reply {
+                post_id: post.id,
+                person_id: comment.person_id,
+                depth: depth - 1,
+            },
+        })
+    }
+
+    /// Events of the activity in the store at or after `from` and before `to` in seconds of
+    /// event time, with their time in time order. The threads of the comments are resolved with
+    /// one query per reply level above the range, instead of one per comment.
+    pub fn between(
+        store: &dyn SocialGraphStore,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<(u64, ActivePostEvent)>> {
+        let (from, to) = (Utc.timestamp(from as i64, 0), Utc.timestamp(to as i64, 0));
+        let comments = store.comments_between(from, to)?;
+
+        // Comment => Parent, of the comments in the range and their ancestors
+        let mut parents: HashMap<i32, Either<i32, i32>> = comments
+            .iter()
+            .map(|comment| (comment.id, comment.parent_id()))
+            .collect();
+        let mut queried = HashSet::new();
+        loop {
+            let mut missing = parents
+                .values()
+                .filter_map(|parent| parent.right())
+                .filter(|id| !parents.contains_key(id) && !queried.contains(id))
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                break;
+            }
+            missing.sort();
+            missing.dedup();
+
+            for comment in store.comments(&missing)? {
+                parents.insert(comment.id, comment.parent_id());
+            }
+            queried.extend(missing);
+        }
+
+        let mut events = Vec::new();
+        for comment in comments {
+            match Self::resolve(&comment, &parents) {
+                Ok(event) => events.push((comment.creation_date.timestamp() as u64, event)),
+                Err(error) => eprintln!("{}", FailedRecord::new(&comment, error)),
+            }
+        }
+        for like in store.likes_between(from, to)? {
+            events.push((
+                like.creation_date.timestamp() as u64,
+                ActivePostEvent::Like {
+                    post_id: like.post_id,
+                    person_id: like.person_id,
+                },
+            ));
+        }
+
+        events.sort_by_key(|(time, _)| *time);
+        Ok(events)
+    }
+
+    /// Event of a comment, following its ancestors up to the root post
+    fn resolve(comment: &CommentRecord, parents: &HashMap<i32, Either<i32, i32>>) -> Result<Self> {
+        let mut parent = comment.parent_id();
+        let mut depth = 0;
+        let post_id = loop {
+            match parent {
+                Either::Left(post_id) => break post_id,
+                Either::Right(comment_id) => {
+                    parent = *parents.get(&comment_id).ok_or(DspaError::MissingParent {
+                        comment_id: comment.id,
+                    })?;
+                    depth += 1;
+                }
+            }
+        };
+
+        Ok(if depth == 0 {
+            ActivePostEvent::Comment {
+                post_id,
+                person_id: comment.person_id,
+            }
+        } else {
+            ActivePostEvent::Reply {
+                post_id,
+                person_id: comment.person_id,
+                depth,
+            }
+        })
+    }
+
     pub fn id(&self) -> i32 {
         match self {
             ActivePostEvent::Comment { post_id, .. } => *post_id,
+            ActivePostEvent::Reply { post_id, .. } => *post_id,
             ActivePostEvent::Like { post_id, .. } => *post_id,
         }
     }
@@ -23,45 +625,430 @@ impl ActivePostEvent {
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ActivePost {
     id: i32,
-    users: HashSet<i32>,
+    // Shared with the reports of the post, copied once the post changes after a report
+    users: Arc<Users>,
     comments: u64,
+    replies: u64,
     likes: u64,
+    // Sum of the depths of all replies
+    reply_depths: u64,
+    max_reply_depth: u32,
 }
 
 impl fmt::Display for ActivePost {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Active Post {{ id: {:8}, users: {:8}, comments: {:8}, likes: {:8} }}",
+            "Active Post {{ id: {:8}, users: {:8}, comments: {:8}, replies: {:8}, likes: {:8}, reply depth: {:3} max {:6.2} mean }}",
             self.id,
             self.users.len(),
             self.comments,
-            self.likes
+            self.replies,
+            self.likes,
+            self.max_reply_depth,
+            self.mean_reply_depth()
         )
     }
 }
 
 impl ActivePost {
-    pub fn new(id: i32) -> Self {
+    pub fn new(id: i32, counting: Counting) -> Self {
         ActivePost {
             id,
-            users: Default::default(),
+            users: Arc::new(Users::new(counting)),
             comments: Default::default(),
+            replies: Default::default(),
             likes: Default::default(),
+            reply_depths: Default::default(),
+            max_reply_depth: Default::default(),
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Number of unique people that commented, replied or liked, estimated unless counted exactly
+    pub fn users(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Relative error of the estimated unique people when comparing it to the exact count
+    pub fn users_error(&self) -> Option<f64> {
+        self.users.error()
+    }
+
+    /// Number of comments on the post itself
+    pub fn comments(&self) -> u64 {
+        self.comments
+    }
+
+    /// Number of replies to comments
+    pub fn replies(&self) -> u64 {
+        self.replies
+    }
+
+    pub fn likes(&self) -> u64 {
+        self.likes
+    }
+
+    pub fn max_reply_depth(&self) -> u32 {
+        self.max_reply_depth
+    }
+
+    /// Mean depth of the replies, 0 without replies
+    pub fn mean_reply_depth(&self) -> f64 {
+        if self.replies == 0 {
+            0.0
+        } else {
+            self.reply_depths as f64 / self.replies as f64
         }
     }
 
+    /// Report of the post, metrics that are not updated keep the value of the previous report
+    pub fn report(&self, previous: Option<&ActivePost>, users: bool, counts: bool) -> ActivePost {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return self.clone(),
+        };
+
+        let mut report = if counts {
+            self.clone()
+        } else {
+            previous.clone()
+        };
+        report.users = if users {
+            self.users.clone()
+        } else {
+            previous.users.clone()
+        };
+        report
+    }
+
     pub fn update(&mut self, event: ActivePostEvent) {
         match event {
             ActivePostEvent::Comment { person_id, .. } => {
                 self.comments += 1;
-                self.users.insert(person_id);
+                Arc::make_mut(&mut self.users).insert(person_id);
+            }
+            ActivePostEvent::Reply {
+                person_id, depth, ..
+            } => {
+                self.replies += 1;
+                self.reply_depths += u64::from(depth);
+                self.max_reply_depth = self.max_reply_depth.max(depth);
+                Arc::make_mut(&mut self.users).insert(person_id);
             }
             ActivePostEvent::Like { person_id, .. } => {
                 self.likes += 1;
-                self.users.insert(person_id);
+                Arc::make_mut(&mut self.users).insert(person_id);
             }
-            _ => {}
         }
     }
 }
+
+/// Change of an active post since its previous report
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Change {
+    /// First report of the post
+    Activated,
+    Updated,
+    Unchanged,
+    /// The post expired since its previous report, which is repeated
+    Deactivated,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Activated => write!(f, "activated"),
+            Change::Updated => write!(f, "updated"),
+            Change::Unchanged => write!(f, "unchanged"),
+            Change::Deactivated => write!(f, "deactivated"),
+        }
+    }
+}
+
+/// Report of a post with its change since the previous report
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostReport {
+    pub change: Change,
+    pub post: ActivePost,
+}
+
+/// Statistics of an active post at a reporting timestamp
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Insertable)]
+#[table_name = "post_stats"]
+pub struct PostStatsRow {
+    pub timestamp: DateTime<Utc>,
+    pub post_id: i32,
+    pub users: i32,
+    pub comments: i64,
+    pub likes: i64,
+    pub replies: i64,
+    pub max_reply_depth: i32,
+    pub mean_reply_depth: f64,
+}
+
+impl fmt::Display for PostStatsRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Active Post {{ id: {:8}, users: {:8}, comments: {:8}, replies: {:8}, likes: {:8}, reply depth: {:3} max {:6.2} mean }}",
+            self.post_id,
+            self.users,
+            self.comments,
+            self.replies,
+            self.likes,
+            self.max_reply_depth,
+            self.mean_reply_depth
+        )
+    }
+}
+
+impl ResultRow for PostStatsRow {
+    type Record = ActivePost;
+    type Table = post_stats::table;
+
+    const NAME: &'static str = "post_stats";
+
+    fn rows(timestamp: u64, post: &ActivePost) -> Vec<Self> {
+        vec![PostStatsRow {
+            timestamp: Utc.timestamp(timestamp as i64, 0),
+            post_id: post.id,
+            users: post.users.len() as i32,
+            comments: post.comments as i64,
+            likes: post.likes as i64,
+            replies: post.replies as i64,
+            max_reply_depth: post.max_reply_depth as i32,
+            mean_reply_depth: post.mean_reply_depth(),
+        }]
+    }
+
+    fn table() -> Self::Table {
+        post_stats::table
+    }
+}
+
+/// Change of an active post at a reporting timestamp
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Insertable)]
+#[table_name = "post_stats_change"]
+pub struct PostChangeRow {
+    pub timestamp: DateTime<Utc>,
+    pub post_id: i32,
+    pub change: String,
+    pub users: i32,
+    pub comments: i64,
+    pub likes: i64,
+    pub replies: i64,
+    pub max_reply_depth: i32,
+    pub mean_reply_depth: f64,
+}
+
+impl fmt::Display for PostChangeRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Post Change {{ id: {:8}, change: {:11}, users: {:8}, comments: {:8}, replies: {:8}, likes: {:8}, reply depth: {:3} max {:6.2} mean }}",
+            self.post_id,
+            self.change,
+            self.users,
+            self.comments,
+            self.replies,
+            self.likes,
+            self.max_reply_depth,
+            self.mean_reply_depth
+        )
+    }
+}
+
+impl ResultRow for PostChangeRow {
+    type Record = PostReport;
+    type Table = post_stats_change::table;
+
+    const NAME: &'static str = "post_stats_change";
+
+    fn rows(timestamp: u64, report: &PostReport) -> Vec<Self> {
+        PostStatsRow::rows(timestamp, &report.post)
+            .into_iter()
+            .map(|row| PostChangeRow {
+                timestamp: row.timestamp,
+                post_id: row.post_id,
+                change: report.change.to_string(),
+                users: row.users,
+                comments: row.comments,
+                likes: row.likes,
+                replies: row.replies,
+                max_reply_depth: row.max_reply_depth,
+                mean_reply_depth: row.mean_reply_depth,
+            })
+            .collect()
+    }
+
+    fn table() -> Self::Table {
+        post_stats_change::table
+    }
+}
+
+/// Top active post at a reporting timestamp
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Insertable)]
+#[table_name = "top_post"]
+pub struct TopPostRow {
+    pub timestamp: DateTime<Utc>,
+    pub rank: i32,
+    pub post_id: i32,
+    pub score: f64,
+    pub users: i32,
+    pub comments: i64,
+    pub replies: i64,
+    pub likes: i64,
+}
+
+impl fmt::Display for TopPostRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Top Post {{ rank: {:3}, id: {:8}, score: {:10.2}, users: {:8}, comments: {:8}, replies: {:8}, likes: {:8} }}",
+            self.rank,
+            self.post_id,
+            self.score,
+            self.users,
+            self.comments,
+            self.replies,
+            self.likes
+        )
+    }
+}
+
+impl ResultRow for TopPostRow {
+    type Record = RankedPost;
+    type Table = top_post::table;
+
+    const NAME: &'static str = "top_post";
+
+    fn rows(timestamp: u64, ranked: &RankedPost) -> Vec<Self> {
+        let post = &ranked.post;
+        vec![TopPostRow {
+            timestamp: Utc.timestamp(timestamp as i64, 0),
+            rank: ranked.rank as i32,
+            post_id: post.id,
+            score: ranked.score,
+            users: post.users.len() as i32,
+            comments: post.comments as i64,
+            replies: post.replies as i64,
+            likes: post.likes as i64,
+        }]
+    }
+
+    fn table() -> Self::Table {
+        top_post::table
+    }
+}
+
+/// Engagement of the active posts of a forum, tag or tag class at a reporting timestamp
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Insertable)]
+#[table_name = "rollup"]
+pub struct RollupRow {
+    pub timestamp: DateTime<Utc>,
+    pub kind: String,
+    pub group_id: i32,
+    pub posts: i64,
+    pub users: i32,
+    pub comments: i64,
+    pub replies: i64,
+    pub likes: i64,
+}
+
+impl fmt::Display for RollupRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rollup {{ {:9} {:8}, posts: {:8}, users: {:8}, comments: {:8}, replies: {:8}, likes: {:8} }}",
+            self.kind,
+            self.group_id,
+            self.posts,
+            self.users,
+            self.comments,
+            self.replies,
+            self.likes
+        )
+    }
+}
+
+impl ResultRow for RollupRow {
+    type Record = Rollup;
+    type Table = rollup::table;
+
+    const NAME: &'static str = "rollup";
+
+    fn rows(timestamp: u64, rollup: &Rollup) -> Vec<Self> {
+        vec![RollupRow {
+            timestamp: Utc.timestamp(timestamp as i64, 0),
+            kind: rollup.group.kind().to_owned(),
+            group_id: rollup.group.id(),
+            posts: rollup.posts as i64,
+            users: rollup.users() as i32,
+            comments: rollup.comments as i64,
+            replies: rollup.replies as i64,
+            likes: rollup.likes as i64,
+        }]
+    }
+
+    fn table() -> Self::Table {
+        rollup::table
+    }
+}
+
+/// Trending tag at the end of a trending interval
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Insertable)]
+#[table_name = "trending_tag"]
+pub struct TrendingTagRow {
+    pub timestamp: DateTime<Utc>,
+    pub rank: i32,
+    pub tag_id: i32,
+    pub name: Option<String>,
+    pub score: f64,
+    pub posts: i64,
+    pub engagements: i64,
+    pub baseline: f64,
+}
+
+impl fmt::Display for TrendingTagRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Trending Tag {{ rank: {:3}, id: {:8}, name: {:24}, score: {:8.2}, posts: {:6}, engagements: {:6}, baseline: {:8.2} }}",
+            self.rank,
+            self.tag_id,
+            self.name.as_ref().map_or("", String::as_str),
+            self.score,
+            self.posts,
+            self.engagements,
+            self.baseline
+        )
+    }
+}
+
+impl ResultRow for TrendingTagRow {
+    type Record = TrendingTag;
+    type Table = trending_tag::table;
+
+    const NAME: &'static str = "trending_tag";
+
+    fn rows(timestamp: u64, tag: &TrendingTag) -> Vec<Self> {
+        vec![TrendingTagRow {
+            timestamp: Utc.timestamp(timestamp as i64, 0),
+            rank: tag.rank as i32,
+            tag_id: tag.tag_id,
+            name: tag.name.clone(),
+            score: tag.score,
+            posts: tag.posts as i64,
+            engagements: tag.engagements as i64,
+            baseline: tag.baseline,
+        }]
+    }
+
+    fn table() -> Self::Table {
+        trending_tag::table
+    }
+}
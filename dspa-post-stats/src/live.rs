@@ -0,0 +1,289 @@
+//! Live state of the active posts, served over a local HTTP port with `--query-port`.
+//!
+//! Worker 0 applies the reports of every completed report time to a snapshot, such that every
+//! answer is consistent with one report time. The snapshot is only updated at report times, so it
+//! trails the input by up to the report interval in event time, and every answer carries the
+//! report time it reflects. The endpoint answers with JSON:
+//! * `/posts` - all active posts
+//! * `/posts/<id>` - the statistics of one active post, 404 if the post is not active
+//! * `/forums/<id>/posts` - the active posts in a forum
+//! * `/persons/<id>/posts` - the active posts created by a person
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use serde_derive::Serialize;
+use structopt::clap::{App, Arg, ArgMatches};
+use structopt::StructOpt;
+use tiny_http::{Header, Response, Server};
+
+use dspa_lib::store::SocialGraphStore;
+
+use crate::{ActivePost, Change, PostReport};
+
+pub const QUERY_ADDRESS: &str = "127.0.0.1";
+pub const POST_STATS_QUERY_PORT: u16 = 9240;
+
+/// Statistics of an active post as of the latest report
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LivePost {
+    pub id: i32,
+    /// Forum and creator of the post, unknown if the store does not contain the post
+    pub forum_id: Option<i32>,
+    pub person_id: Option<i32>,
+    pub users: usize,
+    pub comments: u64,
+    pub replies: u64,
+    pub likes: u64,
+    pub max_reply_depth: u32,
+    pub mean_reply_depth: f64,
+}
+
+impl LivePost {
+    fn update(&mut self, post: &ActivePost) {
+        self.users = post.users();
+        self.comments = post.comments();
+        self.replies = post.replies();
+        self.likes = post.likes();
+        self.max_reply_depth = post.max_reply_depth();
+        self.mean_reply_depth = post.mean_reply_depth();
+    }
+}
+
+/// Active posts as of a completed report time
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    /// Latest applied report time, none before the first report
+    pub time: Option<u64>,
+    // Post Id => Post
+    pub posts: HashMap<i32, LivePost>,
+}
+
+impl Snapshot {
+    pub fn post(&self, id: i32) -> Option<&LivePost> {
+        self.posts.get(&id)
+    }
+
+    /// Active posts matching the predicate, ordered by id
+    pub fn filter<P>(&self, predicate: P) -> Vec<&LivePost>
+    where
+        P: Fn(&LivePost) -> bool,
+    {
+        let mut posts = self
+            .posts
+            .values()
+            .filter(|post| predicate(post))
+            .collect::<Vec<_>>();
+        posts.sort_by_key(|post| post.id);
+        posts
+    }
+
+    pub fn forum(&self, forum_id: i32) -> Vec<&LivePost> {
+        self.filter(|post| post.forum_id == Some(forum_id))
+    }
+
+    pub fn person(&self, person_id: i32) -> Vec<&LivePost> {
+        self.filter(|post| post.person_id == Some(person_id))
+    }
+
+    /// Status code and JSON body of the answer to a request for the given URL
+    pub fn respond(&self, url: &str) -> (u16, String) {
+        #[derive(Serialize)]
+        struct Post<'a> {
+            time: Option<u64>,
+            post: Option<&'a LivePost>,
+        }
+        #[derive(Serialize)]
+        struct Posts<'a> {
+            time: Option<u64>,
+            posts: Vec<&'a LivePost>,
+        }
+
+        let path = url.split('?').next().unwrap_or_default();
+        let segments = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        let id = |segment: &str| segment.parse::<i32>().ok();
+
+        let posts = |posts| Posts {
+            time: self.time,
+            posts,
+        };
+        let answer = match segments.as_slice() {
+            ["posts"] => Some((200, to_json(&posts(self.filter(|_| true))))),
+            ["posts", post_id] => id(post_id).map(|post_id| {
+                let post = self.post(post_id);
+                let status = if post.is_some() { 200 } else { 404 };
+                let body = to_json(&Post {
+                    time: self.time,
+                    post,
+                });
+                (status, body)
+            }),
+            ["forums", forum_id, "posts"] => {
+                id(forum_id).map(|forum_id| (200, to_json(&posts(self.forum(forum_id)))))
+            }
+            ["persons", person_id, "posts"] => {
+                id(person_id).map(|person_id| (200, to_json(&posts(self.person(person_id)))))
+            }
+            _ => None,
+        };
+
+        answer.unwrap_or_else(|| (404, "{\"error\":\"Not Found\"}".to_owned()))
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Failed to encode answer")
+}
+
+/// Snapshot shared between worker 0 and the query endpoint
+#[derive(Clone, Debug, Default)]
+pub struct LivePosts {
+    snapshot: Arc<RwLock<Snapshot>>,
+}
+
+impl LivePosts {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    /// Status code and JSON body of the answer to a request for the given URL
+    pub fn respond(&self, url: &str) -> (u16, String) {
+        self.snapshot.read().unwrap().respond(url)
+    }
+
+    /// Apply all reports of a completed report time, looking up the forum and creator of the
+    /// posts that are not yet known
+    pub fn apply(&self, time: u64, reports: &[PostReport], store: &dyn SocialGraphStore) {
+        let known = {
+            let snapshot = self.snapshot.read().unwrap();
+            reports
+                .iter()
+                .map(|report| snapshot.posts.contains_key(&report.post.id()))
+                .collect::<Vec<_>>()
+        };
+
+        // Look up new posts before blocking the readers
+        let mut records = HashMap::new();
+        for (report, known) in reports.iter().zip(known) {
+            if known || report.change == Change::Deactivated {
+                continue;
+            }
+            match store.post(report.post.id()) {
+                Ok(record) => {
+                    records.insert(report.post.id(), record);
+                }
+                Err(error) => eprintln!("Failed to look up post {}: {}", report.post.id(), error),
+            }
+        }
+
+        let mut snapshot = self.snapshot.write().unwrap();
+        for PostReport { change, post } in reports {
+            if *change == Change::Deactivated {
+                snapshot.posts.remove(&post.id());
+                continue;
+            }
+
+            let record = records.remove(&post.id()).and_then(|record| record);
+            let live = snapshot.posts.entry(post.id()).or_insert_with(|| LivePost {
+                id: post.id(),
+                forum_id: record.as_ref().map(|record| record.forum_id),
+                person_id: record.as_ref().map(|record| record.person_id),
+                users: 0,
+                comments: 0,
+                replies: 0,
+                likes: 0,
+                max_reply_depth: 0,
+                mean_reply_depth: 0.0,
+            });
+            live.update(post);
+        }
+        snapshot.time = Some(time);
+    }
+}
+
+/// Query options, parsed by hand because `--query-port` takes an optional value
+#[derive(Debug)]
+pub struct QueryArgs {
+    /// Port to serve the live state of the active posts on, `None` if the endpoint is disabled
+    pub port: Option<u16>,
+}
+
+impl QueryArgs {
+    pub fn augment_clap<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.arg(
+            Arg::with_name("query-port")
+                .long("query-port")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .validator(|port| port.parse::<u16>().map(|_| ()).map_err(|e| e.to_string()))
+                .help(
+                    "Serve the live state of the active posts on the given port, or on 9240 \
+                     without a value. Only the first process serves it (default: disabled)",
+                ),
+        )
+    }
+
+    pub fn is_subcommand() -> bool {
+        false
+    }
+
+    /// Port of the endpoint, if it is enabled
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+impl StructOpt for QueryArgs {
+    fn clap<'a, 'b>() -> App<'a, 'b> {
+        Self::augment_clap(App::new("query"))
+    }
+
+    fn from_clap(matches: &ArgMatches) -> Self {
+        let port = if matches.is_present("query-port") {
+            Some(
+                matches
+                    .value_of("query-port")
+                    .map_or(POST_STATS_QUERY_PORT, |port| port.parse().unwrap()),
+            )
+        } else {
+            None
+        };
+        QueryArgs { port }
+    }
+}
+
+/// Serve the live state on a background thread
+pub fn serve(port: u16, live: LivePosts) {
+    let server = match Server::http((QUERY_ADDRESS, port)) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("Failed to serve queries on port {}: {}", port, error);
+            return;
+        }
+    };
+
+    thread::spawn(move || {
+        let content_type =
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+
+        for request in server.incoming_requests() {
+            let (status, body) = live.respond(request.url());
+            let response = Response::from_string(body)
+                .with_status_code(status)
+                .with_header(content_type.clone());
+
+            if let Err(error) = request.respond(response) {
+                eprintln!("Failed to respond to query: {}", error);
+            }
+        }
+    });
+}
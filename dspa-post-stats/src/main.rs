@@ -16,79 +16,170 @@ use r2d2::Pool;
 use serde_derive::{Deserialize, Serialize};
 use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::{
-    Broadcast, Capability, Concat, Concatenate, Exchange, Inspect, Map, Operator, Probe,
+    Broadcast, Capability, Concat, Concatenate, Exchange, Filter, Inspect, Map, Operator, Probe,
     UnorderedInput,
 };
 use timely::dataflow::{ProbeHandle, Scope, Stream};
 use zmq::Context;
 
-use dspa_lib::operators::{streams, Ordered};
+use dspa_lib::error::{DspaError, FailedRecord, SplitErrors};
+use dspa_lib::metrics::POST_STATS_METRICS_PORT;
+use dspa_lib::operators::{streams, Checkpoint, Gather, Ordered, TrackLatency, WriteTo};
 use dspa_lib::records::{CommentRecord, LikeRecord, PostRecord, StreamRecord};
 use dspa_lib::schema::{comment, like_ as like, post};
-use dspa_lib::{Topic, DATABASE_URL, MAX_DELAY};
+use dspa_lib::shutdown;
+use dspa_lib::{Topic, MAX_DELAY};
 
-use dspa_post_stats::operators::PostStats;
-use dspa_post_stats::{ActivePost, ActivePostEvent};
+use dspa_post_stats::live::{self, LivePosts};
+use dspa_post_stats::operators::{EngagedTags, Live, PostStats, Rollups, TopPosts, TrendingTags};
+use dspa_post_stats::trending::TagUse;
+use dspa_post_stats::{
+    ActivePost, ActivePostEvent, Change, PostChangeRow, PostStatsRow, RollupRow, TopPostRow,
+    TrendingTagRow, ARGS,
+};
 
 fn main() {
-    let pool = Arc::new(
-        Pool::builder()
-            .max_size(16)
-            .build(ConnectionManager::<PgConnection>::new(DATABASE_URL))
-            .unwrap(),
-    );
+    lazy_static::initialize(&ARGS);
+
+    let store = ARGS.store.store();
+    let config = ARGS.config();
+    let ranking = ARGS.ranking.ranking();
+    let rollup_path = ARGS.rollup_path();
+    let trending_path = ARGS.trending_path();
+    let trending = ARGS.trending();
+    let checkpoints = ARGS.checkpoint.checkpoints("dspa-post-stats");
+    ARGS.metrics
+        .serve(POST_STATS_METRICS_PORT, ARGS.timely.process);
+    // The live state is kept on worker 0, which belongs to the first process
+    let live = LivePosts::new();
+    if let Some(port) = ARGS.query.port() {
+        if ARGS.timely.process == 0 {
+            live::serve(port, live.clone());
+        }
+    }
+    shutdown::handle_signals();
 
     let ctx = Context::new();
 
-    timely::execute(timely::Configuration::Thread, move |worker| {
-        // timely::execute(timely::Configuration::Process(num_cpus::get()), move |worker| {
+    timely::execute(ARGS.timely.configuration(), move |worker| {
         let idx = worker.index();
         let peers = worker.peers();
 
         worker.dataflow(|scope| {
-            let pool = pool.clone();
+            let (posts, comments, likes, failed_events, stamps) = streams(
+                scope,
+                idx,
+                &ctx,
+                checkpoints.restored(),
+                ARGS.partition.partitions,
+                &ARGS.subscription,
+                &ARGS.deadletter,
+            );
 
-            let (posts, comments, likes) = streams(scope, idx, &ctx);
-
-            let comment_events = {
-                let pool = pool.clone();
+            let (comments, failed_comments) = comments
+                .exchange(|comment| comment.id as u64)
+                .ordered(idx, peers, store.clone(), &posts, &checkpoints);
+            let (comment_events, failed_roots) = {
+                let store = store.clone();
                 comments
-                    .exchange(|comment| comment.id as u64)
-                    .ordered(idx, peers, pool.clone(), &posts)
                     .map(move |comment| {
-                        let connection = pool.get().unwrap();
+                        ActivePostEvent::comment(&comment, &*store)
+                            .map_err(|error| FailedRecord::new(&comment, error))
+                    })
+                    .split_errors()
+            };
+
+            let (likes, failed_likes) = likes.exchange(|like| like.post_id as u64).ordered(
+                idx,
+                peers,
+                store.clone(),
+                &posts,
+                &checkpoints,
+            );
+            let like_events = likes.map(|like| ActivePostEvent::Like {
+                post_id: like.post_id,
+                person_id: like.person_id,
+            });
+
+            failed_events
+                .concat(&failed_comments)
+                .concat(&failed_roots)
+                .concat(&failed_likes)
+                .inspect(|failure| eprintln!("{}", failure));
+
+            let events = comment_events.concat(&like_events);
+            let reports = events.exchange(|event| event.id() as u64).post_stats(
+                store.clone(),
+                config,
+                &checkpoints,
+            );
 
-                        ActivePostEvent::Comment {
-                            post_id: comment.root(&connection).unwrap().id,
-                            person_id: comment.person_id,
-                        }
+            // Worker 0 gathers the reports once, for the live state and for writing all posts
+            let gathered = if ARGS.ranking.top.is_none() || ARGS.query.port().is_some() {
+                Some(reports.gather())
+            } else {
+                None
+            };
+
+            if let (Some(gathered), Some(_)) = (&gathered, ARGS.query.port()) {
+                gathered.live(live.clone(), store.clone());
+            }
+
+            let mut written = match (ARGS.ranking.top, &gathered) {
+                (Some(k), _) => reports
+                    .filter(|report| report.change != Change::Deactivated)
+                    .map(|report| report.post)
+                    .top_posts(k, ranking)
+                    .write_to(ARGS.sink.sink::<TopPostRow>())
+                    .map(|_| ()),
+                (None, Some(gathered)) if ARGS.changes => gathered
+                    .map(|reports| {
+                        reports
+                            .into_iter()
+                            .filter(|report| report.change != Change::Unchanged)
+                            .collect::<Vec<_>>()
+                    })
+                    .filter(|reports| !reports.is_empty())
+                    .write_to(ARGS.sink.sink::<PostChangeRow>())
+                    .map(|_| ()),
+                (None, Some(gathered)) => gathered
+                    .map(|reports| {
+                        reports
+                            .into_iter()
+                            .filter(|report| report.change != Change::Deactivated)
+                            .map(|report| report.post)
+                            .collect::<Vec<_>>()
                     })
+                    .filter(|posts| !posts.is_empty())
+                    .write_to(ARGS.sink.sink::<PostStatsRow>())
+                    .map(|_| ()),
+                (None, None) => unreachable!("Reports are gathered unless ranked"),
             };
 
-            let like_events = likes
-                .exchange(|like| like.post_id as u64)
-                .ordered(idx, peers, pool.clone(), &posts)
-                .map(|like| ActivePostEvent::Like {
-                    post_id: like.post_id,
-                    person_id: like.person_id,
-                });
-
-            comment_events
-                .concat(&like_events)
-                .exchange(|event| event.id() as u64)
-                .post_stats(pool.clone())
-                .inspect_batch(|timestamp, posts| {
-                    if !posts.is_empty() {
-                        println!(
-                            "{} - {}",
-                            Utc.timestamp(*timestamp as i64, 0).format("%D - %r"),
-                            timestamp
-                        );
-                        for post in posts {
-                            println!("\t{}", post);
-                        }
-                    }
-                });
+            if ARGS.rollup.rollups {
+                let rollups = reports
+                    .rollups(store.clone(), ARGS.rollup.hierarchy)
+                    .gather()
+                    .write_to(ARGS.sink.sink_at::<RollupRow>(rollup_path.clone()))
+                    .map(|_| ());
+                written = written.concat(&rollups);
+            }
+
+            if let (Some(k), Some(trending)) = (ARGS.trending.trending, trending) {
+                let engaged = events.engaged_tags(&posts, store.clone(), config.schedule.ttl);
+                let trending_tags = posts
+                    .flat_map(|post| TagUse::posted(&post))
+                    .concat(&engaged)
+                    .trending_tags(k, trending, store.clone(), &checkpoints)
+                    .write_to(ARGS.sink.sink_at::<TrendingTagRow>(trending_path.clone()))
+                    .map(|_| ());
+                written = written.concat(&trending_tags);
+            }
+
+            stamps.track_latency(&written);
+
+            // Checkpoints are complete once all outputs are written
+            written.checkpoint(&checkpoints);
         });
     })
     .unwrap();
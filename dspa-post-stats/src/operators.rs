@@ -1,103 +1,831 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::mem::replace;
 use std::sync::Arc;
 
-use diesel::r2d2::ConnectionManager;
-use diesel::PgConnection;
-use r2d2::Pool;
-use timely::dataflow::channels::pact::Exchange;
-use timely::dataflow::operators::Operator;
+use serde_derive::{Deserialize, Serialize};
+use timely::dataflow::channels::pact::{Exchange, Pipeline};
+use timely::dataflow::operators::{Inspect, Map, Operator};
 use timely::dataflow::{Scope, Stream};
 
-use crate::{ActivePost, ActivePostEvent};
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::metrics::{batch_time, SEEDING_FAILURES, USERS_ESTIMATION_ERROR};
+use dspa_lib::operators::Gather;
+use dspa_lib::records::PostRecord;
+use dspa_lib::store::SocialGraphStore;
 
-const MIN_30: u64 = 30 * 60;
-const HR_12: u64 = 60 * 60 * 12;
+use crate::distinct::Counting;
+use crate::live::LivePosts;
+use crate::rollups::{Group, Rollup};
+use crate::trending::{TagTrend, TagUse, Trending, TrendingTag};
+use crate::{
+    ActivePost, ActivePostEvent, Change, PostReport, PostStatsConfig, RankedPost, Ranking,
+    Reactivation,
+};
 
-#[inline]
-fn round_to_next(current: u64) -> u64 {
-    let rem = current % MIN_30;
+/// Active and inactive posts of a worker
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    // Post Id => Post Data
+    active: HashMap<i32, ActivePost>,
+    // Post Id => Expiry Time
+    expiry: HashMap<i32, u64>,
+    // Post Id => Last Report
+    reported: HashMap<i32, ActivePost>,
+    // Last reports of the posts that expired since the last report
+    expired: Vec<ActivePost>,
+    // Post Id => Time the post became inactive
+    inactive: HashMap<i32, u64>,
+    // Post Id => Post Data of inactive posts, kept to reactivate them
+    kept: HashMap<i32, ActivePost>,
+}
+
+impl State {
+    fn is_empty(&self) -> bool {
+        self.active.is_empty() && self.inactive.is_empty()
+    }
+
+    fn expire(&mut self, id: i32, expiry_time: u64, config: &PostStatsConfig) {
+        self.expiry.remove(&id);
+        if let Some(post) = self.active.remove(&id) {
+            if config.retention.reactivation == Reactivation::Keep {
+                self.kept.insert(id, post);
+            }
+        }
+        self.expired.extend(self.reported.remove(&id));
+        self.inactive.insert(id, expiry_time);
+    }
+
+    fn apply(&mut self, time: u64, event: ActivePostEvent, config: &PostStatsConfig) {
+        let id = event.id();
+
+        // Expire post if the event happened after its expiry
+        if let Some(expiry_time) = self.expiry.get(&id).cloned() {
+            if expiry_time < time {
+                self.expire(id, expiry_time, config);
+            }
+        }
 
-    if rem == 0 {
-        current
-    } else {
-        current + MIN_30 - rem
+        // Posts inactive for long enough are tracked as new posts, even between reports
+        let forget_after = config.retention.forget_after;
+        if let Some(inactive_since) = self.inactive.get(&id).cloned() {
+            if forget_after > 0 && inactive_since + forget_after < time {
+                self.inactive.remove(&id);
+                self.kept.remove(&id);
+            }
+        }
+
+        if self.inactive.contains_key(&id) {
+            match config.retention.reactivation {
+                Reactivation::Ignore => return,
+                Reactivation::Reset => {}
+                Reactivation::Keep => {
+                    if let Some(post) = self.kept.remove(&id) {
+                        self.active.insert(id, post);
+                    }
+                }
+            }
+            self.inactive.remove(&id);
+        }
+
+        self.active
+            .entry(id)
+            .or_insert_with(|| ActivePost::new(id, config.counting))
+            .update(event);
+        self.expiry.insert(id, time + config.schedule.ttl);
     }
+
+    fn report(&mut self, time: u64, config: &PostStatsConfig) -> Vec<PostReport> {
+        let (schedule, retention) = (&config.schedule, &config.retention);
+        // Remove inactive posts
+        let expired = self
+            .expiry
+            .iter()
+            .filter(|(_, expiry_time)| **expiry_time < time)
+            .map(|(id, expiry_time)| (*id, *expiry_time))
+            .collect::<Vec<_>>();
+        for (id, expiry_time) in expired {
+            self.expire(id, expiry_time, config);
+        }
+
+        // Forget posts that have been inactive for long enough, they are tracked as new posts
+        if retention.forget_after > 0 {
+            let kept = &mut self.kept;
+            self.inactive.retain(|id, inactive_since| {
+                if *inactive_since + retention.forget_after < time {
+                    kept.remove(id);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        let (users, counts) = (schedule.reports_users(time), schedule.reports_counts(time));
+        let mut reports = Vec::with_capacity(self.active.len() + self.expired.len());
+        for (id, post) in self.active.iter() {
+            let previous = self.reported.get(id);
+            let report = post.report(previous, users, counts);
+            let change = match previous {
+                None => Change::Activated,
+                Some(previous) if *previous == report => Change::Unchanged,
+                Some(_) => Change::Updated,
+            };
+
+            reports.push(PostReport {
+                change,
+                post: report.clone(),
+            });
+            self.reported.insert(*id, report);
+        }
+
+        // Posts reactivated since their expiry are only reported as activated
+        let active = &self.active;
+        reports.extend(
+            self.expired
+                .drain(..)
+                .filter(|post| !active.contains_key(&post.id()))
+                .map(|post| PostReport {
+                    change: Change::Deactivated,
+                    post,
+                }),
+        );
+        reports
+    }
+}
+
+/// Report the error of the estimated unique users of the reported posts as metrics and on stderr
+fn report_error(idx: usize, time: u64, reports: &[PostReport]) {
+    let errors = reports
+        .iter()
+        .filter(|report| report.change != Change::Deactivated)
+        .filter_map(|report| report.post.users_error())
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        return;
+    }
+
+    let mean = errors.iter().sum::<f64>() / errors.len() as f64;
+    let max = errors.iter().cloned().fold(0.0, f64::max);
+    let worker = idx.to_string();
+    USERS_ESTIMATION_ERROR
+        .with_label_values(&[&worker, "mean"])
+        .set(mean);
+    USERS_ESTIMATION_ERROR
+        .with_label_values(&[&worker, "max"])
+        .set(max);
+    eprintln!(
+        "Worker {}: unique users estimation error at {}: mean {:.2}%, max {:.2}% over {} posts",
+        idx,
+        time,
+        mean * 100.0,
+        max * 100.0,
+        errors.len()
+    );
+}
+
+/// Activity in the store before the input frontier, exchanged like the received events
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Seed {
+    /// Received events before the time are replaced by the seeded ones, sent to every worker
+    Until {
+        time: u64,
+        worker: usize,
+    },
+    Event(ActivePostEvent),
+}
+
+/// Activity within the TTL before the input frontier once it left the initial time, queried
+/// once on worker 0. Failures are counted and leave the received events in place.
+fn seed<G>(
+    events: &Stream<G, ActivePostEvent>,
+    store: Arc<dyn SocialGraphStore>,
+    ttl: u64,
+    enabled: bool,
+) -> Stream<G, Seed>
+where
+    G: Scope<Timestamp = u64>,
+{
+    let idx = events.scope().index();
+    let peers = events.scope().peers();
+
+    let mut vec = Vec::new();
+    events.unary_frontier(Pipeline, "Seed", move |cap, _| {
+        let mut cap = if enabled { Some(cap) } else { None };
+
+        move |input, output| {
+            input.for_each(|_, data| {
+                data.swap(&mut vec);
+                vec.clear();
+            });
+
+            if cap.is_none() || input.frontier().less_equal(&0) {
+                return;
+            }
+            let cap = cap.take().unwrap();
+            let to = match input.frontier().frontier().iter().min() {
+                Some(to) if idx == 0 => *to,
+                _ => return,
+            };
+
+            match ActivePostEvent::between(&*store, to.saturating_sub(ttl), to) {
+                Ok(events) => {
+                    output
+                        .session(&cap)
+                        .give_iterator((0..peers).map(|worker| Seed::Until { time: to, worker }));
+                    for (time, event) in events {
+                        output.session(&cap.delayed(&time)).give(Seed::Event(event));
+                    }
+                }
+                Err(error) => {
+                    SEEDING_FAILURES.inc();
+                    eprintln!("Failed to seed post statistics: {}", error);
+                }
+            }
+        }
+    })
 }
 
 pub trait PostStats<G>
 where
     G: Scope<Timestamp = u64>,
 {
-    fn post_stats(&self, pool: Arc<Pool<ConnectionManager<PgConnection>>>) -> Stream<G, String>;
+    fn post_stats(
+        &self,
+        store: Arc<dyn SocialGraphStore>,
+        config: PostStatsConfig,
+        checkpoints: &Checkpoints,
+    ) -> Stream<G, PostReport>;
 }
 
 impl<G> PostStats<G> for Stream<G, ActivePostEvent>
 where
     G: Scope<Timestamp = u64>,
 {
-    fn post_stats(&self, pool: Arc<Pool<ConnectionManager<PgConnection>>>) -> Stream<G, String> {
-        let pool = pool.clone();
+    fn post_stats(
+        &self,
+        store: Arc<dyn SocialGraphStore>,
+        config: PostStatsConfig,
+        checkpoints: &Checkpoints,
+    ) -> Stream<G, PostReport> {
+        let idx = self.scope().index();
+        let checkpoints = checkpoints.clone();
 
-        let mut seen: HashSet<i32> = HashSet::new();
+        // Restored state already contains the activity before the restored time
+        let mut seeded = !config.seed || checkpoints.restored().is_some();
+        let schedule = config.schedule;
 
-        // Post Id => Post Data
-        let mut active: HashMap<i32, ActivePost> = HashMap::new();
+        let mut state: State = checkpoints.load("post-stats", idx).unwrap_or_default();
 
-        // Post Id => Expiry Time
-        let mut expiry: HashMap<i32, u64> = HashMap::new();
+        // Continue reporting and snapshotting restored posts, or seed once the input frontier
+        // has left the initial time
+        let mut init = Vec::new();
+        if !seeded {
+            init.push(0);
+        }
+        if let Some(time) = checkpoints.restored() {
+            if !state.active.is_empty() {
+                init.push(schedule.next(time + 1));
+            }
+            if !state.is_empty() {
+                init.extend(checkpoints.next(time + 1));
+            }
+        }
+
+        // Time => Events, applied once their time is complete
+        let mut pending: BTreeMap<u64, Vec<ActivePostEvent>> = BTreeMap::new();
+        // Time => Seeded events that arrived before the time up to which they replace the
+        // received events
+        let mut seeds: BTreeMap<u64, Vec<ActivePostEvent>> = BTreeMap::new();
+        let mut seeded_until = None;
+
+        let batch_time = batch_time("PostStats");
+        let seeds_stream = seed(self, store, schedule.ttl, !seeded);
 
         let mut vec = Vec::new();
-        self.unary_notify(
+        let mut seed_vec = Vec::new();
+        self.binary_notify(
+            &seeds_stream,
             Exchange::new(|event: &ActivePostEvent| event.id() as u64),
+            Exchange::new(|seed: &Seed| match seed {
+                Seed::Until { worker, .. } => *worker as u64,
+                Seed::Event(event) => event.id() as u64,
+            }),
             "PostStats",
-            None,
-            move |input, output, notificator| {
+            init,
+            move |input, seed_input, output, notificator| {
+                let _timer = batch_time.start_timer();
+
                 input.for_each(|cap, data| {
-                    // TODO: Fetch existing active posts from DB
                     data.swap(&mut vec);
 
-                    vec.drain(..).for_each(|event| {
-                        let id = event.id();
+                    // Records are inserted before they are published, such that the received
+                    // events before the seeded time are part of the seeded ones
+                    if seeded_until.map_or(false, |to| *cap.time() < to) {
+                        vec.clear();
+                        return;
+                    }
 
-                        // Only evaluate if not seen or currently active
-                        if !seen.contains(&id) || active.contains_key(&id) {
-                            active
-                                .entry(id)
-                                .or_insert_with(|| ActivePost::new(id))
-                                .update(event);
-                            expiry
-                                .entry(id)
-                                .and_modify(|time| *time = *cap.time() + HR_12)
-                                .or_insert_with(|| *cap.time() + HR_12);
+                    pending
+                        .entry(*cap.time())
+                        .or_default()
+                        .extend(vec.drain(..));
 
-                            seen.insert(id);
+                    notificator.notify_at(cap.delayed(&schedule.next(*cap.time())));
+                    if let Some(checkpoint) = checkpoints.next(*cap.time()) {
+                        notificator.notify_at(cap.delayed(&checkpoint));
+                    }
+                });
+
+                // Seeded events are applied whenever they arrive, the notification at the
+                // initial time holds back all reports until the seed has been sent
+                seed_input.for_each(|cap, data| {
+                    data.swap(&mut seed_vec);
+                    let mut events = 0;
+                    for seed in seed_vec.drain(..) {
+                        match seed {
+                            Seed::Until { time, .. } => {
+                                seeded_until = Some(time);
+                                pending = pending.split_off(&time);
+                            }
+                            Seed::Event(event) => {
+                                seeds.entry(*cap.time()).or_default().push(event);
+                                events += 1;
+                            }
                         }
-                    });
+                    }
+                    if events > 0 {
+                        notificator.notify_at(cap.delayed(&schedule.next(*cap.time())));
+                    }
 
-                    notificator.notify_at(cap.delayed(&round_to_next(*cap.time())));
+                    if seeded_until.is_some() {
+                        for (time, events) in replace(&mut seeds, BTreeMap::new()) {
+                            pending.entry(time).or_default().extend(events);
+                        }
+                    }
                 });
 
                 notificator.for_each(|cap, _, notificator| {
-                    // Remove inactive posts
-                    expiry.retain(|id, expiry_time| {
-                        if *expiry_time < *cap.time() {
-                            active.remove(&id);
-                            false
-                        } else {
-                            true
+                    // The seed input has passed the initial time
+                    if !seeded {
+                        seeded = true;
+                        return;
+                    }
+
+                    // Apply events up to and including the current time, in time order
+                    let remaining = pending.split_off(&(*cap.time() + 1));
+                    for (time, events) in replace(&mut pending, remaining) {
+                        for event in events {
+                            state.apply(time, event, &config);
                         }
-                    });
+                    }
+
+                    let time = *cap.time();
+                    if schedule.is_report(time) {
+                        let reports = state.report(time, &config);
+                        if let Counting::Compare(_) = config.counting {
+                            report_error(idx, time, &reports);
+                        }
+                        output.session(&cap).give_iterator(reports.into_iter());
+
+                        if !state.active.is_empty() {
+                            notificator.notify_at(cap.delayed(&schedule.next(time + 1)));
+                        }
+                    }
+
+                    // Events after the checkpoint are still pending and replayed after a restore
+                    if checkpoints.is_checkpoint(*cap.time()) {
+                        checkpoints.save("post-stats", idx, *cap.time(), &state);
+
+                        if !state.is_empty() && !notificator.frontier(0).is_empty() {
+                            if let Some(checkpoint) = checkpoints.next(cap.time() + 1) {
+                                notificator.notify_at(cap.delayed(&checkpoint));
+                            }
+                        }
+                    }
+                });
+            },
+        )
+    }
+}
+
+pub trait TopPosts<G>
+where
+    G: Scope<Timestamp = u64>,
+{
+    /// The `k` posts with the highest score of every timestamp, ranked on every worker before
+    /// the candidates are merged on worker 0
+    fn top_posts(&self, k: usize, ranking: Ranking) -> Stream<G, Vec<RankedPost>>;
+}
+
+impl<G> TopPosts<G> for Stream<G, ActivePost>
+where
+    G: Scope<Timestamp = u64>,
+{
+    fn top_posts(&self, k: usize, ranking: Ranking) -> Stream<G, Vec<RankedPost>> {
+        // Time => Posts
+        let mut pending: HashMap<u64, Vec<ActivePost>> = HashMap::new();
+
+        let batch_time = batch_time("TopPosts");
+
+        let mut vec = Vec::new();
+        self.unary_notify(
+            Pipeline,
+            "TopPosts",
+            None,
+            move |input, output, notificator| {
+                let _timer = batch_time.start_timer();
+
+                input.for_each(|cap, data| {
+                    data.swap(&mut vec);
+
+                    pending
+                        .entry(*cap.time())
+                        .or_default()
+                        .extend(vec.drain(..));
+                    notificator.notify_at(cap.retain());
+                });
+
+                // Only the local top posts can be part of the global ones
+                notificator.for_each(|cap, _, _| {
+                    if let Some(posts) = pending.remove(cap.time()) {
+                        let top = ranking.top(posts, k);
+                        output
+                            .session(&cap)
+                            .give_iterator(top.into_iter().map(|ranked| ranked.post));
+                    }
+                });
+            },
+        )
+        .gather()
+        .map(move |posts| ranking.top(posts, k))
+    }
+}
+
+pub trait Live<G>
+where
+    G: Scope<Timestamp = u64>,
+{
+    /// Apply the gathered reports of every completed report time to the live state on worker 0.
+    /// The live state is that of the last completed report time.
+    fn live(&self, live: LivePosts, store: Arc<dyn SocialGraphStore>)
+        -> Stream<G, Vec<PostReport>>;
+}
+
+impl<G> Live<G> for Stream<G, Vec<PostReport>>
+where
+    G: Scope<Timestamp = u64>,
+{
+    fn live(
+        &self,
+        live: LivePosts,
+        store: Arc<dyn SocialGraphStore>,
+    ) -> Stream<G, Vec<PostReport>> {
+        // Gathered reports are complete and arrive in time order
+        self.inspect_time(move |time, reports| live.apply(*time, reports, &*store))
+    }
+}
+
+pub trait Rollups<G>
+where
+    G: Scope<Timestamp = u64>,
+{
+    /// Engagement of the active posts per forum and tag at every report time, and per tag class
+    /// if `hierarchy` is set
+    fn rollups(&self, store: Arc<dyn SocialGraphStore>, hierarchy: bool) -> Stream<G, Rollup>;
+}
+
+impl<G> Rollups<G> for Stream<G, PostReport>
+where
+    G: Scope<Timestamp = u64>,
+{
+    fn rollups(&self, store: Arc<dyn SocialGraphStore>, hierarchy: bool) -> Stream<G, Rollup> {
+        // Time => Reports
+        let mut pending: HashMap<u64, Vec<PostReport>> = HashMap::new();
+        // Post Id => Groups, looked up once while the post is active and in the store
+        let mut groups: HashMap<i32, Vec<Group>> = HashMap::new();
 
-                    // Output formatted strings
+        let local_batch_time = batch_time("LocalRollups");
+
+        let mut vec = Vec::new();
+        let partial = self.unary_notify(
+            Pipeline,
+            "LocalRollups",
+            None,
+            move |input, output, notificator| {
+                let _timer = local_batch_time.start_timer();
+
+                input.for_each(|cap, data| {
+                    data.swap(&mut vec);
+
+                    pending
+                        .entry(*cap.time())
+                        .or_default()
+                        .extend(vec.drain(..));
+                    notificator.notify_at(cap.retain());
+                });
+
+                // Roll up the posts of this worker before exchanging the partial rollups
+                notificator.for_each(|cap, _, _| {
+                    let mut rollups: HashMap<Group, Rollup> = HashMap::new();
+                    for report in pending.remove(cap.time()).unwrap_or_default() {
+                        let id = report.post.id();
+                        if report.change == Change::Deactivated {
+                            groups.remove(&id);
+                            continue;
+                        }
+
+                        if !groups.contains_key(&id) {
+                            match Group::of_post(&*store, id, hierarchy) {
+                                // Posts missing from the store are looked up again next time
+                                Ok(post_groups) if post_groups.is_empty() => continue,
+                                Ok(post_groups) => {
+                                    groups.insert(id, post_groups);
+                                }
+                                Err(error) => {
+                                    eprintln!("Failed to look up groups of post {}: {}", id, error);
+                                    continue;
+                                }
+                            }
+                        }
+
+                        for group in groups[&id].iter() {
+                            rollups
+                                .entry(*group)
+                                .and_modify(|rollup| rollup.add(&report.post))
+                                .or_insert_with(|| Rollup::new(*group, &report.post));
+                        }
+                    }
                     output
                         .session(&cap)
-                        .give_iterator(active.values().map(|post| format!("{}", post)));
+                        .give_iterator(rollups.into_iter().map(|(_, rollup)| rollup));
+                });
+            },
+        );
+
+        // Time => Group => Rollup
+        let mut pending: HashMap<u64, HashMap<Group, Rollup>> = HashMap::new();
+
+        let batch_time = batch_time("Rollups");
 
-                    if !active.is_empty() {
-                        notificator.notify_at(cap.delayed(&round_to_next(cap.time() + 1)));
+        let mut vec = Vec::new();
+        partial.unary_notify(
+            Exchange::new(|rollup: &Rollup| rollup.group.key()),
+            "Rollups",
+            None,
+            move |input, output, notificator| {
+                let _timer = batch_time.start_timer();
+
+                input.for_each(|cap, data| {
+                    data.swap(&mut vec);
+
+                    let rollups = pending.entry(*cap.time()).or_default();
+                    for rollup in vec.drain(..) {
+                        match rollups.get_mut(&rollup.group) {
+                            Some(merged) => merged.merge(&rollup),
+                            None => {
+                                rollups.insert(rollup.group, rollup);
+                            }
+                        }
+                    }
+                    notificator.notify_at(cap.retain());
+                });
+
+                notificator.for_each(|cap, _, _| {
+                    if let Some(rollups) = pending.remove(cap.time()) {
+                        let mut rollups = rollups
+                            .into_iter()
+                            .map(|(_, rollup)| rollup)
+                            .collect::<Vec<_>>();
+                        rollups.sort_by_key(|rollup| rollup.group);
+                        output.session(&cap).give_iterator(rollups.into_iter());
                     }
                 });
             },
         )
     }
 }
+
+pub trait TrendingTags<G>
+where
+    G: Scope<Timestamp = u64>,
+{
+    /// The `k` tags with the highest burst score at every trending interval, ranked on every
+    /// worker before the candidates are merged and named on worker 0
+    fn trending_tags(
+        &self,
+        k: usize,
+        trending: Trending,
+        store: Arc<dyn SocialGraphStore>,
+        checkpoints: &Checkpoints,
+    ) -> Stream<G, Vec<TrendingTag>>;
+}
+
+impl<G> TrendingTags<G> for Stream<G, TagUse>
+where
+    G: Scope<Timestamp = u64>,
+{
+    fn trending_tags(
+        &self,
+        k: usize,
+        trending: Trending,
+        store: Arc<dyn SocialGraphStore>,
+        checkpoints: &Checkpoints,
+    ) -> Stream<G, Vec<TrendingTag>> {
+        let idx = self.scope().index();
+        let checkpoints = checkpoints.clone();
+
+        // Tag Id => Trend
+        let mut tags: HashMap<i32, TagTrend> =
+            checkpoints.load("trending-tags", idx).unwrap_or_default();
+
+        // Continue reporting and snapshotting restored tags
+        let mut init = Vec::new();
+        if let Some(time) = checkpoints.restored() {
+            if !tags.is_empty() {
+                init.push(trending.next(time + 1));
+                init.extend(checkpoints.next(time + 1));
+            }
+        }
+
+        // Time => Uses, applied once their time is complete
+        let mut pending: BTreeMap<u64, Vec<TagUse>> = BTreeMap::new();
+
+        let batch_time = batch_time("TrendingTags");
+
+        let mut vec = Vec::new();
+        self.unary_notify(
+            Exchange::new(|tag_use: &TagUse| tag_use.tag_id() as u64),
+            "TrendingTags",
+            init,
+            move |input, output, notificator| {
+                let _timer = batch_time.start_timer();
+
+                input.for_each(|cap, data| {
+                    data.swap(&mut vec);
+
+                    pending
+                        .entry(*cap.time())
+                        .or_default()
+                        .extend(vec.drain(..));
+
+                    notificator.notify_at(cap.delayed(&trending.next(*cap.time())));
+                    if let Some(checkpoint) = checkpoints.next(*cap.time()) {
+                        notificator.notify_at(cap.delayed(&checkpoint));
+                    }
+                });
+
+                notificator.for_each(|cap, _, notificator| {
+                    // Count the uses up to and including the current time
+                    let remaining = pending.split_off(&(*cap.time() + 1));
+                    for (_, uses) in replace(&mut pending, remaining) {
+                        for tag_use in uses {
+                            tags.entry(tag_use.tag_id()).or_default().add(tag_use);
+                        }
+                    }
+
+                    let time = *cap.time();
+                    if time % trending.interval == 0 {
+                        let candidates = tags
+                            .iter_mut()
+                            .map(|(tag_id, trend)| trend.update(*tag_id, &trending))
+                            .collect::<Vec<_>>();
+                        tags.retain(|_, trend| !trend.is_negligible());
+
+                        // Only the local top tags can be part of the global ones
+                        output
+                            .session(&cap)
+                            .give_iterator(trending.top(candidates, k).into_iter());
+
+                        if !tags.is_empty() {
+                            notificator.notify_at(cap.delayed(&(time + trending.interval)));
+                        }
+                    }
+
+                    // Uses after the checkpoint are still pending and replayed after a restore
+                    if checkpoints.is_checkpoint(time) {
+                        checkpoints.save("trending-tags", idx, time, &tags);
+
+                        if !tags.is_empty() && !notificator.frontier(0).is_empty() {
+                            if let Some(checkpoint) = checkpoints.next(time + 1) {
+                                notificator.notify_at(cap.delayed(&checkpoint));
+                            }
+                        }
+                    }
+                });
+            },
+        )
+        .gather()
+        .map(move |candidates| {
+            let mut top = trending.top(candidates, k);
+            for tag in top.iter_mut() {
+                match store.tag_name(tag.tag_id) {
+                    Ok(name) => tag.name = name,
+                    Err(error) => eprintln!("Failed to look up tag {}: {}", tag.tag_id, error),
+                }
+            }
+            top
+        })
+    }
+}
+
+pub trait EngagedTags<G>
+where
+    G: Scope<Timestamp = u64>,
+{
+    /// Uses of the tags of the posts that comments and likes are on. The tags of a post are
+    /// taken from the received posts or looked up once in the store, and forgotten once the
+    /// post had no engagement for `ttl` seconds of event time.
+    fn engaged_tags(
+        &self,
+        posts: &Stream<G, PostRecord>,
+        store: Arc<dyn SocialGraphStore>,
+        ttl: u64,
+    ) -> Stream<G, TagUse>;
+}
+
+impl<G> EngagedTags<G> for Stream<G, ActivePostEvent>
+where
+    G: Scope<Timestamp = u64>,
+{
+    fn engaged_tags(
+        &self,
+        posts: &Stream<G, PostRecord>,
+        store: Arc<dyn SocialGraphStore>,
+        ttl: u64,
+    ) -> Stream<G, TagUse> {
+        // Post Id => (Tags, Time of the latest post or engagement)
+        let mut tags: HashMap<i32, (Vec<i32>, u64)> = HashMap::new();
+        let mut forgotten = 0;
+
+        let batch_time = batch_time("EngagedTags");
+
+        let mut vec = Vec::new();
+        let mut posts_vec = Vec::new();
+        self.binary_frontier(
+            posts,
+            Exchange::new(|event: &ActivePostEvent| event.id() as u64),
+            Exchange::new(|post: &PostRecord| post.id as u64),
+            "EngagedTags",
+            |_, _| {
+                move |input, posts_input, output| {
+                    let _timer = batch_time.start_timer();
+
+                    posts_input.for_each(|cap, data| {
+                        data.swap(&mut posts_vec);
+                        for post in posts_vec.drain(..) {
+                            let tags = tags.entry(post.id).or_insert((Vec::new(), 0));
+                            tags.0 = post.tags;
+                            tags.1 = tags.1.max(*cap.time());
+                        }
+                    });
+
+                    input.for_each(|cap, data| {
+                        data.swap(&mut vec);
+                        let time = *cap.time();
+                        let mut session = output.session(&cap);
+                        for event in vec.drain(..) {
+                            let id = event.id();
+                            if !tags.contains_key(&id) {
+                                match store.post(id) {
+                                    Ok(Some(post)) => {
+                                        tags.insert(id, (post.tags, time));
+                                    }
+                                    // Posts missing from the store are looked up again next time
+                                    Ok(None) => continue,
+                                    Err(error) => {
+                                        eprintln!(
+                                            "Failed to look up tags of post {}: {}",
+                                            id, error
+                                        );
+                                        continue;
+                                    }
+                                }
+                            }
+
+                            let (post_tags, latest) = tags.get_mut(&id).unwrap();
+                            *latest = (*latest).max(time);
+                            session.give_iterator(
+                                post_tags.iter().map(|tag| TagUse::Engagement(*tag)),
+                            );
+                        }
+                    });
+
+                    // Forget the tags of posts without engagement for the TTL
+                    let frontier = input
+                        .frontier()
+                        .frontier()
+                        .iter()
+                        .chain(posts_input.frontier().frontier().iter())
+                        .min()
+                        .cloned();
+                    if let Some(frontier) = frontier {
+                        if frontier > forgotten {
+                            forgotten = frontier;
+                            tags.retain(|_, (_, latest)| *latest + ttl >= frontier);
+                        }
+                    }
+                }
+            },
+        )
+    }
+}
@@ -0,0 +1,155 @@
+//! Rollups of the active posts per forum, tag and tag class.
+//!
+//! Tags roll up into the classes they have the type of and, transitively, into the parent
+//! classes of those, such that a post counts once towards every class above its tags.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_derive::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use dspa_lib::error::Result;
+use dspa_lib::store::SocialGraphStore;
+
+use crate::distinct::Users;
+use crate::ActivePost;
+
+/// Group of posts that is rolled up
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Group {
+    Forum(i32),
+    Tag(i32),
+    TagClass(i32),
+}
+
+impl Group {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Group::Forum(_) => "forum",
+            Group::Tag(_) => "tag",
+            Group::TagClass(_) => "tag_class",
+        }
+    }
+
+    pub fn id(&self) -> i32 {
+        match self {
+            Group::Forum(id) | Group::Tag(id) | Group::TagClass(id) => *id,
+        }
+    }
+
+    /// Key to route the partial rollups of a group to one worker
+    pub fn key(&self) -> u64 {
+        let kind = match self {
+            Group::Forum(_) => 0,
+            Group::Tag(_) => 1,
+            Group::TagClass(_) => 2,
+        };
+        (self.id() as u32 as u64) * 3 + kind
+    }
+
+    /// Groups of a post: its forum, its tags and, following the hierarchy, the classes of its
+    /// tags and all their ancestors. Posts missing from the store do not belong to any group.
+    pub fn of_post(
+        store: &dyn SocialGraphStore,
+        post_id: i32,
+        hierarchy: bool,
+    ) -> Result<Vec<Self>> {
+        let post = match store.post(post_id)? {
+            Some(post) => post,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut tags = post.tags.clone();
+        tags.sort();
+        tags.dedup();
+
+        let mut groups = vec![Group::Forum(post.forum_id)];
+        groups.extend(tags.iter().map(|tag| Group::Tag(*tag)));
+
+        if hierarchy {
+            let mut classes = HashSet::new();
+            let mut queue = Vec::new();
+            for tag in tags.iter() {
+                queue.extend(store.tag_classes(*tag)?);
+            }
+            // The hierarchy may contain cycles or shared ancestors, every class counts once
+            while let Some(class) = queue.pop() {
+                if classes.insert(class) {
+                    queue.extend(store.parent_classes(class)?);
+                }
+            }
+            let mut classes = classes.into_iter().collect::<Vec<_>>();
+            classes.sort();
+            groups.extend(classes.into_iter().map(Group::TagClass));
+        }
+
+        Ok(groups)
+    }
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind(), self.id())
+    }
+}
+
+/// Engagement of the active posts of a group
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rollup {
+    pub group: Group,
+    pub posts: u64,
+    pub comments: u64,
+    pub replies: u64,
+    pub likes: u64,
+    users: Users,
+}
+
+impl Rollup {
+    pub fn new(group: Group, post: &ActivePost) -> Self {
+        Rollup {
+            group,
+            posts: 1,
+            comments: post.comments,
+            replies: post.replies,
+            likes: post.likes,
+            users: (*post.users).clone(),
+        }
+    }
+
+    pub fn add(&mut self, post: &ActivePost) {
+        self.posts += 1;
+        self.comments += post.comments;
+        self.replies += post.replies;
+        self.likes += post.likes;
+        self.users.merge(&post.users);
+    }
+
+    /// Combine with a partial rollup of the same group
+    pub fn merge(&mut self, other: &Rollup) {
+        self.posts += other.posts;
+        self.comments += other.comments;
+        self.replies += other.replies;
+        self.likes += other.likes;
+        self.users.merge(&other.users);
+    }
+
+    /// Unique users engaged with any of the posts
+    pub fn users(&self) -> usize {
+        self.users.len()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RollupArgs {
+    #[structopt(long = "rollups")]
+    /// Also report the engagement of the active posts per forum and tag
+    pub rollups: bool,
+    #[structopt(long = "tag-hierarchy")]
+    /// Roll tags up into their tag classes and all parent classes
+    pub hierarchy: bool,
+    #[structopt(long = "rollup-path", parse(from_os_str))]
+    /// File to write text, json or csv rollups to (default: <output-path>.rollup, or stdout with text results)
+    pub rollup_path: Option<PathBuf>,
+}
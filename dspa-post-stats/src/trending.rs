@@ -0,0 +1,209 @@
+//! Trending tags, scored by bursts of their use compared to a long-term baseline.
+//!
+//! Every interval, a tag is used once for every new post carrying it and with the engagement
+//! weight for every comment or like on such a post. The uses of an interval update two
+//! exponentially decayed averages, a short-term one following the current activity and a
+//! long-term baseline. The burst score is the excess of the short-term average over the baseline,
+//! scaled by the expected deviation of the baseline `sqrt(baseline + 1)`, such that rare tags need
+//! a relatively larger burst than frequent tags.
+
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+use serde_derive::{Deserialize, Serialize};
+use structopt::clap::{Error as UsageError, ErrorKind};
+use structopt::StructOpt;
+
+use dspa_lib::records::PostRecord;
+
+/// Averages below this are forgotten
+const NEGLIGIBLE: f64 = 0.01;
+
+#[derive(Debug, StructOpt)]
+pub struct TrendingArgs {
+    #[structopt(long = "trending")]
+    /// Also report the given number of trending tags at every trending interval
+    pub trending: Option<usize>,
+    #[structopt(
+        name = "trending-interval",
+        long = "trending-interval",
+        default_value = "3600"
+    )]
+    /// Event time in seconds between the reports of the trending tags
+    pub interval: u64,
+    #[structopt(long = "short-half-life", default_value = "3600")]
+    /// Event time in seconds after which the uses of a tag weigh half in the short-term average
+    pub short_half_life: u64,
+    #[structopt(long = "long-half-life", default_value = "86400")]
+    /// Event time in seconds after which the uses of a tag weigh half in the baseline
+    pub long_half_life: u64,
+    #[structopt(long = "engagement-weight", default_value = "0.5")]
+    /// Weight of a comment or like on a post with the tag, relative to a new post with the tag
+    pub engagement_weight: f64,
+    #[structopt(long = "trending-path", parse(from_os_str))]
+    /// File to write text, json or csv trending tags to (default: <output-path>.trending, or stdout with text results)
+    pub trending_path: Option<PathBuf>,
+}
+
+impl TrendingArgs {
+    /// Scoring of the trending tags, or a usage error if the arguments are inconsistent
+    pub fn trending(&self) -> std::result::Result<Trending, UsageError> {
+        let invalid = |message: &str| {
+            Err(UsageError::with_description(
+                message,
+                ErrorKind::ValueValidation,
+            ))
+        };
+        if self.interval == 0 {
+            return invalid("The trending interval must be positive");
+        }
+        if self.short_half_life == 0 || self.short_half_life >= self.long_half_life {
+            return invalid(
+                "The short half-life must be positive and shorter than the long half-life",
+            );
+        }
+
+        Ok(Trending {
+            interval: self.interval,
+            short_half_life: self.short_half_life,
+            long_half_life: self.long_half_life,
+            engagement_weight: self.engagement_weight,
+        })
+    }
+}
+
+/// Scoring of the trending tags, all times in seconds of event time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Trending {
+    pub interval: u64,
+    pub short_half_life: u64,
+    pub long_half_life: u64,
+    pub engagement_weight: f64,
+}
+
+impl Default for Trending {
+    /// Hourly reports, comparing the last hours to the last days
+    fn default() -> Self {
+        Trending {
+            interval: 60 * 60,
+            short_half_life: 60 * 60,
+            long_half_life: 24 * 60 * 60,
+            engagement_weight: 0.5,
+        }
+    }
+}
+
+impl Trending {
+    /// First report time at or after the given time
+    pub fn next(&self, time: u64) -> u64 {
+        let rem = time % self.interval;
+        if rem == 0 {
+            time
+        } else {
+            time + self.interval - rem
+        }
+    }
+
+    /// Factor an average decays by in one interval
+    fn decay(&self, half_life: u64) -> f64 {
+        0.5f64.powf(self.interval as f64 / half_life as f64)
+    }
+
+    /// Candidates of the tags with the highest positive score, ranked by score descending and
+    /// then by tag id
+    pub fn top(&self, mut tags: Vec<TrendingTag>, k: usize) -> Vec<TrendingTag> {
+        tags.retain(|tag| tag.score > 0.0);
+        tags.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then(a.tag_id.cmp(&b.tag_id))
+        });
+        tags.truncate(k);
+        for (rank, tag) in tags.iter_mut().enumerate() {
+            tag.rank = rank + 1;
+        }
+        tags
+    }
+}
+
+/// Use of a tag by a new post or by activity on a post
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TagUse {
+    Post(i32),
+    Engagement(i32),
+}
+
+impl TagUse {
+    pub fn tag_id(&self) -> i32 {
+        match self {
+            TagUse::Post(tag_id) | TagUse::Engagement(tag_id) => *tag_id,
+        }
+    }
+
+    /// Uses of the tags of a new post
+    pub fn posted(post: &PostRecord) -> Vec<Self> {
+        post.tags.iter().map(|tag| TagUse::Post(*tag)).collect()
+    }
+}
+
+/// Uses and decayed averages of a tag
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TagTrend {
+    /// Uses in the current interval
+    pub posts: u64,
+    pub engagements: u64,
+    pub short: f64,
+    pub baseline: f64,
+}
+
+impl TagTrend {
+    pub fn add(&mut self, tag_use: TagUse) {
+        match tag_use {
+            TagUse::Post(_) => self.posts += 1,
+            TagUse::Engagement(_) => self.engagements += 1,
+        }
+    }
+
+    /// Close the current interval and return the candidate of the tag
+    pub fn update(&mut self, tag_id: i32, trending: &Trending) -> TrendingTag {
+        let uses = self.posts as f64 + self.engagements as f64 * trending.engagement_weight;
+        let short = trending.decay(trending.short_half_life);
+        let long = trending.decay(trending.long_half_life);
+        self.short = short * self.short + (1.0 - short) * uses;
+        self.baseline = long * self.baseline + (1.0 - long) * uses;
+
+        let tag = TrendingTag {
+            rank: 0,
+            tag_id,
+            name: None,
+            score: (self.short - self.baseline) / (self.baseline + 1.0).sqrt(),
+            posts: self.posts,
+            engagements: self.engagements,
+            baseline: self.baseline,
+        };
+        self.posts = 0;
+        self.engagements = 0;
+        tag
+    }
+
+    /// Whether the tag has not been used for long enough to be forgotten
+    pub fn is_negligible(&self) -> bool {
+        self.posts == 0 && self.engagements == 0 && self.baseline < NEGLIGIBLE
+    }
+}
+
+/// Tag with its burst score at a report time
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrendingTag {
+    /// Rank starting at 1, 0 before ranking
+    pub rank: usize,
+    pub tag_id: i32,
+    /// Name from the store, unknown if the store does not contain the tag
+    pub name: Option<String>,
+    pub score: f64,
+    /// Uses in the interval before the report
+    pub posts: u64,
+    pub engagements: u64,
+    pub baseline: f64,
+}
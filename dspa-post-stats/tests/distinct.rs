@@ -0,0 +1,63 @@
+use dspa_post_stats::distinct::{Counting, HyperLogLog, Users};
+
+#[test]
+fn estimates_are_within_the_standard_error() {
+    for &(precision, count) in &[(12, 100), (12, 10_000), (10, 100_000)] {
+        let mut sketch = HyperLogLog::new(precision);
+        (0..count).for_each(|person| sketch.insert(person));
+        // Duplicates do not change the estimate
+        (0..count).for_each(|person| sketch.insert(person));
+
+        let error = (sketch.estimate() - count as f64).abs() / count as f64;
+        let standard_error = 1.04 / f64::from(1u32 << precision).sqrt();
+        assert!(
+            error < 3.0 * standard_error,
+            "Error {} for {} users with precision {}",
+            error,
+            count,
+            precision
+        );
+    }
+}
+
+#[test]
+fn sketches_of_few_users_stay_small() {
+    let mut sketch = HyperLogLog::new(16);
+    (0..10).for_each(|person| sketch.insert(person));
+    assert!(bincode::serialize(&sketch).unwrap().len() < 100);
+    assert_eq!(sketch.estimate().round(), 10.0);
+
+    // Merged sketches equal the sketch of all users, whether they are sparse or dense
+    let mut merged = sketch.clone();
+    let mut other = HyperLogLog::new(16);
+    (10..10_000).for_each(|person| other.insert(person));
+    merged.merge(&other);
+    (10..10_000).for_each(|person| sketch.insert(person));
+    assert_eq!(merged, sketch);
+}
+
+#[test]
+fn counting_modes_share_the_same_interface() {
+    let mut exact = Users::new(Counting::Exact);
+    let mut approximate = Users::new(Counting::Approximate(12));
+    let mut compare = Users::new(Counting::Compare(12));
+    for person in &[1, 2, 3, 2, 1] {
+        exact.insert(*person);
+        approximate.insert(*person);
+        compare.insert(*person);
+    }
+
+    assert_eq!(exact.len(), 3);
+    assert_eq!(approximate.len(), 3);
+    assert_eq!(compare.len(), 3);
+
+    assert_eq!(exact.error(), None);
+    assert_eq!(approximate.error(), None);
+    assert!(compare.error().unwrap() < 0.01);
+}
+
+#[test]
+#[should_panic]
+fn precision_is_bounded() {
+    HyperLogLog::new(20);
+}
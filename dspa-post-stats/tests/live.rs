@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use structopt::StructOpt;
+use timely::dataflow::operators::{Concat, Map};
+
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::operators::Gather;
+use dspa_lib::records::PostRecord;
+use dspa_lib::store::{MemoryStore, SocialGraphStore};
+use dspa_lib::testing::{comment, like, post, Harness};
+use dspa_post_stats::live::{LivePosts, QueryArgs, POST_STATS_QUERY_PORT};
+use dspa_post_stats::operators::{Live, PostStats};
+use dspa_post_stats::{ActivePostEvent, PostStatsConfig};
+
+const MIN_30: u64 = 30 * 60;
+const HR_12: u64 = 60 * 60 * 12;
+
+/// Answers to the queries after every report time applied to the live state. Report times
+/// released at once by the harness are all answered from the state of the latest one.
+fn answers(harness: Harness, urls: &'static [&'static str]) -> BTreeMap<u64, Vec<(u16, Value)>> {
+    let store = MemoryStore::new();
+    // (Post Id, Person Id, Forum Id)
+    for (id, person_id, forum_id) in &[(1, 10, 100), (2, 10, 200), (3, 11, 100)] {
+        store.insert_post(PostRecord {
+            forum_id: *forum_id,
+            ..post(*id, *person_id, 0)
+        });
+    }
+    let store: Arc<dyn SocialGraphStore> = Arc::new(store);
+
+    harness
+        .run(move |(_, comments, likes)| {
+            let comment_events = comments.map(|comment| ActivePostEvent::Comment {
+                post_id: comment.reply_to_post_id.unwrap(),
+                person_id: comment.person_id,
+            });
+            let like_events = likes.map(|like| ActivePostEvent::Like {
+                post_id: like.post_id,
+                person_id: like.person_id,
+            });
+
+            let live = LivePosts::new();
+            comment_events
+                .concat(&like_events)
+                .post_stats(
+                    store.clone(),
+                    PostStatsConfig::default(),
+                    &Checkpoints::default(),
+                )
+                .gather()
+                .live(live.clone(), store.clone())
+                .map(move |_| urls.iter().map(|url| live.respond(url)).collect::<Vec<_>>())
+        })
+        .results
+        .into_iter()
+        .map(|(time, mut answers)| {
+            assert_eq!(answers.len(), 1, "Live state is only kept on worker 0");
+            let answers = answers
+                .pop()
+                .unwrap()
+                .into_iter()
+                .map(|(status, body)| (status, serde_json::from_str(&body).unwrap()))
+                .collect();
+            (time, answers)
+        })
+        .collect()
+}
+
+fn ids(answer: &Value) -> Vec<i64> {
+    answer["posts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|post| post["id"].as_i64().unwrap())
+        .collect()
+}
+
+#[test]
+fn active_posts_are_queried_by_id_forum_and_creator() {
+    let harness = Harness::default()
+        .workers(2)
+        .event(100, comment(20, 1, 1, 100))
+        .event(200, like(2, 1, 200))
+        .event(300, like(1, 2, 300))
+        .event(400, like(1, 3, 400))
+        .event(500, like(1, 4, 500))
+        .advance_to(MIN_30 + 1);
+
+    let urls = &[
+        "/posts/1",
+        "/posts",
+        "/forums/100/posts",
+        "/persons/10/posts",
+        "/posts/5",
+    ];
+    let answers = answers(harness, urls);
+    let first = &answers[&MIN_30];
+
+    assert_eq!(first[0].0, 200);
+    assert_eq!(
+        first[0].1,
+        json!({
+            "time": MIN_30,
+            "post": {
+                "id": 1,
+                "forum_id": 100,
+                "person_id": 10,
+                "users": 2,
+                "comments": 1,
+                "replies": 0,
+                "likes": 1,
+                "max_reply_depth": 0,
+                "mean_reply_depth": 0.0,
+            }
+        })
+    );
+    assert_eq!(ids(&first[1].1), vec![1, 2, 3, 4]);
+    assert_eq!(ids(&first[2].1), vec![1, 3]);
+    assert_eq!(ids(&first[3].1), vec![1, 2]);
+    // Posts missing from the store are active without forum and creator
+    assert_eq!(first[1].1["posts"][3]["forum_id"], Value::Null);
+
+    assert_eq!(first[4].0, 404);
+    assert_eq!(first[4].1, json!({ "time": MIN_30, "post": null }));
+}
+
+#[test]
+fn answers_are_consistent_with_a_completed_report() {
+    let harness = Harness::default()
+        .workers(2)
+        .event(100, like(1, 1, 100))
+        .advance_to(MIN_30 + 100)
+        .event(MIN_30 + 100, like(2, 1, MIN_30 + 100))
+        .event(MIN_30 + 200, like(1, 2, MIN_30 + 200))
+        .advance_to(2 * MIN_30 + 1);
+
+    let answers = answers(harness, &["/posts"]);
+
+    let posts = |time: u64| answers[&time][0].1["posts"].clone();
+    assert_eq!(answers[&MIN_30][0].1["time"], json!(MIN_30));
+    assert_eq!(posts(MIN_30), json!([post_json(1, 1, 1)]));
+    assert_eq!(
+        posts(2 * MIN_30),
+        json!([post_json(1, 2, 2), post_json(2, 1, 1)])
+    );
+
+    // Expired posts are removed once their deactivation is reported
+    let last = *answers.keys().last().unwrap();
+    assert_eq!(last, HR_12 + MIN_30 * 2);
+    assert_eq!(posts(last), json!([]));
+}
+
+fn post_json(id: i32, users: u64, likes: u64) -> Value {
+    let (forum_id, person_id) = match id {
+        1 => (100, 10),
+        _ => (200, 10),
+    };
+    json!({
+        "id": id,
+        "forum_id": forum_id,
+        "person_id": person_id,
+        "users": users,
+        "comments": 0,
+        "replies": 0,
+        "likes": likes,
+        "max_reply_depth": 0,
+        "mean_reply_depth": 0.0,
+    })
+}
+
+#[test]
+fn query_endpoint_is_opt_in() {
+    let port = |args: &[&str]| QueryArgs::from_iter(args).port();
+
+    assert_eq!(port(&["dspa-post-stats"]), None);
+    assert_eq!(
+        port(&["dspa-post-stats", "--query-port"]),
+        Some(POST_STATS_QUERY_PORT)
+    );
+    assert_eq!(
+        port(&["dspa-post-stats", "--query-port", "9300"]),
+        Some(9300)
+    );
+}
@@ -0,0 +1,623 @@
+use std::env::temp_dir;
+use std::fs::remove_dir_all;
+use std::process;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use timely::dataflow::operators::{Concat, Map};
+
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::error::{DspaError, Result};
+use dspa_lib::metrics::SEEDING_FAILURES;
+use dspa_lib::operators::Checkpoint;
+use dspa_lib::records::{CommentRecord, PostRecord};
+use dspa_lib::store::{MemoryStore, SocialGraphStore};
+use dspa_lib::testing::{comment, like, post, reply, Harness};
+use dspa_post_stats::operators::{PostStats, TopPosts};
+use dspa_post_stats::{
+    ActivePostEvent, Change, Metric, PostReport, PostStatsConfig, Ranking, Reactivation, Retention,
+    Schedule, ScheduleArgs,
+};
+
+const MIN_30: u64 = 30 * 60;
+const HR: u64 = 60 * 60;
+const HR_12: u64 = 60 * 60 * 12;
+
+// (Post Id, Users, Comments, Likes)
+type Stats = (i32, usize, u64, u64);
+
+fn post_stats(harness: Harness) -> Vec<(u64, Vec<Stats>)> {
+    post_stats_with(harness, Checkpoints::default())
+}
+
+fn post_stats_with(harness: Harness, checkpoints: Checkpoints) -> Vec<(u64, Vec<Stats>)> {
+    post_stats_configured(harness, PostStatsConfig::default(), checkpoints)
+}
+
+fn post_stats_configured(
+    harness: Harness,
+    config: PostStatsConfig,
+    checkpoints: Checkpoints,
+) -> Vec<(u64, Vec<Stats>)> {
+    reports(harness, config, checkpoints)
+        .into_iter()
+        .map(|(time, reports)| {
+            let mut stats = reports
+                .into_iter()
+                .filter(|(change, _)| *change != Change::Deactivated)
+                .map(|(_, stats)| stats)
+                .collect::<Vec<_>>();
+            stats.sort();
+            (time, stats)
+        })
+        .filter(|(_, stats)| !stats.is_empty())
+        .collect()
+}
+
+fn reports(
+    harness: Harness,
+    config: PostStatsConfig,
+    checkpoints: Checkpoints,
+) -> Vec<(u64, Vec<(Change, Stats)>)> {
+    let store: Arc<dyn SocialGraphStore> = Arc::new(MemoryStore::new());
+
+    harness
+        .run(move |(_, comments, likes)| {
+            let comment_events = comments.map(|comment| ActivePostEvent::Comment {
+                post_id: comment.reply_to_post_id.unwrap(),
+                person_id: comment.person_id,
+            });
+            let like_events = likes.map(|like| ActivePostEvent::Like {
+                post_id: like.post_id,
+                person_id: like.person_id,
+            });
+
+            comment_events
+                .concat(&like_events)
+                .post_stats(store.clone(), config, &checkpoints)
+                .checkpoint(&checkpoints)
+                .map(|PostReport { change, post }| {
+                    (
+                        change,
+                        (post.id(), post.users(), post.comments(), post.likes()),
+                    )
+                })
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn reports_active_posts_every_30_minutes() {
+    let harness = Harness::default()
+        .event(100, comment(10, 1, 1, 100))
+        .event(200, like(2, 1, 200))
+        .event(300, like(1, 1, 300))
+        .advance_to(MIN_30)
+        .event(MIN_30 + 100, comment(11, 3, 2, MIN_30 + 100));
+
+    let output = post_stats(harness);
+
+    assert_eq!(output[0], (MIN_30, vec![(1, 2, 1, 2)]));
+    assert_eq!(output[1], (2 * MIN_30, vec![(1, 2, 1, 2), (2, 1, 1, 0)]));
+}
+
+#[test]
+fn posts_expire_after_12_hours_of_inactivity() {
+    let harness = Harness::default().event(100, comment(10, 1, 1, 100));
+
+    let output = post_stats(harness);
+
+    // Active from the first report up to and including the last one before expiry
+    let times = output.iter().map(|(time, _)| *time).collect::<Vec<_>>();
+    let expected = (1..=(HR_12 / MIN_30))
+        .map(|i| i * MIN_30)
+        .collect::<Vec<_>>();
+    assert_eq!(times, expected);
+}
+
+#[test]
+fn expired_posts_are_not_tracked_again() {
+    let harness = Harness::default()
+        .event(100, comment(10, 1, 1, 100))
+        .advance_to(2 * HR_12)
+        .event(2 * HR_12 + 100, like(2, 1, 2 * HR_12 + 100))
+        .advance_to(3 * HR_12);
+
+    let output = post_stats(harness);
+
+    assert!(output.iter().all(|(time, _)| *time <= HR_12));
+}
+
+#[test]
+fn inconsistent_schedules_are_usage_errors() {
+    let args = ScheduleArgs {
+        ttl: HR,
+        report_interval: MIN_30,
+        users_interval: Some(HR + 1),
+        counts_interval: None,
+        report_offset: 0,
+    };
+    assert!(args.schedule().is_err());
+
+    let args = ScheduleArgs {
+        report_interval: 0,
+        users_interval: None,
+        ..args
+    };
+    assert!(args.schedule().is_err());
+}
+
+#[test]
+fn reports_follow_the_configured_schedule() {
+    let schedule = Schedule {
+        ttl: 2 * HR,
+        interval: MIN_30,
+        users_interval: HR,
+        counts_interval: MIN_30,
+        offset: 10 * 60,
+    };
+    let harness = Harness::default()
+        .event(100, like(1, 1, 100))
+        .event(MIN_30 + 100, like(2, 1, MIN_30 + 100))
+        .event(MIN_30 + 200, like(3, 1, MIN_30 + 200));
+
+    let config = PostStatsConfig {
+        schedule,
+        ..PostStatsConfig::default()
+    };
+    let output = post_stats_configured(harness, config, Checkpoints::default());
+
+    // Reports at 10 and 40 minutes past the hour, users are only updated every hour
+    assert_eq!(output[0], (10 * 60, vec![(1, 1, 0, 1)]));
+    assert_eq!(output[1], (10 * 60 + MIN_30, vec![(1, 1, 0, 3)]));
+    assert_eq!(output[2], (10 * 60 + HR, vec![(1, 3, 0, 3)]));
+
+    // Inactive 2 hours after the last like
+    let last = output.last().unwrap().0;
+    assert_eq!(last, 10 * 60 + 2 * HR);
+}
+
+#[test]
+fn changes_are_reported_with_their_kind() {
+    let harness = Harness::default()
+        .event(100, like(1, 1, 100))
+        .event(200, like(1, 2, 200))
+        .advance_to(MIN_30)
+        .event(MIN_30 + 100, like(2, 1, MIN_30 + 100));
+
+    let output = reports(harness, PostStatsConfig::default(), Checkpoints::default());
+
+    assert_eq!(
+        output[0],
+        (
+            MIN_30,
+            vec![
+                (Change::Activated, (1, 1, 0, 1)),
+                (Change::Activated, (2, 1, 0, 1))
+            ]
+        )
+    );
+    assert_eq!(
+        output[1],
+        (
+            2 * MIN_30,
+            vec![
+                (Change::Updated, (1, 2, 0, 2)),
+                (Change::Unchanged, (2, 1, 0, 1))
+            ]
+        )
+    );
+
+    // Post 2 expires first and is reported once more with its last statistics
+    let (time, deactivated) = output
+        .iter()
+        .find(|(_, reports)| {
+            reports
+                .iter()
+                .any(|(change, _)| *change == Change::Deactivated)
+        })
+        .unwrap();
+    assert_eq!(*time, HR_12 + MIN_30);
+    assert_eq!(
+        *deactivated,
+        vec![
+            (Change::Unchanged, (1, 2, 0, 2)),
+            (Change::Deactivated, (2, 1, 0, 1))
+        ]
+    );
+    assert_eq!(
+        output.last().unwrap(),
+        &(
+            HR_12 + 2 * MIN_30,
+            vec![(Change::Deactivated, (1, 2, 0, 2))]
+        )
+    );
+}
+
+#[test]
+fn replies_are_counted_apart_from_comments() {
+    let store = MemoryStore::new();
+    store.insert_post(post(1, 1, 0));
+    let comments = vec![
+        comment(10, 2, 1, 100),
+        reply(11, 3, 10, 200),
+        reply(12, 2, 11, 300),
+        reply(13, 4, 10, 400),
+        comment(14, 3, 1, 500),
+    ];
+    comments
+        .iter()
+        .cloned()
+        .for_each(|comment| store.insert_comment(comment));
+    let store: Arc<dyn SocialGraphStore> = Arc::new(store);
+
+    let harness = comments
+        .into_iter()
+        .fold(Harness::default(), |harness, comment| {
+            let time = comment.creation_date.timestamp() as u64;
+            harness.event(time, comment)
+        })
+        .advance_to(MIN_30 + 1);
+
+    let output = harness
+        .run(move |(_, comments, _)| {
+            let lookup = store.clone();
+            comments
+                .map(move |comment| ActivePostEvent::comment(&comment, &*lookup).unwrap())
+                .post_stats(
+                    store.clone(),
+                    PostStatsConfig::default(),
+                    &Checkpoints::default(),
+                )
+                .map(|report| report.post)
+                .map(|post| {
+                    (
+                        post.id(),
+                        post.users(),
+                        post.comments(),
+                        post.replies(),
+                        post.max_reply_depth(),
+                        post.mean_reply_depth(),
+                    )
+                })
+        })
+        .results
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    // Replies at depths 1, 2 and 1
+    assert_eq!(output[0], (MIN_30, vec![(1, 3, 2, 3, 2, 4.0 / 3.0)]));
+}
+
+#[test]
+fn expired_posts_are_reactivated_according_to_policy() {
+    let harness = || {
+        Harness::default()
+            .event(100, like(1, 1, 100))
+            .event(200, like(2, 1, 200))
+            .advance_to(2 * HR_12)
+            .event(2 * HR_12 + 100, like(3, 1, 2 * HR_12 + 100))
+    };
+    let reactivated = |reactivation| {
+        let retention = Retention {
+            reactivation,
+            ..Retention::default()
+        };
+        let config = PostStatsConfig {
+            retention,
+            ..PostStatsConfig::default()
+        };
+        reports(harness(), config, Checkpoints::default())
+            .into_iter()
+            .find(|(time, _)| *time > 2 * HR_12)
+            .map(|(_, reports)| reports)
+    };
+
+    assert_eq!(reactivated(Reactivation::Ignore), None);
+    assert_eq!(
+        reactivated(Reactivation::Reset),
+        Some(vec![(Change::Activated, (1, 1, 0, 1))])
+    );
+    assert_eq!(
+        reactivated(Reactivation::Keep),
+        Some(vec![(Change::Activated, (1, 3, 0, 3))])
+    );
+}
+
+#[test]
+fn inactive_posts_are_forgotten() {
+    let retention = Retention {
+        reactivation: Reactivation::Ignore,
+        forget_after: HR_12,
+    };
+    let harness = Harness::default()
+        .event(100, like(1, 1, 100))
+        .advance_to(2 * HR_12)
+        .event(2 * HR_12 + 100, like(2, 2, 2 * HR_12 + 100))
+        .advance_to(3 * HR_12)
+        .event(3 * HR_12 + 100, like(3, 1, 3 * HR_12 + 100));
+
+    let config = PostStatsConfig {
+        retention,
+        ..PostStatsConfig::default()
+    };
+    let output = reports(harness, config, Checkpoints::default());
+
+    // Post 1 is inactive from 12 hours and forgotten after 24 hours, when post 2 is reported
+    let (_, reports) = output.iter().find(|(time, _)| *time > 3 * HR_12).unwrap();
+    assert!(reports.contains(&(Change::Activated, (1, 1, 0, 1))));
+}
+
+#[test]
+fn inactive_posts_are_forgotten_without_other_activity() {
+    let retention = Retention {
+        reactivation: Reactivation::Ignore,
+        forget_after: HR_12,
+    };
+    let harness = Harness::default()
+        .event(100, like(1, 1, 100))
+        .advance_to(3 * HR_12)
+        .event(3 * HR_12 + 100, like(3, 1, 3 * HR_12 + 100));
+
+    let config = PostStatsConfig {
+        retention,
+        ..PostStatsConfig::default()
+    };
+    let output = reports(harness, config, Checkpoints::default());
+
+    // No report forgets post 1 before its next like, which still finds it forgotten
+    let (_, reports) = output.iter().find(|(time, _)| *time > 3 * HR_12).unwrap();
+    assert_eq!(reports, &vec![(Change::Activated, (1, 1, 0, 1))]);
+}
+
+fn seeded(workers: usize) -> Vec<(u64, Vec<Stats>)> {
+    // Records are in the database before they are published
+    let store = MemoryStore::new();
+    store.insert_post(post(1, 1, 0));
+    store.insert_like(like(4, 1, 100));
+    store.insert_comment(comment(10, 1, 1, 3 * HR - 600));
+    store.insert_comment(reply(11, 3, 10, 3 * HR - 300));
+    store.insert_like(like(2, 1, 3 * HR - 200));
+    store.insert_like(like(5, 2, 3 * HR + 100));
+    seeded_from(Arc::new(store), workers)
+}
+
+fn seeded_from(store: Arc<dyn SocialGraphStore>, workers: usize) -> Vec<(u64, Vec<Stats>)> {
+    let harness = Harness::default()
+        .workers(workers)
+        .event(3 * HR - 200, like(2, 1, 3 * HR - 200))
+        .advance_to(3 * HR)
+        .event(3 * HR + 100, like(5, 2, 3 * HR + 100));
+
+    harness
+        .run(move |(_, _, likes)| {
+            likes
+                .map(|like| ActivePostEvent::Like {
+                    post_id: like.post_id,
+                    person_id: like.person_id,
+                })
+                .post_stats(
+                    store.clone(),
+                    PostStatsConfig {
+                        schedule: Schedule {
+                            ttl: 2 * HR,
+                            ..Schedule::default()
+                        },
+                        seed: true,
+                        ..PostStatsConfig::default()
+                    },
+                    &Checkpoints::default(),
+                )
+                .map(|report| report.post)
+                .map(|post| (post.id(), post.users(), post.comments(), post.likes()))
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn post_stats_are_seeded_from_the_store() {
+    let output = seeded(1);
+
+    // The like at 100 is older than the TTL, the like before the frontier is not counted twice
+    assert_eq!(output[0], (3 * HR, vec![(1, 3, 1, 1)]));
+    assert_eq!(
+        output[1],
+        (3 * HR + MIN_30, vec![(1, 3, 1, 1), (2, 1, 0, 1)])
+    );
+    assert_eq!(seeded(3), output);
+}
+
+#[test]
+fn seeds_arriving_after_the_first_event_are_applied_on_every_worker() {
+    let store = MemoryStore::new();
+    for id in 1..=8 {
+        store.insert_post(post(id, id, 0));
+        store.insert_like(like(id + 10, id, HR + id as u64));
+    }
+    // The first received event is before any seed was sent
+    store.insert_like(like(1, 1, 3 * HR - 200));
+
+    let output = seeded_from(Arc::new(store), 4);
+
+    // Every seeded post is reported at its first report time, on whichever worker owns it
+    let seeded: Vec<Stats> = (1..=8).map(|id| (id, 1, 0, 1)).collect();
+    assert_eq!(output[0], (3 * MIN_30, seeded));
+    // The received like before the frontier is only counted once
+    assert_eq!(output[3].0, 3 * HR);
+    assert_eq!(output[3].1[0], (1, 2, 0, 2));
+}
+
+/// Store whose stream history cannot be read
+struct UnavailableStore;
+
+impl SocialGraphStore for UnavailableStore {
+    fn post(&self, _id: i32) -> Result<Option<PostRecord>> {
+        Ok(None)
+    }
+
+    fn comment(&self, _id: i32) -> Result<Option<CommentRecord>> {
+        Ok(None)
+    }
+
+    fn acquaintances(&self, _person_id: i32) -> Result<Vec<i32>> {
+        Ok(Vec::new())
+    }
+
+    fn comments_between(
+        &self,
+        _from: DateTime<Utc>,
+        _to: DateTime<Utc>,
+    ) -> Result<Vec<CommentRecord>> {
+        Err(DspaError::Unavailable("connection refused".to_owned()))
+    }
+}
+
+#[test]
+fn failed_seeding_keeps_the_received_events() {
+    let failures = SEEDING_FAILURES.get();
+    let output = seeded_from(Arc::new(UnavailableStore), 2);
+
+    assert_eq!(SEEDING_FAILURES.get(), failures + 1);
+    assert_eq!(output[0], (3 * HR, vec![(1, 1, 0, 1)]));
+}
+
+#[test]
+fn seeded_threads_are_resolved_above_the_range() {
+    let store = MemoryStore::new();
+    store.insert_post(post(1, 1, 0));
+    store.insert_comment(comment(10, 1, 1, 0));
+    store.insert_comment(reply(11, 2, 10, 50));
+    store.insert_comment(reply(12, 3, 11, 200));
+    store.insert_comment(comment(13, 4, 1, 250));
+
+    let events = ActivePostEvent::between(&store, 100, 300)
+        .unwrap()
+        .into_iter()
+        .map(|(time, event)| (time, format!("{:?}", event)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        vec![
+            (
+                200,
+                "Reply { post_id: 1, person_id: 3, depth: 2 }".to_owned()
+            ),
+            (250, "Comment { post_id: 1, person_id: 4 }".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn results_are_independent_of_worker_count() {
+    let events = (0..20)
+        .map(|i| {
+            (
+                100 + i as u64 * 60,
+                like(i % 5, i % 3, 100 + i as u64 * 60).into(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let single = post_stats(Harness::new(events.clone()));
+    let multiple = post_stats(Harness::new(events).workers(4));
+
+    assert_eq!(single, multiple);
+    assert_eq!(
+        single[0],
+        (MIN_30, vec![(0, 5, 0, 7), (1, 5, 0, 7), (2, 5, 0, 6)])
+    );
+}
+
+fn top_posts(harness: Harness, k: usize, ranking: Ranking) -> Vec<(u64, Vec<(usize, i32)>)> {
+    let store: Arc<dyn SocialGraphStore> = Arc::new(MemoryStore::new());
+
+    harness
+        .run(move |(_, _, likes)| {
+            likes
+                .map(|like| ActivePostEvent::Like {
+                    post_id: like.post_id,
+                    person_id: like.person_id,
+                })
+                .post_stats(
+                    store.clone(),
+                    PostStatsConfig::default(),
+                    &Checkpoints::default(),
+                )
+                .map(|report| report.post)
+                .top_posts(k, ranking)
+                .map(|top| {
+                    top.into_iter()
+                        .map(|ranked| (ranked.rank, ranked.post.id()))
+                        .collect::<Vec<_>>()
+                })
+        })
+        .results
+        .into_iter()
+        .map(|(time, mut tops)| (time, tops.pop().unwrap()))
+        .collect()
+}
+
+#[test]
+fn top_posts_are_merged_across_workers() {
+    // Post i receives i likes from i different people
+    let events = (1..=8)
+        .flat_map(|post| (0..post).map(move |person| (post, person)))
+        .enumerate()
+        .map(|(i, (post, person))| {
+            let time = 100 + i as u64;
+            (time, like(person, post, time).into())
+        })
+        .collect::<Vec<_>>();
+    let ranking = Ranking {
+        metric: Metric::Likes,
+        ..Ranking::default()
+    };
+
+    let single = top_posts(Harness::new(events.clone()), 3, ranking);
+    let multiple = top_posts(Harness::new(events).workers(4), 3, ranking);
+
+    assert_eq!(single, multiple);
+    assert_eq!(single[0], (MIN_30, vec![(1, 8), (2, 7), (3, 6)]));
+}
+
+#[test]
+fn restored_state_continues_like_uninterrupted_run() {
+    let dir = temp_dir().join(format!("dspa-post-stats-{}", process::id()));
+    let events = (0..40)
+        .map(|i| {
+            let time = 100 + i as u64 * 300;
+            (time, like(i % 7, i % 4, time).into())
+        })
+        .collect::<Vec<_>>();
+
+    let (before, after): (Vec<_>, Vec<_>) = events
+        .iter()
+        .cloned()
+        .partition(|(time, _)| *time <= 2 * MIN_30);
+
+    // Interrupted after the checkpoint at 1h, the remaining events are replayed after it
+    post_stats_with(
+        Harness::new(before).advance_to(2 * MIN_30 + 1),
+        Checkpoints::new(dir.clone(), 2 * MIN_30, false),
+    );
+    let restored = post_stats_with(
+        Harness::new(after),
+        Checkpoints::new(dir.clone(), 2 * MIN_30, true),
+    );
+    remove_dir_all(dir).unwrap();
+
+    let uninterrupted = post_stats(Harness::new(events));
+    let expected = uninterrupted
+        .into_iter()
+        .filter(|(time, _)| *time > 2 * MIN_30)
+        .collect::<Vec<_>>();
+
+    assert!(!expected.is_empty());
+    assert_eq!(restored, expected);
+}
@@ -0,0 +1,211 @@
+use std::env::temp_dir;
+use std::fs::{read_to_string, remove_file};
+use std::process;
+use std::sync::Arc;
+
+use structopt::StructOpt;
+
+use timely::dataflow::operators::{Concat, Inspect, Map};
+
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::records::{PostRecord, TagClassIsSubclassOfRecord, TagHasTypeRecord};
+use dspa_lib::sink::ResultRow;
+use dspa_lib::store::{MemoryStore, SocialGraphStore};
+use dspa_lib::testing::{comment, like, post, Harness};
+use dspa_post_stats::distinct::Counting;
+use dspa_post_stats::operators::{PostStats, Rollups};
+use dspa_post_stats::rollups::{Group, Rollup};
+use dspa_post_stats::{
+    ActivePost, ActivePostEvent, Args, PostStatsConfig, PostStatsRow, RollupRow,
+};
+
+const MIN_30: u64 = 30 * 60;
+
+// (Kind, Group Id, Posts, Users, Comments, Likes)
+type Stats = (String, i32, u64, usize, u64, u64);
+
+fn store() -> Arc<dyn SocialGraphStore> {
+    let mut store = MemoryStore::new();
+    // (Post Id, Forum Id, Tags)
+    for (id, forum_id, tags) in &[(1, 100, vec![1, 2]), (2, 100, vec![2]), (3, 200, vec![3])] {
+        store.insert_post(PostRecord {
+            forum_id: *forum_id,
+            tags: tags.clone(),
+            ..post(*id, 0, 0)
+        });
+    }
+    // Tags 1 and 2 have the classes 10 and 11, which are both subclasses of 20
+    for (tag_id, tag_class_id) in &[(1, 10), (2, 11), (3, 30)] {
+        store.insert_tag_type(TagHasTypeRecord {
+            tag_id: *tag_id,
+            tag_class_id: *tag_class_id,
+        });
+    }
+    for (tag_class_id, parent_id) in &[(10, 20), (11, 20), (20, 10)] {
+        store.insert_subclass(TagClassIsSubclassOfRecord {
+            tag_class_id: *tag_class_id,
+            parent_id: *parent_id,
+        });
+    }
+    Arc::new(store)
+}
+
+fn rollups(harness: Harness, hierarchy: bool) -> Vec<(u64, Vec<Stats>)> {
+    let store = store();
+
+    harness
+        .run(move |(posts, comments, likes)| {
+            let observed = store.clone();
+            posts.inspect(move |post| observed.observe_post(post));
+
+            let comment_events = comments.map(|comment| ActivePostEvent::Comment {
+                post_id: comment.reply_to_post_id.unwrap(),
+                person_id: comment.person_id,
+            });
+            let like_events = likes.map(|like| ActivePostEvent::Like {
+                post_id: like.post_id,
+                person_id: like.person_id,
+            });
+
+            comment_events
+                .concat(&like_events)
+                .post_stats(
+                    store.clone(),
+                    PostStatsConfig::default(),
+                    &Checkpoints::default(),
+                )
+                .rollups(store.clone(), hierarchy)
+                .map(|rollup| {
+                    (
+                        rollup.group.kind().to_owned(),
+                        rollup.group.id(),
+                        rollup.posts,
+                        rollup.users(),
+                        rollup.comments,
+                        rollup.likes,
+                    )
+                })
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+fn harness() -> Harness {
+    Harness::default()
+        .workers(3)
+        .event(100, comment(10, 1, 1, 100))
+        .event(200, like(2, 1, 200))
+        .event(300, like(1, 2, 300))
+        .event(400, like(3, 3, 400))
+        .event(500, like(4, 4, 500))
+}
+
+#[test]
+fn active_posts_are_rolled_up_per_forum_and_tag() {
+    let output = rollups(harness(), false);
+
+    let stats = |kind: &str, id, posts, users, comments, likes| {
+        (kind.to_owned(), id, posts, users, comments, likes)
+    };
+    // Person 1 engaged with posts 1 and 2 and counts once, post 4 is not in the store
+    assert_eq!(
+        output[0],
+        (
+            MIN_30,
+            vec![
+                stats("forum", 100, 2, 2, 1, 2),
+                stats("forum", 200, 1, 1, 0, 1),
+                stats("tag", 1, 1, 2, 1, 1),
+                stats("tag", 2, 2, 2, 1, 2),
+                stats("tag", 3, 1, 1, 0, 1),
+            ]
+        )
+    );
+}
+
+#[test]
+fn tags_are_rolled_up_along_the_class_hierarchy() {
+    let output = rollups(harness(), true);
+
+    let classes = output[0]
+        .1
+        .iter()
+        .filter(|(kind, ..)| kind == "tag_class")
+        .map(|(_, id, posts, users, ..)| (*id, *posts, *users))
+        .collect::<Vec<_>>();
+    // Post 1 counts once towards class 20 and through the cycle towards class 10
+    assert_eq!(
+        classes,
+        vec![(10, 2, 2), (11, 2, 2), (20, 2, 2), (30, 1, 1)]
+    );
+}
+
+#[test]
+fn posts_missing_from_the_store_are_looked_up_again() {
+    let harness = Harness::default()
+        .event(100, like(1, 4, 100))
+        .advance_to(MIN_30 + 1)
+        .event(MIN_30 + 100, post(4, 1, MIN_30 + 100));
+    let output = rollups(harness, false);
+
+    // Post 4 only belongs to its forum once it is in the store
+    assert_eq!(
+        output[0],
+        (2 * MIN_30, vec![("forum".to_owned(), 0, 1, 1, 0, 1)])
+    );
+}
+
+#[test]
+fn rollups_are_written_to_their_own_file() {
+    let path = temp_dir().join(format!("dspa-rollups-{}.jsonl", process::id()));
+    let args = Args::from_iter(&[
+        "dspa-post-stats",
+        "--rollups",
+        "--output",
+        "json",
+        "--output-path",
+        path.to_str().unwrap(),
+    ]);
+
+    let post = ActivePost::new(1, Counting::Exact);
+    let mut posts = args.sink.sink::<PostStatsRow>();
+    let mut rollups = args.sink.sink_at::<RollupRow>(args.rollup_path());
+    posts
+        .write(MIN_30, &PostStatsRow::rows(MIN_30, &post))
+        .unwrap();
+    rollups
+        .write(
+            MIN_30,
+            &RollupRow::rows(MIN_30, &Rollup::new(Group::Forum(100), &post)),
+        )
+        .unwrap();
+    drop((posts, rollups));
+
+    let rollup_path = args.rollup_path().unwrap();
+    let written = (
+        read_to_string(&path).unwrap(),
+        read_to_string(&rollup_path).unwrap(),
+    );
+    remove_file(path).unwrap();
+    remove_file(rollup_path).unwrap();
+
+    assert!(written
+        .0
+        .starts_with("{\"timestamp\":\"1970-01-01T00:30:00Z\",\"post_id\":1,"));
+    assert_eq!(written.0.lines().count(), 1);
+    assert!(written
+        .1
+        .starts_with("{\"timestamp\":\"1970-01-01T00:30:00Z\",\"kind\":\"forum\","));
+    assert_eq!(written.1.lines().count(), 1);
+}
+
+#[test]
+fn rollups_are_not_mixed_into_the_results_on_stdout() {
+    let args = Args::from_iter(&["dspa-post-stats", "--rollups", "--output", "csv"]);
+    assert!(args.sink.additional_path("rollup", None).is_err());
+
+    let args = Args::from_iter(&["dspa-post-stats", "--rollups"]);
+    assert_eq!(args.rollup_path(), None);
+}
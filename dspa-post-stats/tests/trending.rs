@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use structopt::StructOpt;
+use timely::dataflow::operators::{Concat, Map};
+
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::records::{PostRecord, TagRecord};
+use dspa_lib::store::{MemoryStore, SocialGraphStore};
+use dspa_lib::testing::{like, post, Harness};
+use dspa_post_stats::operators::{EngagedTags, TrendingTags};
+use dspa_post_stats::trending::TrendingArgs;
+use dspa_post_stats::trending::{TagUse, Trending};
+use dspa_post_stats::{ActivePostEvent, Args};
+
+const HR: u64 = 60 * 60;
+
+// (Rank, Tag Id, Name, Posts, Engagements)
+type Stats = (usize, i32, Option<String>, u64, u64);
+
+fn tagged(id: i32, tags: Vec<i32>, time: u64) -> PostRecord {
+    PostRecord {
+        tags,
+        ..post(id, 0, time)
+    }
+}
+
+fn trending_tags(harness: Harness, posts: Vec<PostRecord>, k: usize) -> Vec<(u64, Vec<Stats>)> {
+    let mut store = MemoryStore::new();
+    store.insert_tag(TagRecord {
+        id: 1,
+        name: "Rust".to_owned(),
+        url: String::new(),
+    });
+    posts.into_iter().for_each(|post| store.insert_post(post));
+    let store: Arc<dyn SocialGraphStore> = Arc::new(store);
+
+    harness
+        .run(move |(posts, _, likes)| {
+            let engaged = likes
+                .map(|like| ActivePostEvent::Like {
+                    post_id: like.post_id,
+                    person_id: like.person_id,
+                })
+                .engaged_tags(&posts, store.clone(), HR);
+
+            posts
+                .flat_map(|post| TagUse::posted(&post))
+                .concat(&engaged)
+                .trending_tags(
+                    k,
+                    Trending::default(),
+                    store.clone(),
+                    &Checkpoints::default(),
+                )
+                .map(|tags| {
+                    tags.into_iter()
+                        .map(|tag| (tag.rank, tag.tag_id, tag.name, tag.posts, tag.engagements))
+                        .collect::<Vec<_>>()
+                })
+        })
+        .results
+        .into_iter()
+        .map(|(time, mut tags)| (time, tags.pop().unwrap()))
+        .collect()
+}
+
+#[test]
+fn tags_are_ranked_by_burst_score_with_their_names() {
+    let posts = vec![
+        tagged(1, vec![1, 3], 100),
+        tagged(2, vec![1], 200),
+        tagged(3, vec![1, 2], 300),
+        tagged(4, vec![4], 0),
+    ];
+    let mut harness = Harness::default().workers(2);
+    for post in posts.iter().take(3) {
+        harness = harness.event(post.creation_date.timestamp() as u64, post.clone());
+    }
+    // Two likes weigh as much as one post
+    let harness = harness
+        .event(400, like(1, 4, 400))
+        .event(500, like(2, 4, 500));
+
+    let output = trending_tags(harness, posts, 4);
+
+    assert_eq!(
+        output[0],
+        (
+            HR,
+            vec![
+                (1, 1, Some("Rust".to_owned()), 3, 0),
+                (2, 2, None, 1, 0),
+                (3, 3, None, 1, 0),
+                (4, 4, None, 0, 2),
+            ]
+        )
+    );
+}
+
+#[test]
+fn bursts_outrank_steady_use() {
+    let mut posts = Vec::new();
+    for hour in 0..48 {
+        posts.push(tagged(2 * hour as i32, vec![1], hour * HR + 100));
+        posts.push(tagged(2 * hour as i32 + 1, vec![1], hour * HR + 200));
+    }
+    posts.push(tagged(1000, vec![2], 47 * HR + 300));
+    posts.push(tagged(1001, vec![2], 47 * HR + 400));
+
+    let harness = posts
+        .iter()
+        .fold(Harness::default().workers(2), |harness, post| {
+            harness.event(post.creation_date.timestamp() as u64, post.clone())
+        });
+
+    let output = trending_tags(harness, posts, 2);
+
+    let ranked = |time: u64| {
+        output
+            .iter()
+            .find(|(report, _)| *report == time)
+            .map(|(_, tags)| tags.iter().map(|tag| tag.1).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    assert_eq!(ranked(HR), vec![1]);
+    assert_eq!(ranked(48 * HR), vec![2, 1]);
+    // Without further use, the short-term average of the steady tag drops below its baseline
+    assert_eq!(ranked(50 * HR), vec![2]);
+}
+
+#[test]
+fn inconsistent_trending_arguments_are_usage_errors() {
+    let args = TrendingArgs::from_iter(&["dspa-post-stats", "--trending-interval", "0"]);
+    assert!(args.trending().is_err());
+
+    let args = TrendingArgs::from_iter(&["dspa-post-stats", "--short-half-life", "86400"]);
+    assert!(args.trending().is_err());
+
+    // Only checked when the trending tags are reported
+    let args = Args::from_iter(&["dspa-post-stats", "--trending-interval", "0"]);
+    assert_eq!(args.trending(), None);
+}
+
+#[test]
+fn trending_tags_are_written_to_their_own_file() {
+    let args = Args::from_iter(&[
+        "dspa-post-stats",
+        "--trending",
+        "3",
+        "--rollups",
+        "--output",
+        "csv",
+        "--output-path",
+        "/tmp/posts.csv",
+    ]);
+    assert_eq!(
+        args.trending_path(),
+        Some(PathBuf::from("/tmp/posts.csv.trending"))
+    );
+    assert_eq!(
+        args.rollup_path(),
+        Some(PathBuf::from("/tmp/posts.csv.rollup"))
+    );
+
+    let args = Args::from_iter(&["dspa-post-stats", "--trending", "3", "--output", "json"]);
+    assert!(args.sink.additional_path("trending", None).is_err());
+}
@@ -1,10 +1,25 @@
 #[macro_use]
+extern crate diesel;
+#[macro_use]
 extern crate lazy_static;
 
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
 use serde_derive::{Deserialize, Serialize};
 use structopt::StructOpt;
 
+use dspa_lib::checkpoint::CheckpointArgs;
+use dspa_lib::deadletter::DeadLetterArgs;
+use dspa_lib::error::Result;
+use dspa_lib::metrics::MetricsArgs;
+use dspa_lib::partition::PartitionArgs;
 use dspa_lib::records::{CommentRecord, LikeRecord, PostRecord};
+use dspa_lib::schema::recommendation;
+use dspa_lib::sink::{ResultRow, SinkArgs};
+use dspa_lib::store::{SocialGraphStore, StoreArgs};
+use dspa_lib::subscription::SubscriptionArgs;
+use dspa_lib::TimelyArgs;
 
 pub mod operators;
 
@@ -17,6 +32,22 @@ lazy_static! {
 pub struct Args {
     #[structopt(short = "u", long = "users")]
     pub users: Vec<i32>,
+    #[structopt(flatten)]
+    pub store: StoreArgs,
+    #[structopt(flatten)]
+    pub checkpoint: CheckpointArgs,
+    #[structopt(flatten)]
+    pub sink: SinkArgs,
+    #[structopt(flatten)]
+    pub metrics: MetricsArgs,
+    #[structopt(flatten)]
+    pub partition: PartitionArgs,
+    #[structopt(flatten)]
+    pub subscription: SubscriptionArgs,
+    #[structopt(flatten)]
+    pub deadletter: DeadLetterArgs,
+    #[structopt(flatten)]
+    pub timely: TimelyArgs,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -34,4 +65,74 @@ impl RecommendationEvent {
             RecommendationEvent::Like(record) => record.person_id,
         }
     }
+
+    pub fn features(&self, store: &dyn SocialGraphStore) -> Result<Vec<Feature>> {
+        let map = match self {
+            RecommendationEvent::Post(post) => post.hashmap(),
+            RecommendationEvent::Like(like) => like.hashmap(),
+            RecommendationEvent::Comment(comment) => comment.hashmap(store)?,
+        };
+
+        Ok(map
+            .iter()
+            .filter_map(|(key, value)| match *key {
+                "post_id" => Some(Feature::Post(*value)),
+                "forum_id" => Some(Feature::Forum(*value)),
+                "place_id" => Some(Feature::Place(*value)),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+/// Item that users can have in common
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Feature {
+    Post(i32),
+    Forum(i32),
+    Place(i32),
+}
+
+/// Recommended user of a selected user at a reporting timestamp, best recommendation has rank 1
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Insertable)]
+#[table_name = "recommendation"]
+pub struct RecommendationRow {
+    pub timestamp: DateTime<Utc>,
+    pub person_id: i32,
+    pub rank: i32,
+    pub recommended_id: i32,
+}
+
+impl fmt::Display for RecommendationRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "User {} - Recommendation {}: {}",
+            self.person_id, self.rank, self.recommended_id
+        )
+    }
+}
+
+impl ResultRow for RecommendationRow {
+    type Record = (i32, Vec<i32>);
+    type Table = recommendation::table;
+
+    const NAME: &'static str = "recommendation";
+
+    fn rows(timestamp: u64, (person_id, recommended): &(i32, Vec<i32>)) -> Vec<Self> {
+        recommended
+            .iter()
+            .enumerate()
+            .map(|(rank, recommended_id)| RecommendationRow {
+                timestamp: Utc.timestamp(timestamp as i64, 0),
+                person_id: *person_id,
+                rank: rank as i32 + 1,
+                recommended_id: *recommended_id,
+            })
+            .collect()
+    }
+
+    fn table() -> Self::Table {
+        recommendation::table
+    }
 }
@@ -1,18 +1,13 @@
-use std::sync::Arc;
-
-use chrono::{TimeZone, Utc};
-use diesel::r2d2::ConnectionManager;
-use diesel::PgConnection;
-use r2d2::Pool;
 use timely::dataflow::operators::{Concat, Exchange, Inspect, Map};
 
 use zmq::Context;
 
-use dspa_lib::operators::{streams, Ordered};
-use dspa_lib::DATABASE_URL;
+use dspa_lib::metrics::RECOMMENDATIONS_METRICS_PORT;
+use dspa_lib::operators::{streams, Checkpoint, Gather, Ordered, TrackLatency, WriteTo};
+use dspa_lib::shutdown;
 
 use dspa_recommendations::operators::{Recommendations, Window};
-use dspa_recommendations::{RecommendationEvent, ARGS};
+use dspa_recommendations::{RecommendationEvent, RecommendationRow, ARGS};
 
 const HR_4: u64 = 60 * 60 * 4;
 const HR_1: u64 = 60 * 60;
@@ -20,52 +15,62 @@ const HR_1: u64 = 60 * 60;
 fn main() {
     lazy_static::initialize(&ARGS);
 
-    let pool = Arc::new(
-        Pool::builder()
-            .max_size(16)
-            .build(ConnectionManager::<PgConnection>::new(DATABASE_URL))
-            .unwrap(),
-    );
+    let store = ARGS.store.store();
+    let checkpoints = ARGS.checkpoint.checkpoints("dspa-recommendations");
+    ARGS.metrics
+        .serve(RECOMMENDATIONS_METRICS_PORT, ARGS.timely.process);
+    shutdown::handle_signals();
 
     let ctx = Context::new();
-    timely::execute(timely::Configuration::Thread, move |worker| {
-        // timely::execute(timely::Configuration::Process(num_cpus::get()), move |worker| {
+    timely::execute(ARGS.timely.configuration(), move |worker| {
         let idx = worker.index();
         let peers = worker.peers();
 
         worker.dataflow(|scope| {
-            let (posts, comments, likes) = streams(scope, idx, &ctx);
+            let (posts, comments, likes, failed_events, stamps) = streams(
+                scope,
+                idx,
+                &ctx,
+                checkpoints.restored(),
+                ARGS.partition.partitions,
+                &ARGS.subscription,
+                &ARGS.deadletter,
+            );
 
-            let comment_events = comments
+            let (comments, failed_comments) = comments
                 .exchange(|comment| comment.id as u64)
-                .ordered(idx, peers, pool.clone(), &posts)
-                .map(|comment| RecommendationEvent::Comment(comment));
-            let like_events = likes
-                .exchange(|like| like.post_id as u64)
-                .ordered(idx, peers, pool.clone(), &posts)
-                .map(|like| RecommendationEvent::Like(like));
+                .ordered(idx, peers, store.clone(), &posts, &checkpoints);
+            let comment_events = comments.map(|comment| RecommendationEvent::Comment(comment));
+            let (likes, failed_likes) = likes.exchange(|like| like.post_id as u64).ordered(
+                idx,
+                peers,
+                store.clone(),
+                &posts,
+                &checkpoints,
+            );
+            let like_events = likes.map(|like| RecommendationEvent::Like(like));
             let post_events = posts
                 .exchange(|post| post.id as u64)
                 .map(|post| RecommendationEvent::Post(post));
 
-            post_events
+            let (recommendations, failed_features) = post_events
                 .concat(&comment_events)
                 .concat(&like_events)
-                .window(HR_4, HR_1)
-                .exchange(|_| 0)
-                .recommendations(&pool, &ARGS.users)
-                .inspect_batch(|timestamp, recommendations| {
-                    if !recommendations.is_empty() {
-                        println!(
-                            "{} - {}",
-                            Utc.timestamp(*timestamp as i64, 0).format("%D - %r"),
-                            timestamp
-                        );
-                        for (user, recommended) in recommendations {
-                            println!("\tUser {} - Recomendations: {:?}", user, recommended);
-                        }
-                    }
-                });
+                .window(HR_4, HR_1, &checkpoints)
+                .recommendations(&store, &ARGS.users);
+
+            failed_events
+                .concat(&failed_comments)
+                .concat(&failed_likes)
+                .concat(&failed_features)
+                .inspect(|failure| eprintln!("{}", failure));
+
+            let written = recommendations
+                .gather()
+                .write_to(ARGS.sink.sink::<RecommendationRow>());
+
+            stamps.track_latency(&written);
+            written.checkpoint(&checkpoints);
         });
     })
     .unwrap();
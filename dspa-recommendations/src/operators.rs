@@ -1,23 +1,22 @@
+use std::collections::hash_map::DefaultHasher;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::iter::FromIterator;
+use std::hash::{Hash, Hasher};
 use std::mem::swap;
 use std::sync::Arc;
 
-use diesel::prelude::*;
-use diesel::r2d2::ConnectionManager;
-use diesel::PgConnection;
-use r2d2::Pool;
 use timely::dataflow::channels::pact::{Exchange, Pipeline};
-use timely::dataflow::operators::Operator;
+use timely::dataflow::operators::{Map, Operator};
 use timely::dataflow::{Scope, Stream};
 use timely::Data;
 
-use dspa_lib::records::{PersonKnowsRecord, PersonRecord};
-use dspa_lib::schema::*;
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::error::{FailedRecord, SplitErrors};
+use dspa_lib::metrics::batch_time;
+use dspa_lib::store::SocialGraphStore;
 
-use crate::RecommendationEvent;
+use crate::{Feature, RecommendationEvent};
 
 #[inline]
 fn round_to_next(current: u64, multiple: u64) -> u64 {
@@ -35,44 +34,94 @@ where
     G: Scope<Timestamp = u64>,
     D: Data,
 {
-    fn window(&self, size: u64, frequency: u64) -> Stream<G, D>;
+    fn window(&self, size: u64, frequency: u64, checkpoints: &Checkpoints) -> Stream<G, D>;
 }
 
 impl<G> Window<G, RecommendationEvent> for Stream<G, RecommendationEvent>
 where
     G: Scope<Timestamp = u64>,
 {
-    fn window(&self, size: u64, frequency: u64) -> Stream<G, RecommendationEvent> {
-        let mut active: BTreeMap<u64, Vec<RecommendationEvent>> = BTreeMap::new();
+    fn window(
+        &self,
+        size: u64,
+        frequency: u64,
+        checkpoints: &Checkpoints,
+    ) -> Stream<G, RecommendationEvent> {
+        let idx = self.scope().index();
+        let checkpoints = checkpoints.clone();
+
+        // Time => Events
+        let mut active: BTreeMap<u64, Vec<RecommendationEvent>> =
+            checkpoints.load("window", idx).unwrap_or_default();
+
+        // Continue outputting and snapshotting restored events
+        let mut init = Vec::new();
+        if let Some(time) = checkpoints.restored() {
+            if !active.is_empty() {
+                init.push(round_to_next(time + 1, frequency));
+                init.extend(checkpoints.next(time + 1));
+            }
+        }
+
+        let batch_time = batch_time("Window");
 
         let mut vec = Vec::new();
         self.unary_notify(
             Pipeline,
             "Window",
-            None,
+            init,
             move |input, output, notificator| {
+                let _timer = batch_time.start_timer();
+
                 input.for_each(|cap, data| {
                     data.swap(&mut vec);
 
                     active.entry(*cap.time()).or_default().extend(vec.drain(..));
                     notificator.notify_at(cap.delayed(&(round_to_next(*cap.time(), frequency))));
+                    if let Some(checkpoint) = checkpoints.next(*cap.time()) {
+                        notificator.notify_at(cap.delayed(&checkpoint));
+                    }
                 });
 
                 notificator.for_each(|cap, _, notificator| {
-                    // Remove all events out of given window
-                    let mut remaining = active.split_off(&(*cap.time() - size - 1));
-
-                    // Make remaining the new active
-                    swap(&mut active, &mut remaining);
+                    if *cap.time() % frequency == 0 {
+                        // Remove all events out of given window
+                        let mut remaining = active.split_off(&cap.time().saturating_sub(size + 1));
+
+                        // Make remaining the new active
+                        swap(&mut active, &mut remaining);
+
+                        // Events after the current time belong to later windows
+                        output.session(&cap).give_iterator(
+                            active
+                                .range(..=*cap.time())
+                                .flat_map(|(_, events)| events)
+                                .cloned(),
+                        );
+
+                        // If data is available, queue for next iteration
+                        if !active.is_empty() {
+                            notificator.notify_at(
+                                cap.delayed(&(round_to_next(*cap.time() + 1, frequency))),
+                            )
+                        }
+                    }
 
-                    output
-                        .session(&cap)
-                        .give_iterator(active.values().flatten().cloned());
+                    // Events after the checkpoint are replayed after a restore
+                    if checkpoints.is_checkpoint(*cap.time()) {
+                        let snapshot: BTreeMap<_, _> = active
+                            .range(..=*cap.time())
+                            .map(|(time, events)| (*time, events.clone()))
+                            .collect();
+                        if !snapshot.is_empty() {
+                            checkpoints.save("window", idx, *cap.time(), &snapshot);
 
-                    // If data is available, queue for next iteration
-                    if !remaining.is_empty() {
-                        notificator
-                            .notify_at(cap.delayed(&(round_to_next(*cap.time() + 1, frequency))))
+                            if !notificator.frontier(0).is_empty() {
+                                if let Some(checkpoint) = checkpoints.next(cap.time() + 1) {
+                                    notificator.notify_at(cap.delayed(&checkpoint));
+                                }
+                            }
+                        }
                     }
                 });
             },
@@ -86,207 +135,171 @@ where
 {
     fn recommendations(
         &self,
-        pool: &Arc<Pool<ConnectionManager<PgConnection>>>,
+        store: &Arc<dyn SocialGraphStore>,
         users: &[i32],
-    ) -> Stream<G, (i32, Vec<i32>)>;
+    ) -> (Stream<G, (i32, Vec<i32>)>, Stream<G, FailedRecord>);
 }
 
-//pub struct RecommendationFeatures {
-//    pub person_id: i32,
-//    pub person_id: i32
-//}
-
 impl<G> Recommendations<G> for Stream<G, RecommendationEvent>
 where
     G: Scope<Timestamp = u64>,
 {
     fn recommendations(
         &self,
-        pool: &Arc<Pool<ConnectionManager<PgConnection>>>,
+        store: &Arc<dyn SocialGraphStore>,
         users: &[i32],
-    ) -> Stream<G, (i32, Vec<i32>)> {
-        let pool = pool.clone();
-        let connection = pool.get().unwrap();
+    ) -> (Stream<G, (i32, Vec<i32>)>, Stream<G, FailedRecord>) {
+        let store = store.clone();
 
-        let users: HashSet<i32> = users.iter().cloned().collect(); // HashSet::from_iter(users); // .iter().collect::<HashSet<_>>();
+        let users: HashSet<i32> = users.iter().cloned().collect();
         let mut friends: HashMap<i32, HashSet<i32>> = HashMap::new();
 
         for user in &users {
-            friends.insert(
-                *user,
-                person_knows::table
-                    .filter(person_knows::person_id.eq(user))
-                    .load::<PersonKnowsRecord>(&connection)
-                    .unwrap()
-                    .iter()
-                    .map(|record| record.acquaintance_id)
-                    .collect::<HashSet<_>>(),
-            );
+            let acquaintances = store.acquaintances(*user).unwrap_or_else(|error| {
+                eprintln!("Failed to load acquaintances of user {}: {}", user, error);
+                Vec::new()
+            });
+            friends.insert(*user, acquaintances.into_iter().collect::<HashSet<_>>());
 
             // Add user to friends to filter out self recommendations
             friends.get_mut(user).unwrap().insert(*user);
         }
 
-        // Counts how many items two users have in common
-        self.unary(Pipeline, "Recommendations", |_, _| {
-            let mut vec = Vec::new();
-            move |input, output| {
-                let mut user_to_user_recommendation: HashMap<(i32, i32), i32> = HashMap::new();
-
-                let mut map_post_id: HashMap<i32, Vec<i32>> = HashMap::new();
-                let mut map_forum_id: HashMap<i32, Vec<i32>> = HashMap::new();
-                let mut map_place_id: HashMap<i32, Vec<i32>> = HashMap::new();
-
-                let mut user_related_post_ids: HashMap<i32, Vec<i32>> = HashMap::new();
-                let mut user_related_forum_ids: HashMap<i32, Vec<i32>> = HashMap::new();
-                let mut user_related_place_ids: HashMap<i32, Vec<i32>> = HashMap::new();
-
-                // Hashmap, such that we can extract the top 5 users later on
-                let mut user_vec: HashMap<i32, Vec<(i32, i32)>> = HashMap::new();
-
-                input.for_each(|cap, data| {
-                    data.swap(&mut vec);
-
-                    let mut session = output.session(&cap);
-
-                    vec.drain(..).for_each(|x| {
-                        let map = match x {
-                            RecommendationEvent::Post(post) => post.hashmap(),
-                            RecommendationEvent::Like(like) => like.hashmap(),
-                            RecommendationEvent::Comment(comment) => comment.hashmap(&connection),
-                        };
-
-                        map.keys().for_each(|x| {
-                            let user_id = *map.get("person_id").unwrap();
-                            // Append the user into the item vector
-                            match *x {
-                                "post_id" => {
-                                    map_post_id
-                                        .entry(*map.get(x).unwrap())
-                                        .or_default()
-                                        .push(user_id);
-                                    if users.contains(&user_id) {
-                                        user_related_post_ids
-                                            .entry(user_id)
-                                            .or_default()
-                                            .push(*map.get(x).unwrap());
-                                    }
-                                }
-                                "forum_id" => {
-                                    map_forum_id
-                                        .entry(*map.get(x).unwrap())
-                                        .or_default()
-                                        .push(user_id);
-                                    if users.contains(&user_id) {
-                                        user_related_forum_ids
-                                            .entry(user_id)
-                                            .or_default()
-                                            .push(*map.get(x).unwrap());
-                                    }
-                                }
-                                "place_id" => {
-                                    map_place_id
-                                        .entry(*map.get(x).unwrap())
-                                        .or_default()
-                                        .push(user_id);
-                                    if users.contains(&user_id) {
-                                        user_related_place_ids
-                                            .entry(user_id)
-                                            .or_default()
-                                            .push(*map.get(x).unwrap());
-                                    }
-                                }
-                                _ => (),
-                            };
-                        });
+        // Time => (Feature, User)
+        let mut features: HashMap<u64, Vec<(Feature, i32)>> = HashMap::new();
+        // Time => User => Similar User => Count
+        let mut similarities: HashMap<u64, HashMap<i32, HashMap<i32, i32>>> = HashMap::new();
+
+        let mut feature_vec = Vec::new();
+        let mut similarity_vec = Vec::new();
+
+        let features_time = batch_time("Recommendation Features");
+        let recommendations_time = batch_time("Recommendations");
+
+        let (user_features, failed) = self
+            .map(move |event| {
+                event
+                    .features(&*store)
+                    .map(|features| (event.user(), features))
+                    .map_err(|error| FailedRecord::new(&event, error))
+            })
+            .split_errors();
+
+        // Counts how many items two users have in common, partitioned by item
+        let recommendations = user_features
+            .flat_map(|(user_id, features)| {
+                features.into_iter().map(move |feature| (feature, user_id))
+            })
+            .unary_notify(
+                Exchange::new(|(feature, _): &(Feature, i32)| hash(feature)),
+                "Recommendation Features",
+                None,
+                move |input, output, notificator| {
+                    let _timer = features_time.start_timer();
+
+                    input.for_each(|cap, data| {
+                        data.swap(&mut feature_vec);
+
+                        features
+                            .entry(*cap.time())
+                            .or_default()
+                            .extend(feature_vec.drain(..));
+                        notificator.notify_at(cap.retain());
                     });
 
-                    // For each use in the above item
-
-                    // Iterate through posts
-                    // Iterate through all users (and the respective posts they liked
-                    for (user_id, all_post_ids) in user_related_post_ids.iter() {
-                        // Iterate through all the posts that this user, has
-                        for post_id in all_post_ids.iter() {
-                            // Get the corresponding vector
-                            let similar_user_vector = map_post_id.get(post_id).unwrap();
-                            // Iterate through all the posts than similar_users
-                            for similar_user_id in similar_user_vector {
-                                *user_to_user_recommendation
-                                    .entry((*user_id, *similar_user_id))
-                                    .or_default() += 1;
-                            }
+                    notificator.for_each(|cap, _, _| {
+                        // Feature => User => Count
+                        let mut feature_users: HashMap<Feature, HashMap<i32, i32>> = HashMap::new();
+                        for (feature, user_id) in features.remove(cap.time()).unwrap_or_default() {
+                            *feature_users
+                                .entry(feature)
+                                .or_default()
+                                .entry(user_id)
+                                .or_default() += 1;
                         }
-                    }
 
-                    // Iterate through forums
-                    for (user_id, all_forum_ids) in user_related_forum_ids.iter() {
-                        // Iterate through all the posts that this user, has
-                        for forum_id in all_forum_ids.iter() {
-                            // Get the corresponding vector
-                            let similar_user_vector = map_forum_id.get(forum_id).unwrap();
-                            // Iterate through all the posts than similar_users
-                            for similar_user_id in similar_user_vector {
-                                *user_to_user_recommendation
-                                    .entry((*user_id, *similar_user_id))
-                                    .or_default() += 1;
+                        // Every occurrence of a selected user counts once per occurrence of another user
+                        let mut user_to_user_recommendation: HashMap<(i32, i32), i32> =
+                            HashMap::new();
+                        for feature_users in feature_users.values() {
+                            for (user_id, user_count) in feature_users {
+                                if users.contains(user_id) {
+                                    for (similar_user_id, similar_count) in feature_users {
+                                        *user_to_user_recommendation
+                                            .entry((*user_id, *similar_user_id))
+                                            .or_default() += user_count * similar_count;
+                                    }
+                                }
                             }
                         }
-                    }
 
-                    // Iterate through places
-                    for (user_id, all_place_ids) in user_related_place_ids.iter() {
-                        // Iterate through all the posts that this user, has
-                        for place_id in all_place_ids.iter() {
-                            // Get the corresponding vector
-                            let similar_user_vector = map_place_id.get(place_id).unwrap();
-                            // Iterate through all the posts than similar_users
-                            for similar_user_id in similar_user_vector {
-                                *user_to_user_recommendation
-                                    .entry((*user_id, *similar_user_id))
-                                    .or_default() += 1;
-                            }
+                        output
+                            .session(&cap)
+                            .give_iterator(user_to_user_recommendation.into_iter());
+                    });
+                },
+            )
+            // Combine the counts of all items, partitioned by selected user
+            .unary_notify(
+                Exchange::new(|((user_id, _), _): &((i32, i32), i32)| *user_id as u64),
+                "Recommendations",
+                None,
+                move |input, output, notificator| {
+                    let _timer = recommendations_time.start_timer();
+
+                    input.for_each(|cap, data| {
+                        data.swap(&mut similarity_vec);
+
+                        let similarities = similarities.entry(*cap.time()).or_default();
+                        for ((user_id, similar_user_id), count) in similarity_vec.drain(..) {
+                            *similarities
+                                .entry(user_id)
+                                .or_default()
+                                .entry(similar_user_id)
+                                .or_default() += count;
                         }
-                    }
+                        notificator.notify_at(cap.retain());
+                    });
 
-                    // Iterate over all user-pairs
-                    let mut transformed_map: HashMap<i32, HashMap<i32, i32>> = HashMap::new();
-                    user_to_user_recommendation
-                        .iter()
-                        .for_each(|((u1, u2), count)| {
-                            *transformed_map
-                                .entry(*u1)
-                                .or_default()
-                                .entry(*u2)
-                                .or_default() = *count;
-                        });
+                    notificator.for_each(|cap, _, _| {
+                        let similarities = similarities.remove(cap.time()).unwrap_or_default();
 
-                    let mut recommendation_vector: HashMap<i32, Vec<i32>> = HashMap::new();
+                        let mut recommendation_vector: Vec<(i32, Vec<i32>)> = Vec::new();
+                        similarities.into_iter().for_each(|(u1, user_hashmap)| {
+                            let mut vec_u2similarity_count = user_hashmap
+                                .into_iter()
+                                .map(|(similar_user_id, count)| (count, similar_user_id))
+                                .collect::<Vec<_>>();
 
-                    transformed_map.iter().for_each(|(u1, user_hashmap)| {
-                        let mut vec_u2similarity_count = user_hashmap
-                            .iter()
-                            .map(|(similar_user_id, count)| (count, similar_user_id))
-                            .collect::<Vec<_>>();
+                            // Highest count first, ties broken by user id
+                            vec_u2similarity_count.sort_by_key(|(count, uid)| (-count, *uid));
 
-                        // Vec<(count, similar_user_id)>
-                        vec_u2similarity_count.sort_by_key(|k| -1 * k.0);
+                            let top_5_users: Vec<i32> = vec_u2similarity_count
+                                .into_iter()
+                                .map(|(_, uid)| uid)
+                                .filter(|uid| !friends[&u1].contains(uid))
+                                .take(5)
+                                .collect();
 
-                        let top_5_users: Vec<i32> = vec_u2similarity_count
-                            .iter()
-                            .map(|x| *x.1)
-                            .filter(|uid| !friends.get(u1).unwrap().contains(uid))
-                            .take(5)
-                            .collect();
+                            if !top_5_users.is_empty() {
+                                recommendation_vector.push((u1, top_5_users));
+                            }
+                        });
 
-                        if !top_5_users.is_empty() {
-                            *recommendation_vector.entry(*u1).or_default() = top_5_users;
-                        }
+                        output
+                            .session(&cap)
+                            .give_iterator(recommendation_vector.into_iter());
                     });
+                },
+            );
 
-                    session.give_iterator(recommendation_vector.into_iter());
-                });
-            }
-        })
+        (recommendations, failed)
     }
 }
+
+#[inline]
+fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use timely::dataflow::operators::{Concat, Map};
+
+use dspa_lib::checkpoint::Checkpoints;
+use dspa_lib::records::{PersonKnowsRecord, PostRecord};
+use dspa_lib::store::{MemoryStore, SocialGraphStore};
+use dspa_lib::testing::{like, post, Harness};
+use dspa_recommendations::operators::{Recommendations, Window};
+use dspa_recommendations::RecommendationEvent;
+
+const HR_4: u64 = 60 * 60 * 4;
+const HR_1: u64 = 60 * 60;
+
+fn post_in(id: i32, person_id: i32, forum_id: i32, place_id: i32, time: u64) -> PostRecord {
+    PostRecord {
+        forum_id,
+        place_id,
+        ..post(id, person_id, time)
+    }
+}
+
+fn window(harness: Harness) -> Vec<(u64, Vec<i32>)> {
+    harness
+        .run(|(posts, _, _)| {
+            posts
+                .map(RecommendationEvent::Post)
+                .window(HR_4, HR_1, &Checkpoints::default())
+                .map(|event| match event {
+                    RecommendationEvent::Post(post) => post.id,
+                    _ => unreachable!(),
+                })
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+fn recommendations(harness: Harness, users: &'static [i32]) -> Vec<(u64, Vec<(i32, Vec<i32>)>)> {
+    let mut store = MemoryStore::new();
+    store.insert_knows(PersonKnowsRecord {
+        person_id: 1,
+        acquaintance_id: 4,
+    });
+    let store: Arc<dyn SocialGraphStore> = Arc::new(store);
+
+    harness
+        .run(move |(posts, _, likes)| {
+            posts
+                .map(RecommendationEvent::Post)
+                .concat(&likes.map(RecommendationEvent::Like))
+                .window(HR_4, HR_1, &Checkpoints::default())
+                .recommendations(&store, users)
+                .0
+        })
+        .sorted()
+        .results
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn window_contains_events_of_the_last_4_hours() {
+    let harness = Harness::default()
+        .event(100, post(1, 1, 100))
+        .event(HR_1 + 100, post(2, 1, HR_1 + 100))
+        .event(HR_4 + 200, post(3, 1, HR_4 + 200));
+
+    let output = window(harness);
+
+    assert_eq!(output[0], (HR_1, vec![1]));
+    assert_eq!(output[1], (2 * HR_1, vec![1, 2]));
+    assert_eq!(output[4], (5 * HR_1, vec![2, 3]));
+    // Window keeps reporting until the last event left it
+    assert_eq!(output.last().unwrap().0, 8 * HR_1);
+}
+
+#[test]
+fn window_does_not_contain_future_events() {
+    let harness = Harness::default()
+        .event(100, post(1, 1, 100))
+        .event(HR_1 + 100, post(2, 1, HR_1 + 100))
+        .advance_to(HR_1 + 1);
+
+    let output = window(harness);
+
+    assert_eq!(output[0], (HR_1, vec![1]));
+}
+
+#[test]
+fn recommends_users_with_most_common_items() {
+    let harness = Harness::default()
+        .event(100, post_in(1, 1, 10, 20, 100))
+        .event(110, post_in(2, 2, 10, 20, 110))
+        .event(120, post_in(3, 3, 10, 99, 120))
+        .event(130, post_in(4, 4, 11, 20, 130))
+        .event(140, like(5, 1, 140))
+        .event(150, like(1, 1, 150));
+
+    let output = recommendations(harness, &[1]);
+
+    // User 4 is already an acquaintance, user 1 is never recommended to themselves
+    assert_eq!(output[0], (HR_1, vec![(1, vec![2, 3, 5])]));
+}
+
+#[test]
+fn recommendations_are_independent_of_worker_count() {
+    let events = (0..40)
+        .map(|i| {
+            let time = 100 + i as u64 * 600;
+            (time, post_in(i, i % 7, i % 3, i % 5, time).into())
+        })
+        .collect::<Vec<_>>();
+
+    let single = recommendations(Harness::new(events.clone()), &[0, 1, 2]);
+    let multiple = recommendations(Harness::new(events).workers(3), &[0, 1, 2]);
+
+    assert!(!single.is_empty());
+    assert_eq!(single, multiple);
+}
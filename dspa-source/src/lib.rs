@@ -8,6 +8,9 @@ use std::path::PathBuf;
 use serde::Serialize;
 use structopt::StructOpt;
 
+use dspa_lib::codec::Codec;
+use dspa_lib::metrics::MetricsArgs;
+use dspa_lib::partition::PartitionArgs;
 use dspa_lib::Topic;
 
 pub mod operators;
@@ -28,6 +31,19 @@ pub struct Args {
     pub speedup: u64,
     #[structopt(long = "delay", default_value = "10")]
     pub delay: u64,
+    #[structopt(long = "resume")]
+    /// Only emit stream records after the given event time, the snapshot time of restored processors
+    pub resume: Option<u64>,
+    #[structopt(long = "codec", default_value = "bincode")]
+    /// Serialization of the published messages: bincode, json, msgpack or protobuf
+    pub codec: Codec,
+    #[structopt(long = "roots", default_value = "1000000")]
+    /// Number of most recent comments whose root post is remembered to partition their replies
+    pub roots: usize,
+    #[structopt(flatten)]
+    pub partition: PartitionArgs,
+    #[structopt(flatten)]
+    pub metrics: MetricsArgs,
 }
 
 lazy_static! {
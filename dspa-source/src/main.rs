@@ -10,14 +10,21 @@ use timely;
 use timely::dataflow::operators::{Exchange, Inspect};
 use zmq::Context;
 
+use dspa_lib::metrics::SOURCE_METRICS_PORT;
 use dspa_lib::records::*;
 use dspa_lib::schema::*;
-use dspa_lib::DATABASE_URL;
+use dspa_lib::{shutdown, Control, DATABASE_URL};
 
-use dspa_source::operators::{csv_source, csv_stream_source, BoundedDelay, Insert, Publish};
+use dspa_source::operators::{
+    csv_source, csv_stream_source, publish_control, BoundedDelay, Insert, Publish, RootPosts,
+    TrackRoots,
+};
 use dspa_source::ARGS;
 
 fn main() {
+    ARGS.metrics.serve(SOURCE_METRICS_PORT, 0);
+    shutdown::handle_signals();
+
     let pool = Arc::new(
         Pool::builder()
             .max_size(16)
@@ -189,7 +196,8 @@ fn main() {
         let pool = pool.clone();
         let path = ARGS.path.join("streams/");
 
-        {
+        // Records before the resume time are already in the database
+        if ARGS.resume.is_none() {
             let connection = pool.get().unwrap();
 
             // Drop relations
@@ -198,12 +206,21 @@ fn main() {
             diesel::delete(like_::table).execute(&connection).unwrap();
         }
 
+        // Shared by all workers, the comments are read by the first one
+        let roots = RootPosts::new(ARGS.roots);
+
+        // The broker forwards the end of stream once all registered producers finished
+        publish_control(&Context::new(), ARGS.codec, &Control::Register);
+
         eprintln!("Inserting stream records!");
         timely::execute(timely::Configuration::Thread, move |worker| {
             // timely::execute(timely::Configuration::Process(num_cpus::get()), move |worker| {
             let idx = worker.index();
             let ctx = Context::new();
 
+            let partitions = ARGS.partition.partitions;
+            let roots = roots.clone();
+
             worker.dataflow(|scope| {
                 let (posts, comments, likes) = csv_stream_source(scope, idx, &path);
 
@@ -211,20 +228,28 @@ fn main() {
                     .exchange(|record| record.timestamp() as u64)
                     .bounded_delay(ARGS.delay)
                     .insert(pool.clone())
-                    .publish(&ctx);
+                    .publish(&ctx, ARGS.codec, ARGS.delay, partitions, |post| post.id);
                 comments
+                    .track_roots(&roots)
                     .exchange(|record| record.timestamp() as u64)
                     .bounded_delay(ARGS.delay)
                     .insert(pool.clone())
-                    .publish(&ctx);
+                    .publish(&ctx, ARGS.codec, ARGS.delay, partitions, move |comment| {
+                        roots.root(comment)
+                    });
                 likes
                     .exchange(|record| record.timestamp() as u64)
                     .bounded_delay(ARGS.delay)
                     .insert(pool.clone())
-                    .publish(&ctx);
+                    .publish(&ctx, ARGS.codec, ARGS.delay, partitions, |like| {
+                        like.post_id
+                    });
             });
         })
         .unwrap();
+
+        // All workers are done publishing
+        publish_control(&Context::new(), ARGS.codec, &Control::Eos);
         eprintln!("Done inserting stream records!");
     }
 }
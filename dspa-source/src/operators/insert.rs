@@ -1,3 +1,4 @@
+use std::fmt::Debug;
 use std::sync::Arc;
 
 use diesel::associations::HasTable;
@@ -15,7 +16,9 @@ use r2d2::Pool;
 use timely::dataflow::operators::Inspect;
 use timely::dataflow::{Scope, Stream};
 
+use dspa_lib::error::FailedRecord;
 use dspa_lib::records::TableRecord;
+use dspa_lib::store::retry;
 
 pub trait Insert<G, D>
 where
@@ -27,19 +30,26 @@ where
 impl<'a, G, D> Insert<G, D> for Stream<G, D>
 where
     G: Scope<Timestamp = u64>,
-    D: TableRecord + Insertable<<D as TableRecord>::Table>,
+    D: TableRecord + Debug + Insertable<<D as TableRecord>::Table>,
     <<D as TableRecord>::Table as QuerySource>::FromClause: QueryFragment<Pg>,
     <D as Insertable<<D as TableRecord>::Table>>::Values: QueryFragment<Pg>,
     <D as Insertable<<D as TableRecord>::Table>>::Values: CanInsertInSingleQuery<Pg>,
 {
     fn insert(&self, pool: Arc<Pool<ConnectionManager<PgConnection>>>) -> Stream<G, D> {
         self.inspect(move |record| {
-            let connection = pool.get().unwrap();
+            let inserted = retry("insert", || {
+                let connection = pool.get()?;
 
-            diesel::insert_into(D::table())
-                .values(record.clone())
-                .execute(&connection)
-                .unwrap();
+                diesel::insert_into(D::table())
+                    .values(record.clone())
+                    .execute(&connection)?;
+                Ok(())
+            });
+
+            // Records are still forwarded, the database only serves lookups
+            if let Err(error) = inserted {
+                eprintln!("{}", FailedRecord::new(record, error));
+            }
         })
     }
 }
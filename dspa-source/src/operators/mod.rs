@@ -1,9 +1,11 @@
 mod delay;
 mod insert;
+mod roots;
 mod sink;
 mod source;
 
 pub use delay::*;
 pub use insert::*;
+pub use roots::*;
 pub use sink::*;
 pub use source::*;
@@ -0,0 +1,87 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use timely::dataflow::operators::Inspect;
+use timely::dataflow::{Scope, Stream};
+
+use dspa_lib::records::CommentRecord;
+
+/// Root posts of the most recent comments read so far, shared between the source and the
+/// publishers
+#[derive(Clone, Debug)]
+pub struct RootPosts {
+    roots: Arc<Mutex<Roots>>,
+}
+
+#[derive(Debug)]
+struct Roots {
+    // Comment => Root post
+    roots: HashMap<i32, i32>,
+    // Comments in the order they were read, the oldest are evicted first
+    order: VecDeque<i32>,
+    capacity: usize,
+}
+
+impl RootPosts {
+    /// Remember the root posts of up to `capacity` comments
+    pub fn new(capacity: usize) -> Self {
+        RootPosts {
+            roots: Arc::new(Mutex::new(Roots {
+                roots: HashMap::new(),
+                order: VecDeque::new(),
+                capacity,
+            })),
+        }
+    }
+
+    /// Root post of a comment, falling back to the comment itself if its parent was never read
+    pub fn root(&self, comment: &CommentRecord) -> i32 {
+        let roots = self.roots.lock().unwrap();
+        Self::resolve(&roots.roots, comment).unwrap_or(comment.id)
+    }
+
+    fn resolve(roots: &HashMap<i32, i32>, comment: &CommentRecord) -> Option<i32> {
+        comment.reply_to_post_id.or_else(|| {
+            comment
+                .reply_to_comment_id
+                .and_then(|parent| roots.get(&parent).cloned())
+        })
+    }
+}
+
+impl Roots {
+    fn insert(&mut self, comment: i32, root: i32) {
+        if self.roots.insert(comment, root).is_none() {
+            self.order.push_back(comment);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.roots.remove(&evicted);
+            }
+        }
+    }
+}
+
+pub trait TrackRoots<G>
+where
+    G: Scope,
+{
+    /// Learn the root posts of the comments. Must be applied before the comments are delayed,
+    /// such that parents are seen before their replies.
+    fn track_roots(&self, roots: &RootPosts) -> Stream<G, CommentRecord>;
+}
+
+impl<G> TrackRoots<G> for Stream<G, CommentRecord>
+where
+    G: Scope<Timestamp = u64>,
+{
+    fn track_roots(&self, roots: &RootPosts) -> Stream<G, CommentRecord> {
+        let roots = roots.clone();
+        self.inspect(move |comment| {
+            let mut roots = roots.roots.lock().unwrap();
+            if let Some(root) = RootPosts::resolve(&roots.roots, comment) {
+                roots.insert(comment.id, root);
+            }
+        })
+    }
+}
@@ -1,37 +1,66 @@
-use std::collections::BTreeMap;
+use std::process;
 
-use bincode::serialize;
 use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::Operator;
 use timely::dataflow::{Scope, Stream};
 use zmq::{Context, SocketType, SNDMORE};
 
+use dspa_lib::codec::{Codec, Encode};
+use dspa_lib::envelope::Envelope;
+use dspa_lib::metrics::{batch_time, RECORDS_EMITTED};
+use dspa_lib::partition::partition;
 use dspa_lib::records::StreamRecord;
-use dspa_lib::SOURCE_SOCKET;
+use dspa_lib::{Control, SOURCE_SOCKET};
+
+// Event time between two watermarks of a producer
+const WATERMARK_INTERVAL: u64 = 60;
 
 pub trait Publish<G, D>
 where
     G: Scope,
     D: StreamRecord,
 {
-    fn publish(&self, ctx: &Context);
+    /// Send the records to the partition of their key on the source socket. Watermarks are sent to
+    /// every partition as the input frontier advances, with records being delayed by up to `delay`
+    /// after their event time.
+    fn publish<K>(&self, ctx: &Context, codec: Codec, delay: u64, partitions: usize, key: K)
+    where
+        K: Fn(&D) -> i32 + 'static;
 }
 
 impl<G, D> Publish<G, D> for Stream<G, D>
 where
     G: Scope<Timestamp = u64>,
-    D: StreamRecord,
+    D: StreamRecord + Encode,
 {
-    fn publish(&self, ctx: &Context) {
+    fn publish<K>(&self, ctx: &Context, codec: Codec, delay: u64, partitions: usize, key: K)
+    where
+        K: Fn(&D) -> i32 + 'static,
+    {
         let socket = ctx.socket(SocketType::PUSH).unwrap();
         socket
             .connect(&format!("ipc://{}", SOURCE_SOCKET))
             .expect("Failed to connect!");
 
         let topic = D::TOPIC.to_string();
+        // Every operator instance numbers its own messages per partition
+        let producer = format!(
+            "dspa-source/{}/{}/{}",
+            process::id(),
+            self.scope().index(),
+            topic
+        );
+        let mut sequences = vec![0; partitions];
+        let mut watermark_sequences = vec![0; partitions];
+        let mut watermark = 0;
+
+        let batch_time = batch_time("Publish");
+        let records_emitted = RECORDS_EMITTED.with_label_values(&[&topic]);
 
         let mut vec = Vec::new();
         self.sink(Pipeline, "Publish", move |input| {
+            let _timer = batch_time.start_timer();
+
             input.for_each(|cap, data| {
                 data.swap(&mut vec);
 
@@ -44,10 +73,47 @@ where
                         record.id()
                     );
 
-                    socket.send(&topic, SNDMORE).unwrap();
-                    socket.send(&serialize(&record).unwrap(), 0).unwrap();
+                    let partition = partition(key(&record), partitions);
+                    let envelope = Envelope::new(codec, &producer, sequences[partition], &record);
+                    socket
+                        .send(&D::TOPIC.partition(partition), SNDMORE)
+                        .unwrap();
+                    socket.send_multipart(envelope.encode(), 0).unwrap();
+                    sequences[partition] += 1;
+                    records_emitted.inc();
                 });
             });
+
+            // Records after the frontier have an event time of at least the frontier minus delay
+            if let Some(frontier) = input.frontier().frontier().iter().min() {
+                let time = frontier.saturating_sub(delay);
+                if time >= watermark + WATERMARK_INTERVAL {
+                    watermark = time;
+
+                    let control = Control::Watermark(watermark);
+                    for (partition, sequence) in watermark_sequences.iter_mut().enumerate() {
+                        let envelope = Envelope::control(codec, &producer, *sequence, &control);
+                        socket
+                            .send(&control.topic().partition(partition), SNDMORE)
+                            .unwrap();
+                        socket.send_multipart(envelope.encode(), 0).unwrap();
+                        *sequence += 1;
+                    }
+                }
+            }
         });
     }
 }
+
+/// Send a control message to the source socket, unpartitioned such that every consumer receives it
+pub fn publish_control(ctx: &Context, codec: Codec, control: &Control) {
+    let socket = ctx.socket(SocketType::PUSH).unwrap();
+    socket
+        .connect(&format!("ipc://{}", SOURCE_SOCKET))
+        .expect("Failed to connect!");
+
+    let producer = format!("dspa-source/{}", process::id());
+    let envelope = Envelope::control(codec, &producer, 0, control);
+    socket.send(&control.topic().to_string(), SNDMORE).unwrap();
+    socket.send_multipart(envelope.encode(), 0).unwrap();
+}
@@ -7,8 +7,9 @@ use timely::dataflow::operators::generic::operator::{empty, source};
 use timely::dataflow::operators::{Map, Partition};
 use timely::dataflow::{Scope, Stream};
 
+use dspa_lib::metrics::RECORDS_RECEIVED;
 use dspa_lib::records::{CommentRecord, LikeRecord, PostRecord, Record, StreamRecord};
-use dspa_lib::StreamEvent;
+use dspa_lib::{shutdown, StreamEvent};
 
 use crate::ARGS;
 
@@ -36,7 +37,9 @@ where
                 let mut done = false;
 
                 if let Some(cap) = cap.as_mut() {
-                    if let Some(record) = records.next() {
+                    if shutdown::requested() {
+                        done = true;
+                    } else if let Some(record) = records.next() {
                         output.session(&cap).give(record);
                     } else {
                         done = true;
@@ -97,19 +100,27 @@ where
                 .map(StreamEvent::Like)
                 .peekable();
 
-            // Merge records according to timestamp
+            // Merge records according to timestamp, skipping the ones restored processors have seen
             let mut events = posts
                 .merge_by(comments, |a, b| a.timestamp() < b.timestamp())
                 .merge_by(likes, |a, b| a.timestamp() < b.timestamp())
+                .filter(|event| {
+                    ARGS.resume
+                        .map_or(true, |resume| event.timestamp() as u64 > resume)
+                })
                 .peekable();
 
-            let timestamp_logical = events.peek().unwrap().timestamp() as u64;
+            // Without events after the resume time, the source finishes on its first activation
+            let timestamp_logical = events.peek().map_or(0, |event| event.timestamp() as u64);
             let timestamp_physical = Instant::now();
             move |output| {
                 let mut done = false;
 
                 if let Some(cap) = cap.as_mut() {
-                    if let Some(event) = events.peek() {
+                    if shutdown::requested() {
+                        // Stop reading, records in flight are still published
+                        done = true;
+                    } else if let Some(event) = events.peek() {
                         // Next event exists
 
                         // Get timestamp of next record
@@ -124,7 +135,11 @@ where
                         if logical_duration < physical_duration {
                             // Logical event timestamp surpassed, emit event and update frontier to that event
                             cap.downgrade(&(timestamp_logical + logical_duration.as_secs()));
-                            output.session(&cap).give(events.next().unwrap())
+                            let event = events.next().unwrap();
+                            RECORDS_RECEIVED
+                                .with_label_values(&[&event.topic().to_string()])
+                                .inc();
+                            output.session(&cap).give(event)
                         } else {
                             // Logical event timestamp still in future, update frontier
                             cap.downgrade(&(timestamp_logical + physical_duration.as_secs()));
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_derive::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use dspa_lib::envelope::Frames;
+use dspa_lib::StreamEvent;
+
+/// Raw message of a recording
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// Milliseconds since the first recorded message arrived
+    pub offset: u64,
+    pub topic: String,
+    /// Frames of the message after the topic as received, including the envelope
+    pub frames: Frames,
+}
+
+/// Writes received messages with their arrival time to a recording
+pub struct Recorder<W: Write> {
+    writer: W,
+    start: Option<u64>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Recorder {
+            writer,
+            start: None,
+        }
+    }
+
+    /// Record a message that arrived at the given wall-clock time in milliseconds
+    pub fn record(&mut self, now: u64, topic: &str, frames: &[Vec<u8>]) -> io::Result<()> {
+        let start = *self.start.get_or_insert(now);
+        let message = RecordedMessage {
+            offset: now.saturating_sub(start),
+            topic: topic.to_owned(),
+            frames: frames.to_vec(),
+        };
+        bincode::serialize_into(&mut self.writer, &message).map_err(io::Error::other)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Messages of a recording in the order they arrived, a truncated last message is an error
+pub fn recording<R: BufRead>(mut reader: R) -> impl Iterator<Item = io::Result<RecordedMessage>> {
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+
+        match reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {}
+            Err(error) => {
+                done = true;
+                return Some(Err(error));
+            }
+        }
+
+        let message = bincode::deserialize_from(&mut reader)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error));
+        done = message.is_err();
+        Some(message)
+    })
+}
+
+/// Filters on the printed records, all given filters must match
+#[derive(Debug, Default, StructOpt)]
+pub struct Filter {
+    #[structopt(long = "person")]
+    /// Only records created by the given person
+    pub person: Option<i32>,
+    #[structopt(long = "post")]
+    /// Only records of the given post, comments are matched by their root post
+    pub post: Option<i32>,
+    #[structopt(long = "from")]
+    /// Only records at or after the given event time
+    pub from: Option<i64>,
+    #[structopt(long = "to")]
+    /// Only records before the given event time
+    pub to: Option<i64>,
+}
+
+impl Filter {
+    pub fn matches(&self, event: &StreamEvent, root: Option<i32>) -> bool {
+        let (person, post) = match event {
+            StreamEvent::Post(record) => (record.person_id, Some(record.id)),
+            StreamEvent::Comment(record) => (record.person_id, root),
+            StreamEvent::Like(record) => (record.person_id, Some(record.post_id)),
+        };
+        let timestamp = event.timestamp();
+
+        self.person.map_or(true, |filter| filter == person)
+            && self.post.map_or(true, |filter| Some(filter) == post)
+            && self.from.map_or(true, |from| timestamp >= from)
+            && self.to.map_or(true, |to| timestamp < to)
+    }
+}
+
+/// Root posts of the tapped comments, comments whose parent was not tapped have no root
+#[derive(Debug, Default)]
+pub struct Roots {
+    // Comment => Root post
+    roots: HashMap<i32, i32>,
+}
+
+impl Roots {
+    /// Learn the root post of an event, returning it for comments
+    pub fn observe(&mut self, event: &StreamEvent) -> Option<i32> {
+        match event {
+            StreamEvent::Comment(comment) => {
+                let root = comment.reply_to_post_id.or_else(|| {
+                    comment
+                        .reply_to_comment_id
+                        .and_then(|parent| self.roots.get(&parent).cloned())
+                });
+                if let Some(root) = root {
+                    self.roots.insert(comment.id, root);
+                }
+                root
+            }
+            _ => None,
+        }
+    }
+}
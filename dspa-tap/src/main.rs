@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use structopt::StructOpt;
+use zmq::{Context, SocketType, SNDMORE};
+
+use dspa_lib::deadletter::DeadLetter;
+use dspa_lib::envelope::Envelope;
+use dspa_lib::latency::wall_clock;
+use dspa_lib::partition::split_topic;
+use dspa_lib::shutdown;
+use dspa_lib::subscription::{Subscriber, SubscriptionArgs};
+use dspa_lib::{Topic, SOURCE_SOCKET};
+
+use dspa_tap::{recording, Filter, Recorder, Roots};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "dspa-tap")]
+enum Command {
+    #[structopt(name = "print")]
+    /// Print the decoded messages of the tapped topics until the end of stream or a signal
+    Print {
+        #[structopt(flatten)]
+        tap: TapArgs,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "record")]
+    /// Record the raw messages of the tapped topics with their arrival time until the end of stream or a signal
+    Record {
+        #[structopt(parse(from_os_str))]
+        /// File to write the recording to
+        path: PathBuf,
+        #[structopt(flatten)]
+        tap: TapArgs,
+    },
+    #[structopt(name = "replay")]
+    /// Publish a recording to the source socket
+    Replay {
+        #[structopt(parse(from_os_str))]
+        /// File to read the recording from
+        path: PathBuf,
+        #[structopt(long = "speedup", default_value = "1")]
+        /// Pace relative to the recording, 0 replays without waiting
+        speedup: f64,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct TapArgs {
+    #[structopt(long = "topics")]
+    /// Topics to tap, either all partitions (e.g. `post`) or a single one (e.g. `post.3`) (default: all)
+    topics: Vec<String>,
+    #[structopt(flatten)]
+    subscription: SubscriptionArgs,
+}
+
+impl TapArgs {
+    fn subscribe(&self, ctx: &Context) -> Subscriber {
+        let mut topics = if self.topics.is_empty() {
+            vec![String::new()]
+        } else {
+            self.topics.clone()
+        };
+        // The end of stream stops tapping
+        topics.push(Topic::EOS.to_string());
+        self.subscription.subscribe(ctx, 0, &topics)
+    }
+
+    /// Subscriptions match by prefix, e.g. `post.1` also matches `post.12`
+    fn tapped(&self, topic: &str) -> bool {
+        self.topics.is_empty()
+            || self
+                .topics
+                .iter()
+                .any(|tapped| tapped == topic || tapped == split_topic(topic).0)
+    }
+}
+
+/// Receive messages (topic, frames) until the end of stream or a shutdown
+fn tap<F>(subscriber: &mut Subscriber, mut f: F)
+where
+    F: FnMut(&str, &[Vec<u8>]),
+{
+    while !shutdown::requested() {
+        if let Some((topic, frames)) = subscriber.recv() {
+            f(&topic, &frames);
+            subscriber.processed();
+
+            if topic == Topic::EOS.to_string() {
+                break;
+            }
+        }
+    }
+}
+
+fn print(tap_args: &TapArgs, filter: &Filter) {
+    let ctx = Context::new();
+    let mut subscriber = tap_args.subscribe(&ctx);
+    let mut roots = Roots::default();
+
+    tap(&mut subscriber, |topic, frames| {
+        // Dead letters are published by the broker in a single frame without an envelope
+        if topic == Topic::DeadLetter.to_string() {
+            if tap_args.tapped(topic) {
+                match DeadLetter::decode(frames.first().map_or(&[][..], Vec::as_slice)) {
+                    Ok(letter) => println!("{} {}", topic, letter),
+                    Err(error) => eprintln!("{}", error),
+                }
+            }
+            return;
+        }
+
+        let envelope = match Envelope::decode(topic, frames) {
+            Ok(envelope) => envelope,
+            Err(error) => {
+                eprintln!("{}", error);
+                return;
+            }
+        };
+
+        match envelope.event(topic) {
+            Ok(event) => {
+                let root = roots.observe(&event);
+                if tap_args.tapped(topic) && filter.matches(&event, root) {
+                    println!(
+                        "{} {{ producer: {}, sequence: {} }} {:?}",
+                        topic, envelope.producer, envelope.sequence, event
+                    );
+                }
+            }
+            Err(_) => match envelope.control_message(topic) {
+                Ok(control) => {
+                    if tap_args.tapped(topic) || topic == Topic::EOS.to_string() {
+                        println!(
+                            "{} {{ producer: {}, sequence: {} }} {:?}",
+                            topic, envelope.producer, envelope.sequence, control
+                        );
+                    }
+                }
+                Err(error) => eprintln!("{}", error),
+            },
+        }
+    });
+}
+
+fn record(path: &PathBuf, tap_args: &TapArgs) {
+    let file = File::create(path).expect("Failed to create recording");
+    let mut recorder = Recorder::new(BufWriter::new(file));
+    let mut recorded = 0;
+
+    let ctx = Context::new();
+    let mut subscriber = tap_args.subscribe(&ctx);
+    tap(&mut subscriber, |topic, frames| {
+        if tap_args.tapped(topic) || topic == Topic::EOS.to_string() {
+            recorder
+                .record(wall_clock(), topic, frames)
+                .expect("Failed to write recording");
+            recorded += 1;
+        }
+    });
+
+    recorder
+        .into_inner()
+        .flush()
+        .expect("Failed to write recording");
+    eprintln!("Recorded {} messages to {}", recorded, path.display());
+}
+
+fn replay(path: &PathBuf, speedup: f64) {
+    let file = File::open(path).expect("Failed to open recording");
+
+    let ctx = Context::new();
+    let socket = ctx.socket(SocketType::PUSH).unwrap();
+    socket
+        .connect(&format!("ipc://{}", SOURCE_SOCKET))
+        .expect("Failed to connect!");
+
+    let start = Instant::now();
+    let mut replayed = 0;
+    for message in recording(BufReader::new(file)) {
+        if shutdown::requested() {
+            break;
+        }
+
+        let message = message.expect("Failed to read recording");
+
+        if speedup > 0.0 {
+            let due = Duration::from_millis((message.offset as f64 / speedup) as u64);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                sleep(due - elapsed);
+            }
+        }
+
+        socket.send(&message.topic, SNDMORE).unwrap();
+        socket.send_multipart(&message.frames, 0).unwrap();
+        replayed += 1;
+    }
+
+    eprintln!("Replayed {} messages from {}", replayed, path.display());
+}
+
+fn main() {
+    shutdown::handle_signals();
+
+    match Command::from_args() {
+        Command::Print { tap, filter } => print(&tap, &filter),
+        Command::Record { path, tap } => record(&path, &tap),
+        Command::Replay { path, speedup } => replay(&path, speedup),
+    }
+}
@@ -0,0 +1,65 @@
+use dspa_lib::testing::{comment, like, post, reply};
+use dspa_lib::StreamEvent;
+
+use dspa_tap::{recording, Filter, RecordedMessage, Recorder, Roots};
+
+#[test]
+fn recordings_roundtrip_with_arrival_offsets() {
+    let mut recorder = Recorder::new(Vec::new());
+    recorder
+        .record(1000, "post.0", &[vec![1, 2], vec![3]])
+        .unwrap();
+    recorder.record(1250, "eos", &[]).unwrap();
+
+    let data = recorder.into_inner();
+    let messages = recording(&data[..]).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(
+        messages,
+        vec![
+            RecordedMessage {
+                offset: 0,
+                topic: "post.0".to_owned(),
+                frames: vec![vec![1, 2], vec![3]],
+            },
+            RecordedMessage {
+                offset: 250,
+                topic: "eos".to_owned(),
+                frames: vec![],
+            },
+        ]
+    );
+
+    // Truncated recordings are reported
+    assert!(recording(&data[..data.len() - 1]).any(|message| message.is_err()));
+}
+
+#[test]
+fn filters_match_people_posts_and_times() {
+    let mut roots = Roots::default();
+    let post = StreamEvent::Post(post(1, 10, 100));
+    let comment = StreamEvent::Comment(comment(2, 11, 1, 200));
+    let reply = StreamEvent::Comment(reply(3, 12, 2, 300));
+    let like = StreamEvent::Like(like(13, 1, 400));
+
+    assert_eq!(roots.observe(&post), None);
+    assert_eq!(roots.observe(&comment), Some(1));
+    assert_eq!(roots.observe(&reply), Some(1));
+
+    let filter = Filter {
+        post: Some(1),
+        from: Some(200),
+        to: Some(400),
+        ..Filter::default()
+    };
+    assert!(!filter.matches(&post, None));
+    assert!(filter.matches(&comment, Some(1)));
+    assert!(filter.matches(&reply, Some(1)));
+    assert!(!filter.matches(&like, None));
+
+    let filter = Filter {
+        person: Some(13),
+        ..Filter::default()
+    };
+    assert!(filter.matches(&like, None));
+    assert!(!filter.matches(&post, None));
+}
@@ -24,7 +24,7 @@ cargo run --release --bin dspa-source -- --streams ./data/1k-users-sorted/ --spe
 # Give the processors time to finish processing
 sleep 20
 
-# Clean up processes
+# Stop remaining processes, which write their pending results on SIGTERM
 pkill dspa-mq
 pkill dspa-post-stats
 pkill dspa-recommendations